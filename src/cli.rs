@@ -0,0 +1,215 @@
+use std::process;
+use crate::lib::apu::filter::HighPassFilter;
+use crate::lib::model::Model;
+use crate::lib::screen::{ColorCorrection, DmgPalette, FrameBlending};
+
+const DEFAULT_ROM: &str = "./roms/test.gb";
+
+const USAGE: &str = "Usage: gb [OPTIONS] [ROM]
+
+Options:
+    --headless                 Run without user interaction
+    --frames <N>               Number of frames to run in headless mode, 0 for no limit (default: 60)
+    --export-every <N>         Export the framebuffer every N frames (0: only the last one)
+    --export-dir <DIR>         Directory for exported frames (default: ./frames)
+    --png                      Export frames as PNG
+    --rgba                     Export frames as raw RGBA8 bytes
+    --model <MODEL>            Hardware to emulate: dmg0, dmg, mgb, sgb, sgb2, cgb or agb (default: cgb when the ROM header supports it, dmg otherwise)
+    --cgb                      Same as --model cgb
+    --dmg                      Same as --model dmg
+    --boot-rom <FILE>          Run a DMG (256 bytes) or CGB (2304 bytes) boot ROM before the game
+    --dump-vram                Write tile, tile map and OAM views to the export directory after a headless run
+    --terminal                 Draw the screen in the terminal with ANSI colors
+    --palette <PALETTE>        green, grayscale, or four RRGGBB colors separated by commas
+    --color-correction <MODE>  none, cgb or gba (default: none)
+    --frame-blending <MODE>    none, mix or accumulate (default: none)
+    --record-wav <FILE>        Record the audio of a headless run to a 16-bit stereo WAV file
+    --record-channels          Also record each channel to FILE_ch1.wav to FILE_ch4.wav
+    --sample-rate <HZ>         Sample rate of recorded audio (default: 48000)
+    --high-pass <MODE>         off, dmg or cgb output filter (default: dmg)
+    --record-vgm <FILE>        Log the sound register writes of a headless run to a VGM file
+    --input-script <FILE>      Press buttons in a headless run as listed in FILE, e.g. \"frame 120: press START for 5 frames\"
+    --record-movie <FILE>      Record the buttons of a headless run to a movie file
+    --replay-movie <FILE>      Replay a movie in a headless run for its length and check its state hashes
+    --hash-every <N>           Frames between state hashes in recorded movies (default: 60)
+    --serial-stdout            Print the bytes sent over the serial port in a headless run
+    --link-rom <ROM>           Link a second instance running ROM in the same process (frames exported to EXPORT_DIR/player2)
+    --link-listen <PORT>       Wait for another instance to connect its link cable on localhost PORT
+    --link-connect <ADDR>      Connect the link cable to an instance listening on ADDR, e.g. 127.0.0.1:5000
+    --printer                  Plug a Game Boy Printer into the link port, printing to EXPORT_DIR/print_NNN.png
+    --gdb <PORT>               Debug with GDB connecting on localhost PORT instead of the built-in debugger
+    --track <N>                Track of a GBS file to render to WAV (default: the first song of the file)
+    --seconds <N>              Length of the rendered GBS track (default: 60)
+    -h, --help                 Print this message";
+
+pub struct Options {
+    pub rom_path: String,
+    pub headless: bool,
+    pub frames: u64,
+    pub export_every: u32,
+    pub export_dir: String,
+    pub png: bool,
+    pub rgba: bool,
+    pub terminal: bool,
+    pub dump_vram: bool,
+    // Overrides the model selected from the ROM header.
+    pub model: Option<Model>,
+    pub boot_rom: Option<String>,
+    pub palette: DmgPalette,
+    pub color_correction: ColorCorrection,
+    pub frame_blending: FrameBlending,
+    pub record_wav: Option<String>,
+    pub record_channels: bool,
+    pub record_vgm: Option<String>,
+    pub input_script: Option<String>,
+    pub record_movie: Option<String>,
+    pub replay_movie: Option<String>,
+    pub hash_every: u32,
+    pub serial_stdout: bool,
+    pub link_rom: Option<String>,
+    pub link_listen: Option<u16>,
+    pub link_connect: Option<String>,
+    pub printer: bool,
+    pub gdb: Option<u16>,
+    pub sample_rate: u32,
+    pub high_pass: HighPassFilter,
+    pub track: Option<u8>,
+    pub seconds: u32
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            rom_path: String::from(DEFAULT_ROM),
+            headless: false,
+            frames: 60,
+            export_every: 0,
+            export_dir: String::from("./frames"),
+            png: false,
+            rgba: false,
+            terminal: false,
+            dump_vram: false,
+            model: None,
+            boot_rom: None,
+            palette: DmgPalette::default(),
+            color_correction: ColorCorrection::default(),
+            frame_blending: FrameBlending::default(),
+            record_wav: None,
+            record_channels: false,
+            record_vgm: None,
+            input_script: None,
+            record_movie: None,
+            replay_movie: None,
+            hash_every: 60,
+            serial_stdout: false,
+            link_rom: None,
+            link_listen: None,
+            link_connect: None,
+            printer: false,
+            gdb: None,
+            sample_rate: 48000,
+            high_pass: HighPassFilter::Dmg,
+            track: None,
+            seconds: 60
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn next_value(args: &mut dyn Iterator<Item = String>, flag: &str) -> String {
+    match args.next() {
+        Some(value) => value,
+        None => usage_error(&format!("Missing value for {}", flag))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, flag: &str) -> T {
+    match value.parse::<T>() {
+        Ok(number) => number,
+        Err(_) => usage_error(&format!("Invalid number for {}: {}", flag, value))
+    }
+}
+
+impl Options {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Options {
+        let mut options = Options::default();
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--frames" => options.frames = parse_number(&next_value(&mut args, &arg), &arg),
+                "--export-every" => options.export_every = parse_number(&next_value(&mut args, &arg), &arg),
+                "--export-dir" => options.export_dir = next_value(&mut args, &arg),
+                "--png" => options.png = true,
+                "--rgba" => options.rgba = true,
+                "--terminal" => options.terminal = true,
+                "--dump-vram" => options.dump_vram = true,
+                "--model" => {
+                    let value = next_value(&mut args, &arg);
+                    options.model = match Model::from_name(&value) {
+                        Some(model) => Some(model),
+                        None => usage_error(&format!("Invalid model: {}", value))
+                    }
+                }
+                "--cgb" => options.model = Some(Model::Cgb),
+                "--dmg" => options.model = Some(Model::Dmg),
+                "--boot-rom" => options.boot_rom = Some(next_value(&mut args, &arg)),
+                "--palette" => {
+                    let value = next_value(&mut args, &arg);
+                    options.palette = match DmgPalette::from_name(&value) {
+                        Some(palette) => palette,
+                        None => usage_error(&format!("Invalid palette: {}", value))
+                    }
+                }
+                "--color-correction" => {
+                    let value = next_value(&mut args, &arg);
+                    options.color_correction = match ColorCorrection::from_name(&value) {
+                        Some(correction) => correction,
+                        None => usage_error(&format!("Invalid color correction: {}", value))
+                    }
+                }
+                "--frame-blending" => {
+                    let value = next_value(&mut args, &arg);
+                    options.frame_blending = match FrameBlending::from_name(&value) {
+                        Some(blending) => blending,
+                        None => usage_error(&format!("Invalid frame blending: {}", value))
+                    }
+                }
+                "--record-wav" => options.record_wav = Some(next_value(&mut args, &arg)),
+                "--record-channels" => options.record_channels = true,
+                "--record-vgm" => options.record_vgm = Some(next_value(&mut args, &arg)),
+                "--input-script" => options.input_script = Some(next_value(&mut args, &arg)),
+                "--record-movie" => options.record_movie = Some(next_value(&mut args, &arg)),
+                "--replay-movie" => options.replay_movie = Some(next_value(&mut args, &arg)),
+                "--hash-every" => options.hash_every = parse_number(&next_value(&mut args, &arg), &arg),
+                "--serial-stdout" => options.serial_stdout = true,
+                "--link-rom" => options.link_rom = Some(next_value(&mut args, &arg)),
+                "--link-listen" => options.link_listen = Some(parse_number(&next_value(&mut args, &arg), &arg)),
+                "--link-connect" => options.link_connect = Some(next_value(&mut args, &arg)),
+                "--printer" => options.printer = true,
+                "--gdb" => options.gdb = Some(parse_number(&next_value(&mut args, &arg), &arg)),
+                "--sample-rate" => options.sample_rate = parse_number(&next_value(&mut args, &arg), &arg),
+                "--high-pass" => {
+                    let value = next_value(&mut args, &arg);
+                    options.high_pass = match HighPassFilter::from_name(&value) {
+                        Some(filter) => filter,
+                        None => usage_error(&format!("Invalid high-pass filter: {}", value))
+                    }
+                }
+                "--track" => options.track = Some(parse_number(&next_value(&mut args, &arg), &arg)),
+                "--seconds" => options.seconds = parse_number(&next_value(&mut args, &arg), &arg),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ if arg.starts_with('-') => usage_error(&format!("Unknown option: {}", arg)),
+                _ => options.rom_path = arg
+            }
+        }
+        options
+    }
+}
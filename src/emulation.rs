@@ -1,25 +1,553 @@
-use log::{debug};
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::lib::apu::filter::HighPassFilter;
 use crate::lib::cpu;
+use crate::lib::gdb::GdbStub;
+use crate::lib::mmu;
+use crate::lib::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::lib::input_script::InputScript;
+use crate::lib::model::Model;
+use crate::lib::movie::{Movie, Start, StateHasher};
+use crate::lib::png;
+use crate::lib::screen::{rgb555_to_rgb, DmgPalette, PostProcess};
+use crate::lib::sgb;
+use crate::lib::vram_dump;
+use crate::lib::wav::WavWriter;
+use crate::link::LinkPort;
+use crate::terminal::TerminalRenderer;
+
+// 70224 clocks per frame at 4.194304 MHz.
+const FRAME_DURATION: Duration = Duration::from_nanos(16_742_706);
+// Machine cycles between two exchanges with the other end of a link cable,
+// one scanline.
+const LINK_SYNC_CYCLES: u32 = 114;
 
 pub struct Emulation {
     rom_data: Vec<u8>,
-    cpu: cpu::CPU
+    boot_rom: Option<Vec<u8>>,
+    cpu: cpu::CPU,
+    palette: DmgPalette,
+    post_process: PostProcess,
+    // Last completed frame as RGBA8, after post-processing.
+    output: Vec<u8>,
+    terminal: Option<TerminalRenderer>,
+    recording: Option<AudioRecording>,
+    input_script: Option<InputScript>,
+    movie: Option<MovieMode>,
+    serial_echo: bool,
+    link: Option<Box<dyn LinkPort>>,
+    // Cycles run since the last exchange over the link cable.
+    link_cycles: u32
+}
+
+enum MovieMode {
+    // State hashes are added every `hash_every` frames.
+    Recording { movie: Movie, hash_every: u32 },
+    Replaying(Movie)
+}
+
+/// WAV files being written while the emulation runs.
+struct AudioRecording {
+    mix: WavWriter,
+    channels: Vec<WavWriter>
+}
+
+/// Where and how often frames are written when running headless.
+pub struct FrameExport {
+    pub every: u32,
+    pub dir: String,
+    pub png: bool,
+    pub rgba: bool
 }
 
 impl Emulation {
     pub fn from_rom(rom: Vec<u8>) -> Emulation {
         debug!("[EMU] Creating new emulation from ROM with size: {} bytes ({} KB)...", rom.len(), (rom.len() / 1024));
-        
+
         let mut emulation = Emulation {
             rom_data: rom,
-            cpu: cpu::CPU::new()
+            boot_rom: None,
+            cpu: cpu::CPU::new(),
+            palette: DmgPalette::default(),
+            post_process: PostProcess::default(),
+            output: Vec::new(),
+            terminal: None,
+            recording: None,
+            input_script: None,
+            movie: None,
+            serial_echo: false,
+            link: None,
+            link_cycles: 0
         };
 
         emulation.cpu.read_rom(&emulation.rom_data);
+        let model = Model::default_for(emulation.cpu.get_mmu().is_cgb_supported());
+        emulation.cpu.set_model(model);
+        emulation.update_output();
         emulation
     }
 
     pub fn start(&mut self) {
-        self.cpu.start()
+        let palette = &self.palette;
+        let post_process = &mut self.post_process;
+        let output = &mut self.output;
+        let terminal = &mut self.terminal;
+        self.cpu.start(&mut |mmu| show_frame(mmu, palette, post_process, output, terminal))
+    }
+
+    /// Lets GDB debug the game over its remote protocol, waiting for it to
+    /// connect on localhost `port`. Returns once GDB detaches.
+    pub fn start_gdb(&mut self, port: u16) -> io::Result<()> {
+        let mut stub = GdbStub::new(&self.cpu);
+        let palette = &self.palette;
+        let post_process = &mut self.post_process;
+        let output = &mut self.output;
+        let terminal = &mut self.terminal;
+        stub.listen(&mut self.cpu, port, &mut |mmu| show_frame(mmu, palette, post_process, output, terminal))
+    }
+
+    /// Draws every completed frame in the terminal using ANSI colors.
+    pub fn enable_terminal(&mut self) {
+        self.terminal = Some(TerminalRenderer::new());
+    }
+
+    /// Selects the hardware to emulate, overriding the model picked from the
+    /// ROM header. Resets the machine to the state after the boot ROM, so it
+    /// must be called before any frame has run.
+    pub fn set_model(&mut self, model: Model) {
+        self.cpu.set_model(model);
+        if let Some(boot_rom) = self.boot_rom.take() {
+            if let Err(error) = self.set_boot_rom(boot_rom) {
+                warn!("[EMU] Boot ROM dropped: {}", error);
+            }
+        }
+    }
+
+    /// Runs `boot_rom` from power on instead of starting in the state it
+    /// leaves behind. It must be the 256 byte DMG or SGB boot ROM, or the
+    /// 2304 byte CGB one on color models.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> io::Result<()> {
+        let model = self.cpu.get_mmu().get_model();
+        let expected = if model.is_cgb() { mmu::BOOT_ROM_CGB_SIZE } else { mmu::BOOT_ROM_DMG_SIZE };
+        if boot_rom.len() != expected {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Boot ROM has {} bytes, {} expects {}", boot_rom.len(), model.get_name(), expected)));
+        }
+        self.cpu.set_boot_rom(boot_rom.clone());
+        self.boot_rom = Some(boot_rom);
+        self.update_output();
+        Ok(())
+    }
+
+    pub fn set_palette(&mut self, palette: DmgPalette) {
+        self.palette = palette;
+    }
+
+    /// Sets the color correction and frame blending applied to the output.
+    pub fn set_post_process(&mut self, post_process: PostProcess) {
+        self.post_process = post_process;
+    }
+
+    /// Sets which buttons are held down, as a mask of `Button::mask` values.
+    /// Pressing a button requests the joypad interrupt if its group is
+    /// selected by the game.
+    pub fn set_buttons(&mut self, pressed: u8) {
+        self.cpu.get_mmu_mut().set_buttons(pressed);
+    }
+
+    /// Sets the buttons of `player` (0 to 3) in the SGB multiplayer mode.
+    /// Player 0 is the one `set_buttons` controls.
+    pub fn set_player_buttons(&mut self, player: u8, pressed: u8) {
+        self.cpu.get_mmu_mut().set_player_buttons(player, pressed);
+    }
+
+    /// Prints the bytes sent over the serial port to stdout as they are sent,
+    /// which is how many test ROMs report their results.
+    pub fn set_serial_echo(&mut self, echo: bool) {
+        self.serial_echo = echo;
+    }
+
+    /// Plugs a link cable into the serial port. Both ends must be connected
+    /// before their first frame to stay in lockstep.
+    pub fn connect_link(&mut self, link: Box<dyn LinkPort>) {
+        self.link = Some(link);
+        self.link_cycles = 0;
+    }
+
+    /// Unplugs the link cable, so that the other end stops waiting for this
+    /// one once it has run out of frames.
+    pub fn disconnect_link(&mut self) {
+        self.link = None;
+    }
+
+    /// Drives the buttons from `script` during headless runs.
+    pub fn set_input_script(&mut self, script: InputScript) {
+        self.input_script = Some(script);
+    }
+
+    /// Records the buttons of every frame of headless runs from now on, with
+    /// a state hash every `hash_every` frames (0 for none).
+    pub fn start_movie_recording(&mut self, hash_every: u32) {
+        let model = self.cpu.get_mmu().get_model();
+        let mut movie = Movie::new(png::crc32(&[&self.rom_data]), model, self.movie_start());
+        if hash_every > 0 {
+            movie.push_hash(self.state_hash());
+        }
+        self.movie = Some(MovieMode::Recording { movie, hash_every });
+    }
+
+    pub fn save_movie(&self, file_name: &str) -> io::Result<()> {
+        match &self.movie {
+            Some(MovieMode::Recording { movie, .. }) => {
+                debug!("[EMU] Writing movie with {} frames to {}", movie.get_frame_count(), file_name);
+                movie.save(file_name)
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Takes the buttons of headless runs from `movie` and checks the state
+    /// against its hashes. Must be called before any frame has run, after the
+    /// boot ROM the movie was recorded with, if any, has been set.
+    pub fn replay_movie(&mut self, movie: Movie) -> io::Result<()> {
+        let rom_crc32 = png::crc32(&[&self.rom_data]);
+        if movie.rom_crc32 != rom_crc32 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Movie was recorded with ROM {:08x}, not {:08x}", movie.rom_crc32, rom_crc32)));
+        }
+        self.set_model(movie.model);
+        let start = self.movie_start();
+        if movie.start != start {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Movie was recorded from {}, not {}", describe_start(movie.start), describe_start(start))));
+        }
+        self.movie = Some(MovieMode::Replaying(movie));
+        self.update_movie(0)
+    }
+
+    // State runs start from: power on with the boot ROM, or the state it
+    // leaves behind.
+    fn movie_start(&self) -> Start {
+        match &self.boot_rom {
+            Some(boot_rom) => Start::BootRom(png::crc32(&[boot_rom])),
+            None => Start::PostBoot
+        }
+    }
+
+    /// Hash of the CPU registers, the memory map and the screen.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        self.cpu.hash_state(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.cpu.get_mmu().get_gpu().get_frame_count()
+    }
+
+    /// Makes the APU produce stereo samples at `sample_rate` Hz.
+    pub fn set_audio_output(&mut self, sample_rate: u32, filter: HighPassFilter) {
+        self.cpu.get_mmu_mut().get_apu_mut().set_sample_rate(sample_rate, filter);
+    }
+
+    /// Returns the interleaved stereo samples produced since the last call.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.get_mmu_mut().get_apu_mut().take_samples()
+    }
+
+    /// Writes the stereo mix to `file_name` as 16-bit PCM for every frame run
+    /// from now on. With `per_channel`, each of the four channels is also
+    /// written to its own mono file, named after `file_name` with a `_ch1` to
+    /// `_ch4` suffix.
+    pub fn start_audio_recording(&mut self, file_name: &str, sample_rate: u32, filter: HighPassFilter, per_channel: bool) -> io::Result<()> {
+        debug!("[EMU] Recording audio at {} Hz to {}", sample_rate, file_name);
+        self.set_audio_output(sample_rate, filter);
+        let mut channels = Vec::new();
+        if per_channel {
+            self.cpu.get_mmu_mut().get_apu_mut().enable_channel_capture(filter);
+            let stem = file_name.strip_suffix(".wav").unwrap_or(file_name);
+            for channel in 1..=4 {
+                channels.push(WavWriter::create(&format!("{}_ch{}.wav", stem, channel), 1, sample_rate)?);
+            }
+        }
+        self.recording = Some(AudioRecording {
+            mix: WavWriter::create(file_name, 2, sample_rate)?,
+            channels
+        });
+        Ok(())
+    }
+
+    /// Completes the WAV files started by `start_audio_recording`.
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        if let Some(recording) = self.recording.take() {
+            recording.mix.finish()?;
+            for writer in recording.channels {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Logs every APU register write from now on, to be saved as VGM by
+    /// `save_vgm`.
+    pub fn start_vgm_log(&mut self) {
+        self.cpu.get_mmu_mut().get_apu_mut().start_vgm_log();
+    }
+
+    /// Writes the APU register writes logged since `start_vgm_log` to a VGM
+    /// file and stops logging.
+    pub fn save_vgm(&mut self, file_name: &str) -> io::Result<()> {
+        match self.cpu.get_mmu_mut().get_apu_mut().take_vgm_log() {
+            Some(log) => {
+                debug!("[EMU] Writing VGM log to {}", file_name);
+                fs::write(file_name, log.encode())
+            }
+            None => Ok(())
+        }
+    }
+
+    /// Runs the CPU until the PPU completes the current frame.
+    pub fn run_frame(&mut self) -> io::Result<()> {
+        let frame = self.get_frame_count();
+        while self.get_frame_count() == frame {
+            let cycles = self.cpu.step();
+            if self.link.is_some() {
+                self.link_cycles += cycles;
+                if self.link_cycles >= LINK_SYNC_CYCLES {
+                    self.link_cycles -= LINK_SYNC_CYCLES;
+                    self.sync_link();
+                }
+            }
+        }
+        self.update_output();
+        self.echo_serial()?;
+        self.record_audio()
+    }
+
+    fn sync_link(&mut self) {
+        if let Some(link) = &mut self.link {
+            let serial = self.cpu.get_mmu_mut().get_serial_mut();
+            let state = serial.link_state();
+            match link.exchange(state) {
+                Some(peer) => serial.apply_link_state(&state, &peer),
+                None => {
+                    debug!("[EMU] Link cable disconnected");
+                    self.link = None;
+                }
+            }
+        }
+    }
+
+    fn echo_serial(&mut self) -> io::Result<()> {
+        if !self.serial_echo {
+            return Ok(());
+        }
+        let output = self.cpu.get_mmu_mut().take_serial_output();
+        if !output.is_empty() {
+            let mut stdout = io::stdout();
+            stdout.write_all(&output)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    fn record_audio(&mut self) -> io::Result<()> {
+        if self.recording.is_none() {
+            return Ok(());
+        }
+        let samples = self.take_audio_samples();
+        let channel_samples = self.cpu.get_mmu_mut().get_apu_mut().take_channel_samples();
+        if let Some(recording) = &mut self.recording {
+            recording.mix.write_samples(&samples)?;
+            for (writer, samples) in recording.channels.iter_mut().zip(channel_samples.iter()) {
+                writer.write_samples(samples)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_output(&mut self) {
+        self.output = process_frame(self.cpu.get_mmu(), &self.palette, &mut self.post_process);
+    }
+
+    /// Returns the last completed frame as RGBA8, 160x144 pixels.
+    pub fn frame_rgba(&self) -> &[u8] {
+        &self.output
     }
-}
\ No newline at end of file
+
+    /// Writes the last frame as a PNG. With SGB functions enabled, the frame
+    /// is drawn inside the 256x224 SGB border.
+    pub fn save_png(&self, file_name: &str) -> io::Result<()> {
+        match self.cpu.get_mmu().get_sgb() {
+            Some(sgb) => {
+                let mut rgba = Vec::with_capacity(sgb::BORDER_WIDTH * sgb::BORDER_HEIGHT * 4);
+                for color in sgb.render_border() {
+                    rgba.extend_from_slice(&rgb555_to_rgb(color));
+                    rgba.push(0xFF);
+                }
+                for y in 0..SCREEN_HEIGHT {
+                    let start = ((sgb::SCREEN_Y + y) * sgb::BORDER_WIDTH + sgb::SCREEN_X) * 4;
+                    rgba[start..start + SCREEN_WIDTH * 4].copy_from_slice(&self.output[y * SCREEN_WIDTH * 4..(y + 1) * SCREEN_WIDTH * 4]);
+                }
+                png::write_rgba(file_name, sgb::BORDER_WIDTH as u32, sgb::BORDER_HEIGHT as u32, &rgba)
+            }
+            None => png::write_rgba(file_name, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, self.frame_rgba())
+        }
+    }
+
+    pub fn save_rgba(&self, file_name: &str) -> io::Result<()> {
+        fs::write(file_name, self.frame_rgba())
+    }
+
+    /// Runs `frames` frames (None runs forever) without any user interaction,
+    /// exporting the framebuffer every `export.every` frames and after the
+    /// last one. Buttons are taken from the input script, if any, with
+    /// frames counted from the start of the run. When the terminal renderer
+    /// is enabled, frames are drawn and paced to real time. The audio
+    /// recording is completed even when the run fails.
+    pub fn run_headless(&mut self, frames: Option<u64>, export: &FrameExport) -> io::Result<()> {
+        let result = self.run_frames(frames, export);
+        let stopped = self.stop_audio_recording();
+        result.and(stopped)
+    }
+
+    fn run_frames(&mut self, frames: Option<u64>, export: &FrameExport) -> io::Result<()> {
+        if export.png || export.rgba {
+            fs::create_dir_all(&export.dir)?;
+        }
+        let mut frame = 0;
+        while frames.is_none_or(|frames| frame < frames) {
+            let frame_start = Instant::now();
+            if let Some(script) = &self.input_script {
+                let pressed = script.buttons_at(frame);
+                let others: Vec<u8> = (1..script.get_player_count())
+                    .map(|player| script.player_buttons_at(player, frame))
+                    .collect();
+                self.set_buttons(pressed);
+                for (player, pressed) in others.into_iter().enumerate() {
+                    self.set_player_buttons(player as u8 + 1, pressed);
+                }
+            }
+            if let Some(MovieMode::Replaying(movie)) = &self.movie {
+                let buttons = movie.get_input(frame).unwrap_or_default();
+                for (player, pressed) in buttons.iter().enumerate() {
+                    self.set_player_buttons(player as u8, *pressed);
+                }
+            }
+            self.run_frame()?;
+            frame += 1;
+            self.update_movie(frame)?;
+            if let Some(renderer) = &mut self.terminal {
+                renderer.draw(&self.output)?;
+                if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+                    thread::sleep(remaining);
+                }
+            }
+            let periodic = export.every > 0 && frame % export.every as u64 == 0;
+            if periodic || Some(frame) == frames {
+                self.export_frame(frame, export)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the tile data, both tile maps and OAM as PNG files into `dir`,
+    /// along with a text summary of the PPU registers and OAM table.
+    pub fn dump_vram(&self, dir: &str) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let gpu = self.cpu.get_mmu().get_gpu();
+        let images = [
+            ("vram_tiles.png", vram_dump::tiles(gpu, &self.palette)),
+            ("vram_map0.png", vram_dump::tilemap(gpu, 0, &self.palette)),
+            ("vram_map1.png", vram_dump::tilemap(gpu, 1, &self.palette)),
+            ("vram_oam.png", vram_dump::oam(gpu, &self.palette)),
+        ];
+        for (name, image) in images.iter() {
+            let file_name = format!("{}/{}", dir, name);
+            debug!("[EMU] Writing {}", file_name);
+            png::write_rgba(&file_name, image.width as u32, image.height as u32, &image.rgba)?;
+        }
+        fs::write(format!("{}/vram.txt", dir), vram_dump::summary(gpu))
+    }
+
+    // Records or checks the frame that just completed, `frame` being the
+    // number of frames run so far.
+    fn update_movie(&mut self, frame: u64) -> io::Result<()> {
+        let hash_due = match &self.movie {
+            Some(MovieMode::Recording { hash_every, .. }) => *hash_every > 0 && frame.is_multiple_of(*hash_every as u64),
+            Some(MovieMode::Replaying(movie)) => movie.get_hash(frame).is_some(),
+            None => false
+        };
+        let hash = if hash_due { self.state_hash() } else { 0 };
+        let mmu = self.cpu.get_mmu();
+        let buttons = std::array::from_fn(|player| mmu.get_player_buttons(player as u8));
+        match &mut self.movie {
+            Some(MovieMode::Recording { movie, .. }) => {
+                movie.push_input(buttons);
+                if hash_due {
+                    movie.push_hash(hash);
+                }
+            }
+            Some(MovieMode::Replaying(movie)) => {
+                if let Some(expected) = movie.get_hash(frame) {
+                    if expected != hash {
+                        return Err(io::Error::other(
+                            format!("Desync after frame {}: state hash {:016x}, expected {:016x}", frame, hash, expected)));
+                    }
+                    debug!("[EMU] State hash after frame {} matches the movie", frame);
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn export_frame(&self, frame: u64, export: &FrameExport) -> io::Result<()> {
+        if export.png {
+            let file_name = format!("{}/frame_{:06}.png", export.dir, frame);
+            debug!("[EMU] Writing frame {} to {}", frame, file_name);
+            self.save_png(&file_name)?;
+        }
+        if export.rgba {
+            let file_name = format!("{}/frame_{:06}.rgba", export.dir, frame);
+            debug!("[EMU] Writing frame {} to {}", frame, file_name);
+            self.save_rgba(&file_name)?;
+        }
+        Ok(())
+    }
+}
+
+// Post-processes the frame the PPU just completed, as the SGB colors it when
+// SGB functions are enabled.
+fn process_frame(mmu: &mmu::MMU, palette: &DmgPalette, post_process: &mut PostProcess) -> Vec<u8> {
+    let gpu = mmu.get_gpu();
+    match mmu.get_sgb() {
+        Some(sgb) => post_process.process(sgb.get_screen(), true, palette),
+        None => post_process.process(gpu.get_framebuffer(), gpu.has_color_output(), palette)
+    }
+}
+
+fn describe_start(start: Start) -> String {
+    match start {
+        Start::PostBoot => String::from("the post-boot state"),
+        Start::BootRom(crc32) => format!("boot ROM {:08x}", crc32)
+    }
+}
+
+// Post-processes the frame the PPU just completed in an interactive run and
+// draws it in the terminal if enabled.
+fn show_frame(mmu: &mmu::MMU, palette: &DmgPalette, post_process: &mut PostProcess, output: &mut Vec<u8>, terminal: &mut Option<TerminalRenderer>) {
+    *output = process_frame(mmu, palette, post_process);
+    if let Some(renderer) = terminal {
+        if let Err(error) = renderer.draw(output) {
+            warn!("[EMU] Could not draw frame in terminal: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./emulation_test.rs"]
+mod emulation_test;
@@ -0,0 +1,119 @@
+use super::*;
+use crate::link::ChannelLink;
+
+fn no_export() -> FrameExport {
+    FrameExport { every: 0, dir: String::new(), png: false, rgba: false }
+}
+
+fn emulation() -> Emulation {
+    Emulation::from_rom(vec![0; 0x8000])
+}
+
+fn recorded_movie(e: &Emulation, frames: u64) -> Movie {
+    let mut movie = Movie::new(png::crc32(&[&e.rom_data]), e.cpu.get_mmu().get_model(), Start::PostBoot);
+    for _ in 0..frames {
+        movie.push_input([0; sgb::PLAYER_COUNT]);
+    }
+    movie
+}
+
+#[test]
+fn test_replaying_an_empty_movie_runs_no_frames() {
+    let mut e = emulation();
+    let movie = recorded_movie(&e, 0);
+    e.replay_movie(movie).unwrap();
+    let frames = e.get_frame_count();
+    e.run_headless(Some(0), &no_export()).unwrap();
+    assert_eq!(e.get_frame_count(), frames);
+}
+
+#[test]
+fn test_run_headless_stops_after_the_frame_count() {
+    let mut e = emulation();
+    let frames = e.get_frame_count();
+    e.run_headless(Some(2), &no_export()).unwrap();
+    assert_eq!(e.get_frame_count(), frames + 2);
+}
+
+#[test]
+fn test_replay_checks_the_start_state() {
+    let mut e = emulation();
+    let mut movie = recorded_movie(&e, 1);
+    movie.start = Start::BootRom(0x1234ABCD);
+    assert!(e.replay_movie(movie).is_err());
+
+    let mut e = emulation();
+    e.set_boot_rom(vec![0; 0x100]).unwrap();
+    assert!(e.replay_movie(recorded_movie(&e, 1)).is_err());
+    let mut movie = recorded_movie(&e, 1);
+    movie.start = Start::BootRom(png::crc32(&[&[0; 0x100]]));
+    e.replay_movie(movie).unwrap();
+}
+
+#[test]
+fn test_show_frame_uses_the_sgb_screen() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0146] = 0x03;
+    rom[0x014B] = 0x33;
+    let mut e = Emulation::from_rom(rom);
+    e.set_model(Model::Sgb);
+    assert!(e.cpu.get_mmu().get_sgb().is_some());
+    e.run_headless(Some(1), &no_export()).unwrap();
+    let mut output = Vec::new();
+    show_frame(e.cpu.get_mmu(), &e.palette, &mut e.post_process, &mut output, &mut None);
+    assert_eq!(output, e.frame_rgba());
+}
+
+#[test]
+fn test_linked_instances_can_end_on_different_exchange_counts() {
+    let mut player1 = emulation();
+    let mut player2 = emulation();
+    let (link1, link2) = ChannelLink::pair();
+    player1.connect_link(Box::new(link1));
+    player2.connect_link(Box::new(link2));
+    let player2 = thread::spawn(move || player2.run_headless(Some(3), &no_export()));
+    player1.run_headless(Some(1), &no_export()).unwrap();
+    player1.disconnect_link();
+    player2.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_audio_recording_is_completed_after_a_desync() {
+    let file_name = std::env::temp_dir().join("gb_emulation_desync_test.wav");
+    let file_name = file_name.to_str().unwrap();
+    let mut e = emulation();
+    let mut movie = recorded_movie(&e, 2);
+    movie.push_hash(0);
+    e.replay_movie(movie).unwrap();
+    e.start_audio_recording(file_name, 48000, HighPassFilter::Off, false).unwrap();
+    assert!(e.run_headless(Some(2), &no_export()).is_err());
+
+    let data = fs::read(file_name).unwrap();
+    fs::remove_file(file_name).unwrap();
+    assert!(data.len() > 44);
+    assert_eq!(u32::from_le_bytes([data[40], data[41], data[42], data[43]]) as usize, data.len() - 44);
+}
+
+#[test]
+fn test_movies_record_every_sgb_player() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0146] = 0x03;
+    rom[0x014B] = 0x33;
+    let file_name = std::env::temp_dir().join("gb_emulation_players_test.movie");
+    let file_name = file_name.to_str().unwrap();
+
+    let mut e = Emulation::from_rom(rom.clone());
+    e.set_model(Model::Sgb);
+    e.start_movie_recording(0);
+    e.set_player_buttons(2, 0x81);
+    e.run_headless(Some(1), &no_export()).unwrap();
+    e.save_movie(file_name).unwrap();
+    let movie = Movie::from_file(file_name).unwrap();
+    fs::remove_file(file_name).unwrap();
+    assert_eq!(movie.get_input(0), Some([0x00, 0x00, 0x81, 0x00]));
+
+    let mut e = Emulation::from_rom(rom);
+    e.replay_movie(movie).unwrap();
+    e.run_headless(Some(1), &no_export()).unwrap();
+    assert_eq!(e.cpu.get_mmu().get_player_buttons(2), 0x81);
+}
@@ -0,0 +1,80 @@
+use std::io;
+use crate::lib::apu::CLOCK_RATE;
+use crate::lib::apu::filter::HighPassFilter;
+use crate::lib::cpu;
+use crate::lib::gbs::{GbsHeader, HEADER_SIZE};
+use crate::lib::mbc::MbcType;
+use crate::lib::mbc::gbs::GbsRom;
+use crate::lib::wav::WavWriter;
+
+const CYCLES_PER_SECOND: u64 = CLOCK_RATE as u64 / 4;
+// Drivers that never return from init still get to play after a second.
+const INIT_MAX_CYCLES: u32 = CYCLES_PER_SECOND as u32;
+
+/// Plays GBS sound rips with the CPU, timer and APU of the emulator, calling
+/// the play routine of the rip at the rate set in its header.
+pub struct GbsPlayer {
+    cpu: cpu::CPU,
+    header: GbsHeader
+}
+
+impl GbsPlayer {
+    pub fn from_gbs(data: &[u8]) -> io::Result<GbsPlayer> {
+        let header = GbsHeader::parse(data)?;
+        debug!("[GBS] Loading \"{}\" by {} ({} songs)", header.title, header.author, header.song_count);
+        let mut cpu = cpu::CPU::new();
+        cpu.get_mmu_mut().set_mbc(MbcType::GBS(GbsRom::from_code(header.load_addr, &data[HEADER_SIZE..])));
+        Ok(GbsPlayer { cpu, header })
+    }
+
+    pub fn get_header(&self) -> &GbsHeader {
+        &self.header
+    }
+
+    /// Sets up the hardware like a GBS player does and runs the init
+    /// routine for `track`, counted from 1.
+    pub fn init_track(&mut self, track: u8) -> io::Result<()> {
+        if track == 0 || track > self.header.song_count {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Track {} out of range 1-{}", track, self.header.song_count)));
+        }
+        let mmu = self.cpu.get_mmu_mut();
+        mmu.wb(0xFF26, 0x80);
+        mmu.wb(0xFF25, 0xFF);
+        mmu.wb(0xFF24, 0x77);
+        mmu.wb(0xFF06, self.header.timer_modulo);
+        mmu.wb(0xFF07, self.header.timer_control);
+        let registers = self.cpu.get_registers_mut();
+        registers.set_a(track - 1);
+        registers.set_sp(self.header.stack_pointer);
+        self.cpu.call(self.header.init_addr, INIT_MAX_CYCLES);
+        Ok(())
+    }
+
+    /// Calls the play routine once and lets the hardware run until the
+    /// next call is due.
+    pub fn play_step(&mut self) {
+        let period = self.header.play_period();
+        let cycles = self.cpu.call(self.header.play_addr, period);
+        if cycles < period {
+            self.cpu.get_mmu_mut().step(period - cycles);
+        }
+    }
+
+    /// Plays `track` for `seconds` and writes the output to a 16-bit stereo
+    /// WAV file.
+    pub fn render_wav(&mut self, file_name: &str, track: u8, seconds: u32, sample_rate: u32, filter: HighPassFilter) -> io::Result<()> {
+        debug!("[GBS] Rendering track {} for {} s to {}", track, seconds, file_name);
+        let mut wav = WavWriter::create(file_name, 2, sample_rate)?;
+        self.cpu.get_mmu_mut().get_apu_mut().set_sample_rate(sample_rate, filter);
+        self.init_track(track)?;
+        let total = seconds as u64 * CYCLES_PER_SECOND;
+        let mut elapsed = 0;
+        while elapsed < total {
+            self.play_step();
+            elapsed += self.header.play_period() as u64;
+            wav.write_samples(&self.cpu.get_mmu_mut().get_apu_mut().take_samples())?;
+        }
+        wav.finish()
+    }
+}
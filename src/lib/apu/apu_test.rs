@@ -0,0 +1,280 @@
+use super::*;
+
+fn trigger_square1(apu: &mut APU, nr10: u8, frequency: u16) {
+    apu.wb(0xFF10, nr10);
+    apu.wb(0xFF11, 0x80);
+    apu.wb(0xFF12, 0xF0);
+    apu.wb(0xFF13, frequency as u8);
+    apu.wb(0xFF14, 0x80 | (frequency >> 8) as u8);
+}
+
+fn channel_status(apu: &APU) -> u8 {
+    apu.rb(0xFF26) & 0x0F
+}
+
+#[test]
+fn test_trigger_enables_channel() {
+    let mut apu = APU::new();
+    trigger_square1(&mut apu, 0x00, 0x400);
+    assert_eq!(channel_status(&apu), 0x01);
+}
+
+#[test]
+fn test_dac_off_disables_channel() {
+    let mut apu = APU::new();
+    trigger_square1(&mut apu, 0x00, 0x400);
+    apu.wb(0xFF12, 0x00);
+    assert_eq!(channel_status(&apu), 0x00);
+}
+
+#[test]
+fn test_sweep_overflow_on_trigger_disables_channel() {
+    let mut apu = APU::new();
+    // Period 1, addition, shift 1: 0x7FF + 0x3FF overflows immediately.
+    trigger_square1(&mut apu, 0x11, 0x7FF);
+    assert_eq!(channel_status(&apu), 0x00);
+}
+
+#[test]
+fn test_sweep_keeps_adding_until_overflow() {
+    let mut apu = APU::new();
+    // 0x100 -> 0x180 -> 0x240 -> 0x360 -> 0x510 -> 0x798, then 0xB64 overflows.
+    trigger_square1(&mut apu, 0x11, 0x100);
+    // Sweep is clocked on steps 2 and 6, so four sweeps take 18 clocks.
+    for _ in 0..18 {
+        apu.clock_frame_sequencer();
+    }
+    assert_eq!(channel_status(&apu), 0x01);
+    apu.clock_frame_sequencer();
+    assert_eq!(channel_status(&apu), 0x00);
+}
+
+#[test]
+fn test_clearing_negate_after_negated_sweep_disables_channel() {
+    let mut apu = APU::new();
+    trigger_square1(&mut apu, 0x19, 0x400);
+    assert_eq!(channel_status(&apu), 0x01);
+    apu.wb(0xFF10, 0x11);
+    assert_eq!(channel_status(&apu), 0x00);
+}
+
+#[test]
+fn test_length_counter_disables_channel() {
+    let mut apu = APU::new();
+    // Move the frame sequencer to a step that clocks length next.
+    for _ in 0..8 {
+        apu.clock_frame_sequencer();
+    }
+    apu.wb(0xFF17, 0xF0);
+    apu.wb(0xFF16, 0x3E); // length = 64 - 62 = 2
+    apu.wb(0xFF19, 0xC0);
+    assert_eq!(channel_status(&apu), 0x02);
+    apu.clock_frame_sequencer();
+    apu.clock_frame_sequencer();
+    assert_eq!(channel_status(&apu), 0x02);
+    apu.clock_frame_sequencer();
+    assert_eq!(channel_status(&apu), 0x00);
+}
+
+#[test]
+fn test_enabling_length_gets_extra_clock() {
+    let mut apu = APU::new();
+    // After step 0 the next step does not clock length.
+    apu.clock_frame_sequencer();
+    apu.wb(0xFF17, 0xF0);
+    apu.wb(0xFF16, 0x3F); // length = 1
+    apu.wb(0xFF19, 0x80);
+    assert_eq!(channel_status(&apu), 0x02);
+    apu.wb(0xFF19, 0x40);
+    assert_eq!(channel_status(&apu), 0x00);
+}
+
+#[test]
+fn test_envelope_decreases_volume() {
+    let mut apu = APU::new();
+    // 50% duty starts with a high sample.
+    apu.wb(0xFF16, 0x80);
+    apu.wb(0xFF17, 0xF1);
+    apu.wb(0xFF19, 0x80);
+    assert_eq!(apu.square2.output(), 15);
+    for _ in 0..8 {
+        apu.clock_frame_sequencer();
+    }
+    assert_eq!(apu.square2.output(), 14);
+}
+
+#[test]
+fn test_registers_read_back_with_unused_bits_set() {
+    let mut apu = APU::new();
+    apu.wb(0xFF10, 0x00);
+    apu.wb(0xFF11, 0xBF);
+    apu.wb(0xFF13, 0x12);
+    apu.wb(0xFF14, 0x40);
+    assert_eq!(apu.rb(0xFF10), 0x80);
+    assert_eq!(apu.rb(0xFF11), 0xBF);
+    assert_eq!(apu.rb(0xFF13), 0xFF);
+    assert_eq!(apu.rb(0xFF14), 0xFF);
+    assert_eq!(apu.rb(0xFF15), 0xFF);
+}
+
+#[test]
+fn test_wave_ram_accessible_while_channel_off() {
+    let mut apu = APU::new();
+    apu.wb(0xFF30, 0x12);
+    apu.wb(0xFF3F, 0xEF);
+    assert_eq!(apu.rb(0xFF30), 0x12);
+    assert_eq!(apu.rb(0xFF3F), 0xEF);
+}
+
+#[test]
+fn test_wave_ram_blocked_while_playing() {
+    let mut apu = APU::new();
+    apu.wb(0xFF30, 0x12);
+    apu.wb(0xFF1A, 0x80);
+    apu.wb(0xFF1E, 0x80);
+    assert_eq!(apu.rb(0xFF26) & 0x04, 0x04);
+    assert_eq!(apu.rb(0xFF30), 0xFF);
+    apu.wb(0xFF30, 0x34);
+    apu.wb(0xFF1A, 0x00);
+    assert_eq!(apu.rb(0xFF30), 0x12);
+}
+
+#[test]
+fn test_cgb_wave_ram_reads_current_byte_while_playing() {
+    let mut apu = APU::new();
+    apu.set_cgb(true);
+    apu.wb(0xFF30, 0x12);
+    apu.wb(0xFF1A, 0x80);
+    apu.wb(0xFF1E, 0x80);
+    assert_eq!(apu.rb(0xFF3F), 0x12);
+}
+
+#[test]
+fn test_wave_output_volume_shift() {
+    let mut apu = APU::new();
+    apu.wb(0xFF30, 0xF0);
+    apu.wb(0xFF31, 0xC0);
+    apu.wb(0xFF1A, 0x80);
+    apu.wb(0xFF1C, 0x20); // 100%
+    apu.wb(0xFF1D, 0xFF);
+    apu.wb(0xFF1E, 0x87);
+    // Frequency 0x7FF moves to the next sample every 2 clocks, after a 6 clock delay.
+    apu.wave.step(8);
+    assert_eq!(apu.wave.output(), 0x00); // sample 1: low nibble of byte 0
+    apu.wave.step(2);
+    assert_eq!(apu.wave.output(), 0x0C); // sample 2: high nibble of byte 1
+    apu.wb(0xFF1C, 0x40); // 50%
+    assert_eq!(apu.wave.output(), 0x06);
+    apu.wb(0xFF1C, 0x60); // 25%
+    assert_eq!(apu.wave.output(), 0x03);
+    apu.wb(0xFF1C, 0x00); // mute
+    assert_eq!(apu.wave.output(), 0x00);
+}
+
+fn noise_sequence(apu: &mut APU, steps: usize) -> Vec<u8> {
+    (0..steps).map(|_| {
+        // Divisor code 0 and shift 0: the LFSR is clocked every 8 clocks.
+        apu.noise.step(8);
+        apu.noise.output()
+    }).collect()
+}
+
+#[test]
+fn test_noise_7bit_mode_repeats_every_127_steps() {
+    let mut apu = APU::new();
+    apu.wb(0xFF21, 0xF0);
+    apu.wb(0xFF22, 0x08);
+    apu.wb(0xFF23, 0x80);
+    let sequence = noise_sequence(&mut apu, 20 + 127 * 2);
+    assert_eq!(sequence[20..147], sequence[147..274]);
+    assert!(sequence.contains(&15));
+    assert!(sequence.contains(&0));
+}
+
+#[test]
+fn test_noise_15bit_mode_does_not_repeat_after_127_steps() {
+    let mut apu = APU::new();
+    apu.wb(0xFF21, 0xF0);
+    apu.wb(0xFF22, 0x00);
+    apu.wb(0xFF23, 0x80);
+    let sequence = noise_sequence(&mut apu, 20 + 127 * 2);
+    assert_ne!(sequence[20..147], sequence[147..274]);
+}
+
+#[test]
+fn test_noise_registers_read_back() {
+    let mut apu = APU::new();
+    apu.wb(0xFF20, 0x3F);
+    apu.wb(0xFF21, 0xA5);
+    apu.wb(0xFF22, 0x5B);
+    apu.wb(0xFF23, 0x40);
+    assert_eq!(apu.rb(0xFF1F), 0xFF);
+    assert_eq!(apu.rb(0xFF20), 0xFF);
+    assert_eq!(apu.rb(0xFF21), 0xA5);
+    assert_eq!(apu.rb(0xFF22), 0x5B);
+    assert_eq!(apu.rb(0xFF23), 0xFF);
+}
+
+#[test]
+fn test_power_off_clears_registers_and_ignores_writes() {
+    let mut apu = APU::new();
+    apu.wb(0xFF24, 0x77);
+    apu.wb(0xFF25, 0xFF);
+    trigger_square1(&mut apu, 0x00, 0x400);
+    apu.wb(0xFF30, 0x5A);
+    apu.wb(0xFF26, 0x00);
+    assert_eq!(apu.rb(0xFF26), 0x70);
+    assert_eq!(apu.rb(0xFF24), 0x00);
+    assert_eq!(apu.rb(0xFF25), 0x00);
+    assert_eq!(apu.rb(0xFF12), 0x00);
+    apu.wb(0xFF12, 0xF0);
+    assert_eq!(apu.rb(0xFF12), 0x00);
+    // Wave RAM survives and stays writable.
+    assert_eq!(apu.rb(0xFF30), 0x5A);
+    apu.wb(0xFF26, 0x80);
+    assert_eq!(apu.rb(0xFF26), 0xF0);
+    apu.wb(0xFF12, 0xF0);
+    assert_eq!(apu.rb(0xFF12), 0xF0);
+}
+
+#[test]
+fn test_sample_rate_and_panning() {
+    let mut apu = APU::new();
+    apu.set_sample_rate(48000, filter::HighPassFilter::Off);
+    apu.wb(0xFF24, 0x77);
+    apu.wb(0xFF25, 0x11); // channel 1 on both sides
+    trigger_square1(&mut apu, 0x00, 0x700);
+    apu.wb(0xFF25, 0x10); // channel 1 on the left only
+    // One frame worth of machine cycles.
+    for _ in 0..17556 {
+        apu.step(1);
+    }
+    let samples = apu.take_samples();
+    let frames = samples.len() / 2;
+    assert!((803..=804).contains(&frames), "{} samples", frames);
+    let left_peak = samples.iter().step_by(2).fold(0f32, |m, s| m.max(s.abs()));
+    let right_peak = samples.iter().skip(100).skip(1).step_by(2).fold(0f32, |m, s| m.max(s.abs()));
+    assert!(left_peak > 0.2);
+    assert!(right_peak < 0.01);
+}
+
+#[test]
+fn test_blip_buffer_settles_at_step_level() {
+    let mut blip = blip::BlipBuffer::new(CLOCK_RATE, 48000);
+    blip.set_amplitude(1000, 0.5);
+    let mut out = Vec::new();
+    blip.end_frame(CLOCK_RATE as u64 / 64, &mut out);
+    assert_eq!(out.len(), 750);
+    assert!(out[0].abs() < 0.001);
+    assert!((out[749] - 0.5).abs() < 0.0001);
+}
+
+#[test]
+fn test_high_pass_removes_dc_offset() {
+    let mut high_pass = filter::HighPass::new(filter::HighPassFilter::Dmg, CLOCK_RATE, 48000);
+    let mut last = 1.0;
+    for _ in 0..48000 {
+        last = high_pass.apply(1.0);
+    }
+    assert!(last.abs() < 0.01);
+}
@@ -0,0 +1,89 @@
+use std::f64::consts::PI;
+
+// Number of sub-sample positions a step can be placed at, and kernel width.
+const PHASES: usize = 64;
+const TAPS: usize = 16;
+// Cutoff as a fraction of the output sample rate, just below Nyquist.
+const CUTOFF: f64 = 0.45;
+
+/// Band-limited resampler. The input is a signal that changes in steps at
+/// arbitrary clock positions (like the APU channels); each step is added as
+/// a windowed sinc so that the output at `sample_rate` is free of aliasing.
+pub struct BlipBuffer {
+    samples_per_clock: f64,
+    // Position of clock 0 of the current frame, in output samples.
+    offset: f64,
+    deltas: Vec<f32>,
+    integrator: f32,
+    amplitude: f32,
+    kernel: Vec<[f32; TAPS]>
+}
+
+fn build_kernel() -> Vec<[f32; TAPS]> {
+    let center = (TAPS / 2 - 1) as f64;
+    (0..=PHASES).map(|phase| {
+        let fraction = phase as f64 / PHASES as f64;
+        let mut taps = [0f64; TAPS];
+        for (k, tap) in taps.iter_mut().enumerate() {
+            let x = k as f64 - center - fraction;
+            let sinc = if x == 0.0 { 1.0 } else { (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x) };
+            // Blackman window spanning the whole kernel.
+            let w = (x + TAPS as f64 / 2.0) / TAPS as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+            *tap = sinc * window.max(0.0);
+        }
+        // Normalize so that a step always settles exactly at its new level.
+        let sum: f64 = taps.iter().sum();
+        let mut normalized = [0f32; TAPS];
+        for (n, tap) in normalized.iter_mut().zip(taps.iter()) {
+            *n = (tap / sum) as f32;
+        }
+        normalized
+    }).collect()
+}
+
+impl BlipBuffer {
+    pub fn new (clock_rate: u32, sample_rate: u32) -> BlipBuffer {
+        BlipBuffer {
+            samples_per_clock: sample_rate as f64 / clock_rate as f64,
+            offset: 0.0,
+            deltas: Vec::new(),
+            integrator: 0.0,
+            amplitude: 0.0,
+            kernel: build_kernel()
+        }
+    }
+
+    /// Sets the input level from `clock` (relative to the current frame) on.
+    pub fn set_amplitude (&mut self, clock: u64, amplitude: f32) {
+        let delta = amplitude - self.amplitude;
+        if delta == 0.0 {
+            return;
+        }
+        self.amplitude = amplitude;
+        let position = self.offset + clock as f64 * self.samples_per_clock;
+        let index = position as usize;
+        let phase = ((position - index as f64) * PHASES as f64).round() as usize;
+        if self.deltas.len() < index + TAPS {
+            self.deltas.resize(index + TAPS, 0.0);
+        }
+        for (k, tap) in self.kernel[phase].iter().enumerate() {
+            self.deltas[index + k] += delta * tap;
+        }
+    }
+
+    /// Ends the current frame after `clocks` clocks and appends every output
+    /// sample that can no longer change to `out`.
+    pub fn end_frame (&mut self, clocks: u64, out: &mut Vec<f32>) {
+        let end = self.offset + clocks as f64 * self.samples_per_clock;
+        let count = end as usize;
+        if self.deltas.len() < count + TAPS {
+            self.deltas.resize(count + TAPS, 0.0);
+        }
+        for delta in self.deltas.drain(..count) {
+            self.integrator += delta;
+            out.push(self.integrator);
+        }
+        self.offset = end - count as f64;
+    }
+}
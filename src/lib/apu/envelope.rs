@@ -0,0 +1,61 @@
+/// Volume envelope shared by the square and noise channels (NRx2).
+pub struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+    volume: u8
+}
+
+impl Envelope {
+    pub fn new () -> Envelope {
+        Envelope {
+            initial: 0,
+            increase: false,
+            period: 0,
+            timer: 0,
+            volume: 0
+        }
+    }
+
+    pub fn rb (&self) -> u8 {
+        (self.initial << 4) | ((self.increase as u8) << 3) | self.period
+    }
+
+    pub fn wb (&mut self, value: u8) {
+        self.initial = value >> 4;
+        self.increase = value & 0x08 > 0;
+        self.period = value & 0x07;
+    }
+
+    /// The channel DAC is powered as long as any of the upper 5 bits of NRx2 is set.
+    pub fn is_dac_enabled (&self) -> bool {
+        self.rb() & 0xF8 > 0
+    }
+
+    pub fn get_volume (&self) -> u8 {
+        self.volume
+    }
+
+    pub fn trigger (&mut self) {
+        self.timer = self.period;
+        self.volume = self.initial;
+    }
+
+    pub fn clock (&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+/// The capacitor placed between the mixer and the output on the real
+/// hardware, which removes the DC offset of the channel DACs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HighPassFilter {
+    Off,
+    Dmg,
+    Cgb
+}
+
+impl HighPassFilter {
+    pub fn from_name (name: &str) -> Option<HighPassFilter> {
+        match name {
+            "off" => Some(HighPassFilter::Off),
+            "dmg" => Some(HighPassFilter::Dmg),
+            "cgb" => Some(HighPassFilter::Cgb),
+            _ => None
+        }
+    }
+}
+
+pub struct HighPass {
+    // Fraction of the charge kept from one output sample to the next.
+    charge_factor: f32,
+    capacitor: f32
+}
+
+impl HighPass {
+    pub fn new (filter: HighPassFilter, clock_rate: u32, sample_rate: u32) -> HighPass {
+        let per_clock: f64 = match filter {
+            HighPassFilter::Off => 1.0,
+            HighPassFilter::Dmg => 0.999958,
+            HighPassFilter::Cgb => 0.998943
+        };
+        HighPass {
+            charge_factor: per_clock.powf(clock_rate as f64 / sample_rate as f64) as f32,
+            capacitor: 0.0
+        }
+    }
+
+    pub fn apply (&mut self, input: f32) -> f32 {
+        if self.charge_factor >= 1.0 {
+            return input;
+        }
+        let output = input - self.capacitor;
+        self.capacitor = input - output * self.charge_factor;
+        output
+    }
+}
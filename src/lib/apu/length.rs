@@ -0,0 +1,63 @@
+/// Length counter shared by all channels. It counts down from `max` (64, or
+/// 256 for the wave channel) and disables its channel when it reaches zero.
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool
+}
+
+impl LengthCounter {
+    pub fn new (max: u16) -> LengthCounter {
+        LengthCounter {
+            max,
+            counter: 0,
+            enabled: false
+        }
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    /// Powering the APU off clears NRx4, but the counter itself is kept.
+    pub fn power_off (&mut self) {
+        self.enabled = false;
+    }
+
+    /// Loads the counter from the length bits of NRx1.
+    pub fn load (&mut self, length: u8) {
+        self.counter = self.max - length as u16;
+    }
+
+    /// Handles the length enable bit of an NRx4 write. `length_clocked` tells
+    /// whether the last frame sequencer step clocked the length counters:
+    /// enabling the counter during the half that does not clock it gives it an
+    /// extra clock. Returns false if that extra clock made the counter expire.
+    pub fn set_enabled (&mut self, enabled: bool, length_clocked: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enabled;
+        if length_clocked && !was_enabled && enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter > 0;
+        }
+        true
+    }
+
+    pub fn trigger (&mut self, length_clocked: bool) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && length_clocked {
+                self.counter -= 1;
+            }
+        }
+    }
+
+    /// Clocks the counter, returning false when the channel must be disabled.
+    pub fn clock (&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter > 0;
+        }
+        true
+    }
+}
@@ -0,0 +1,325 @@
+pub mod blip;
+pub mod envelope;
+pub mod filter;
+pub mod length;
+pub mod noise;
+pub mod square;
+pub mod wave;
+use blip::BlipBuffer;
+use filter::{HighPass, HighPassFilter};
+use noise::NoiseChannel;
+use square::SquareChannel;
+use wave::WaveChannel;
+use super::vgm::VgmLog;
+
+// Frame sequencer steps (clocked at 512 Hz) that clock each unit.
+const LENGTH_STEPS: [bool; 8] = [true, false, true, false, true, false, true, false];
+const SWEEP_STEPS: [bool; 8] = [false, false, true, false, false, false, true, false];
+const ENVELOPE_STEP: u8 = 7;
+
+const CLOCKS_PER_CYCLE: u32 = 4;
+pub const CLOCK_RATE: u32 = 4194304;
+
+const NR52_POWER: u8 = 0b10000000;
+
+pub struct APU {
+    square1: SquareChannel,
+    square2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    frame_sequencer_step: u8,
+    powered: bool,
+    // CGB hardware, whichever mode the game runs in.
+    cgb: bool,
+    nr50: u8,
+    nr51: u8,
+    output: Option<AudioOutput>,
+    vgm_log: Option<VgmLog>
+}
+
+/// Resampling state, only allocated once a sample rate has been chosen.
+struct AudioOutput {
+    sample_rate: u32,
+    // Clocks elapsed since the samples were last taken.
+    clock: u64,
+    left: BlipBuffer,
+    right: BlipBuffer,
+    left_high_pass: HighPass,
+    right_high_pass: HighPass,
+    // Optional mono capture of every channel before panning and volume.
+    channels: Vec<ChannelCapture>
+}
+
+struct ChannelCapture {
+    blip: BlipBuffer,
+    high_pass: HighPass,
+    samples: Vec<f32>
+}
+
+impl APU {
+    pub fn new () -> APU {
+        debug!("Creating new APU...");
+        APU {
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            frame_sequencer_step: 0,
+            powered: true,
+            cgb: false,
+            nr50: 0,
+            nr51: 0,
+            output: None,
+            vgm_log: None
+        }
+    }
+
+    /// Selects the CGB behavior of the APU: length counters are cleared on
+    /// power-off and cannot be written while off, and wave RAM can be
+    /// accessed while the wave channel plays.
+    pub fn set_cgb (&mut self, cgb: bool) {
+        self.cgb = cgb;
+        self.wave.set_cgb(cgb);
+    }
+
+    /// Starts producing stereo samples at `sample_rate` Hz, filtered with the
+    /// given high-pass filter.
+    pub fn set_sample_rate (&mut self, sample_rate: u32, filter: HighPassFilter) {
+        self.output = Some(AudioOutput {
+            sample_rate,
+            clock: 0,
+            left: BlipBuffer::new(CLOCK_RATE, sample_rate),
+            right: BlipBuffer::new(CLOCK_RATE, sample_rate),
+            left_high_pass: HighPass::new(filter, CLOCK_RATE, sample_rate),
+            right_high_pass: HighPass::new(filter, CLOCK_RATE, sample_rate),
+            channels: Vec::new()
+        });
+    }
+
+    /// Also resamples each of the four channels on its own, so that they can
+    /// be inspected in isolation. Requires a sample rate to be set.
+    pub fn enable_channel_capture (&mut self, filter: HighPassFilter) {
+        if let Some(output) = &mut self.output {
+            let sample_rate = output.sample_rate;
+            output.channels = (0..4).map(|_| ChannelCapture {
+                blip: BlipBuffer::new(CLOCK_RATE, sample_rate),
+                high_pass: HighPass::new(filter, CLOCK_RATE, sample_rate),
+                samples: Vec::new()
+            }).collect();
+        }
+    }
+
+    /// Returns the interleaved stereo samples (left, right) in the range
+    /// [-1, 1] produced since the last call.
+    pub fn take_samples (&mut self) -> Vec<f32> {
+        let output = match &mut self.output {
+            Some(output) => output,
+            None => return Vec::new()
+        };
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        output.left.end_frame(output.clock, &mut left);
+        output.right.end_frame(output.clock, &mut right);
+        for channel in output.channels.iter_mut() {
+            let mut samples = Vec::new();
+            channel.blip.end_frame(output.clock, &mut samples);
+            for sample in samples {
+                channel.samples.push(channel.high_pass.apply(sample));
+            }
+        }
+        output.clock = 0;
+
+        let mut samples = Vec::with_capacity(left.len() * 2);
+        for (l, r) in left.iter().zip(right.iter()) {
+            samples.push(output.left_high_pass.apply(*l));
+            samples.push(output.right_high_pass.apply(*r));
+        }
+        samples
+    }
+
+    pub fn rb (&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10..=0xFF14 => self.square1.rb(addr - 0xFF10),
+            0xFF15..=0xFF19 => self.square2.rb(addr - 0xFF15),
+            0xFF1A..=0xFF1E => self.wave.rb(addr - 0xFF1A),
+            0xFF1F..=0xFF23 => self.noise.rb(addr - 0xFF1F),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => {
+                0x70 | ((self.powered as u8) << 7)
+                    | (self.square1.is_enabled() as u8)
+                    | ((self.square2.is_enabled() as u8) << 1)
+                    | ((self.wave.is_enabled() as u8) << 2)
+                    | ((self.noise.is_enabled() as u8) << 3)
+            }
+            0xFF30..=0xFF3F => self.wave.read_wave_ram((addr - 0xFF30) as usize),
+            _ => 0xFF
+        }
+    }
+
+    pub fn wb (&mut self, addr: u16, value: u8) {
+        if let Some(log) = &mut self.vgm_log {
+            log.write(addr, value);
+        }
+        if addr == 0xFF26 {
+            self.set_power(value & NR52_POWER > 0);
+            return;
+        }
+        let length_clocked = self.last_step_clocked_length();
+        if !self.powered {
+            // While powered off only wave RAM and, on DMG, the length
+            // counters can be written.
+            match addr {
+                0xFF11 | 0xFF16 | 0xFF20 if !self.cgb => {
+                    self.write_channel(addr, value & 0x3F, length_clocked)
+                }
+                0xFF1B if !self.cgb => self.write_channel(addr, value, length_clocked),
+                0xFF30..=0xFF3F => self.write_channel(addr, value, length_clocked),
+                _ => {}
+            }
+            return;
+        }
+        self.write_channel(addr, value, length_clocked);
+    }
+
+    fn write_channel (&mut self, addr: u16, value: u8, length_clocked: bool) {
+        match addr {
+            0xFF10..=0xFF14 => self.square1.wb(addr - 0xFF10, value, length_clocked),
+            0xFF15..=0xFF19 => self.square2.wb(addr - 0xFF15, value, length_clocked),
+            0xFF1A..=0xFF1E => self.wave.wb(addr - 0xFF1A, value, length_clocked),
+            0xFF1F..=0xFF23 => self.noise.wb(addr - 0xFF1F, value, length_clocked),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            0xFF30..=0xFF3F => self.wave.write_wave_ram((addr - 0xFF30) as usize, value),
+            _ => {}
+        }
+    }
+
+    fn set_power (&mut self, powered: bool) {
+        if self.powered && !powered {
+            let keep_length = !self.cgb;
+            self.square1.power_off(keep_length);
+            self.square2.power_off(keep_length);
+            self.wave.power_off(keep_length);
+            self.noise.power_off(keep_length);
+            self.nr50 = 0;
+            self.nr51 = 0;
+        } else if !self.powered && powered {
+            self.frame_sequencer_step = 0;
+        }
+        self.powered = powered;
+    }
+
+    fn last_step_clocked_length (&self) -> bool {
+        LENGTH_STEPS[((self.frame_sequencer_step + 7) % 8) as usize]
+    }
+
+    /// Called on every falling edge of DIV bit 4 (512 Hz).
+    pub fn clock_frame_sequencer (&mut self) {
+        if !self.powered {
+            return;
+        }
+        let step = self.frame_sequencer_step;
+        if LENGTH_STEPS[step as usize] {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if SWEEP_STEPS[step as usize] {
+            self.square1.clock_sweep();
+        }
+        if step == ENVELOPE_STEP {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_sequencer_step = (step + 1) % 8;
+    }
+
+    /// Starts logging every register write from now on.
+    pub fn start_vgm_log (&mut self) {
+        self.vgm_log = Some(VgmLog::new());
+    }
+
+    pub fn take_vgm_log (&mut self) -> Option<VgmLog> {
+        self.vgm_log.take()
+    }
+
+    /// Returns the mono samples of each channel gathered by the last call to
+    /// `take_samples`, or nothing if channel capture is disabled.
+    pub fn take_channel_samples (&mut self) -> Vec<Vec<f32>> {
+        match &mut self.output {
+            Some(output) => output.channels.iter_mut()
+                .map(|channel| std::mem::take(&mut channel.samples))
+                .collect(),
+            None => Vec::new()
+        }
+    }
+
+    /// Advances the channel frequency timers by the given machine cycles.
+    pub fn step (&mut self, cycles: u32) {
+        let clocks = cycles * CLOCKS_PER_CYCLE;
+        self.square1.step(clocks);
+        self.square2.step(clocks);
+        self.wave.step(clocks);
+        self.noise.step(clocks);
+        if let Some(log) = &mut self.vgm_log {
+            log.advance(clocks);
+        }
+
+        if self.output.is_some() {
+            let (left, right) = self.mix();
+            let levels = self.channel_levels();
+            if let Some(output) = &mut self.output {
+                // Levels are sampled once per instruction, which is far below
+                // the resolution of the output sample rate.
+                output.clock += clocks as u64;
+                output.left.set_amplitude(output.clock, left);
+                output.right.set_amplitude(output.clock, right);
+                for (channel, level) in output.channels.iter_mut().zip(levels.iter()) {
+                    channel.blip.set_amplitude(output.clock, *level);
+                }
+            }
+        }
+    }
+
+    /// DAC output of each channel in the range [-1, 1], or 0 with the DAC off.
+    fn channel_levels (&self) -> [f32; 4] {
+        let dac = |enabled: bool, digital: u8| {
+            if enabled { 1.0 - digital as f32 / 7.5 } else { 0.0 }
+        };
+        [
+            dac(self.square1.is_dac_enabled(), self.square1.output()),
+            dac(self.square2.is_dac_enabled(), self.square2.output()),
+            dac(self.wave.is_dac_enabled(), self.wave.output()),
+            dac(self.noise.is_dac_enabled(), self.noise.output())
+        ]
+    }
+
+    /// Mixes the channels to stereo following NR51 (panning) and NR50
+    /// (master volume). Both sides are in the range [-1, 1].
+    fn mix (&self) -> (f32, f32) {
+        if !self.powered {
+            return (0.0, 0.0);
+        }
+        let levels = self.channel_levels();
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (channel, level) in levels.iter().enumerate() {
+            if self.nr51 & (0x10 << channel) > 0 {
+                left += level;
+            }
+            if self.nr51 & (0x01 << channel) > 0 {
+                right += level;
+            }
+        }
+        let left_volume = (((self.nr50 >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((self.nr50 & 0x07) + 1) as f32 / 8.0;
+        (left * left_volume / 4.0, right * right_volume / 4.0)
+    }
+}
+
+#[cfg(test)]
+#[path = "./apu_test.rs"]
+mod apu_test;
@@ -0,0 +1,145 @@
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+
+const LENGTH_MAX: u16 = 64;
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Noise channel (NR41-NR44) driven by a linear feedback shift register.
+pub struct NoiseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    clock_shift: u8,
+    width_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+}
+
+impl NoiseChannel {
+    pub fn new () -> NoiseChannel {
+        NoiseChannel {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(LENGTH_MAX),
+            envelope: Envelope::new(),
+            clock_shift: 0,
+            width_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_dac_enabled (&self) -> bool {
+        self.dac_enabled
+    }
+
+    /// Clears every register. DMG keeps the length counter, CGB clears it
+    /// too.
+    pub fn power_off (&mut self, keep_length: bool) {
+        if !keep_length {
+            *self = NoiseChannel::new();
+            return;
+        }
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(LENGTH_MAX));
+        length.power_off();
+        *self = NoiseChannel { length, ..NoiseChannel::new() };
+    }
+
+    /// Reads register `reg` (0-4, NR40-NR44, NR40 does not exist).
+    pub fn rb (&self, reg: u16) -> u8 {
+        match reg {
+            2 => self.envelope.rb(),
+            3 => (self.clock_shift << 4) | ((self.width_mode as u8) << 3) | self.divisor_code,
+            4 => 0xBF | ((self.length.is_enabled() as u8) << 6),
+            _ => 0xFF
+        }
+    }
+
+    pub fn wb (&mut self, reg: u16, value: u8, length_clocked: bool) {
+        match reg {
+            1 => self.length.load(value & 0x3F),
+            2 => {
+                self.envelope.wb(value);
+                self.dac_enabled = self.envelope.is_dac_enabled();
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.clock_shift = value >> 4;
+                self.width_mode = value & 0x08 > 0;
+                self.divisor_code = value & 0x07;
+            }
+            4 => {
+                if !self.length.set_enabled(value & 0x40 > 0, length_clocked) && value & 0x80 == 0 {
+                    self.enabled = false;
+                }
+                if value & 0x80 > 0 {
+                    self.enabled = self.dac_enabled;
+                    self.length.trigger(length_clocked);
+                    self.envelope.trigger();
+                    self.timer = self.period();
+                    self.lfsr = 0x7FFF;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn period (&self) -> u32 {
+        DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+
+    pub fn clock_length (&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope (&mut self) {
+        self.envelope.clock();
+    }
+
+    fn clock_lfsr (&mut self) {
+        let feedback = (self.lfsr & 0x01) ^ ((self.lfsr >> 1) & 0x01);
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.width_mode {
+            // 7-bit mode also feeds bit 6, shortening the sequence to 127 steps.
+            self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+        }
+    }
+
+    pub fn step (&mut self, clocks: u32) {
+        // Shifts of 14 and 15 receive no clocks at all.
+        if self.clock_shift >= 14 {
+            return;
+        }
+        let mut clocks = clocks;
+        while clocks > 0 {
+            if self.timer == 0 {
+                self.timer = self.period();
+            }
+            let elapsed = std::cmp::min(clocks, self.timer);
+            self.timer -= elapsed;
+            clocks -= elapsed;
+            if self.timer == 0 {
+                self.clock_lfsr();
+            }
+        }
+    }
+
+    /// Current digital output level (0-15).
+    pub fn output (&self) -> u8 {
+        if !self.enabled || self.lfsr & 0x01 > 0 {
+            return 0;
+        }
+        self.envelope.get_volume()
+    }
+}
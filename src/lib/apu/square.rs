@@ -0,0 +1,223 @@
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+const LENGTH_MAX: u16 = 64;
+const FREQUENCY_MAX: u16 = 2047;
+
+/// Pulse channel (NR10-NR14 / NR21-NR24). Only channel 1 has a sweep unit.
+pub struct SquareChannel {
+    has_sweep: bool,
+    enabled: bool,
+    dac_enabled: bool,
+
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    sweep_negate_used: bool,
+    shadow_frequency: u16,
+
+    duty: u8,
+    duty_position: u8,
+    length: LengthCounter,
+    envelope: Envelope,
+
+    frequency: u16,
+    timer: u32,
+}
+
+impl SquareChannel {
+    pub fn new (has_sweep: bool) -> SquareChannel {
+        SquareChannel {
+            has_sweep,
+            enabled: false,
+            dac_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_timer: 0,
+            sweep_enabled: false,
+            sweep_negate_used: false,
+            shadow_frequency: 0,
+            duty: 0,
+            duty_position: 0,
+            length: LengthCounter::new(LENGTH_MAX),
+            envelope: Envelope::new(),
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_dac_enabled (&self) -> bool {
+        self.dac_enabled
+    }
+
+    /// Clears every register. DMG keeps the length counter, CGB clears it
+    /// too.
+    pub fn power_off (&mut self, keep_length: bool) {
+        if !keep_length {
+            *self = SquareChannel::new(self.has_sweep);
+            return;
+        }
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(LENGTH_MAX));
+        length.power_off();
+        *self = SquareChannel { length, ..SquareChannel::new(self.has_sweep) };
+    }
+
+    /// Reads register `reg` (0-4, NRx0-NRx4). Write-only bits read as 1.
+    pub fn rb (&self, reg: u16) -> u8 {
+        match reg {
+            0 if self.has_sweep => {
+                0x80 | (self.sweep_period << 4) | ((self.sweep_negate as u8) << 3) | self.sweep_shift
+            }
+            1 => 0x3F | (self.duty << 6),
+            2 => self.envelope.rb(),
+            4 => 0xBF | ((self.length.is_enabled() as u8) << 6),
+            _ => 0xFF
+        }
+    }
+
+    /// Writes register `reg` (0-4, NRx0-NRx4). `length_clocked` tells whether
+    /// the last frame sequencer step clocked the length counters, which
+    /// affects how enabling the length counter behaves.
+    pub fn wb (&mut self, reg: u16, value: u8, length_clocked: bool) {
+        match reg {
+            0 if self.has_sweep => {
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 > 0;
+                self.sweep_shift = value & 0x07;
+                // Leaving negate mode after a negated calculation disables the channel.
+                if !self.sweep_negate && self.sweep_negate_used {
+                    self.enabled = false;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            }
+            2 => {
+                self.envelope.wb(value);
+                self.dac_enabled = self.envelope.is_dac_enabled();
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+                if !self.length.set_enabled(value & 0x40 > 0, length_clocked) && value & 0x80 == 0 {
+                    self.enabled = false;
+                }
+                if value & 0x80 > 0 {
+                    self.trigger(length_clocked);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn trigger (&mut self, length_clocked: bool) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger(length_clocked);
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if self.has_sweep {
+            self.shadow_frequency = self.frequency;
+            self.sweep_timer = if self.sweep_period > 0 { self.sweep_period } else { 8 };
+            self.sweep_enabled = self.sweep_period > 0 || self.sweep_shift > 0;
+            self.sweep_negate_used = false;
+            if self.sweep_shift > 0 {
+                self.calculate_sweep();
+            }
+        }
+    }
+
+    // Timer period in clocks (4 per machine cycle).
+    fn period (&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    /// Computes the next sweep frequency, disabling the channel on overflow.
+    fn calculate_sweep (&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        let frequency = if self.sweep_negate {
+            self.sweep_negate_used = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+        if frequency > FREQUENCY_MAX {
+            self.enabled = false;
+        }
+        frequency
+    }
+
+    pub fn clock_sweep (&mut self) {
+        if !self.has_sweep {
+            return;
+        }
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+        if self.sweep_timer > 0 {
+            return;
+        }
+        self.sweep_timer = if self.sweep_period > 0 { self.sweep_period } else { 8 };
+        if self.sweep_enabled && self.sweep_period > 0 {
+            let frequency = self.calculate_sweep();
+            if frequency <= FREQUENCY_MAX && self.sweep_shift > 0 {
+                self.frequency = frequency;
+                self.shadow_frequency = frequency;
+                // The new frequency is checked for overflow again but not applied.
+                self.calculate_sweep();
+            }
+        }
+    }
+
+    pub fn clock_length (&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope (&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Advances the frequency timer by the given amount of clocks.
+    pub fn step (&mut self, clocks: u32) {
+        let mut clocks = clocks;
+        while clocks > 0 {
+            if self.timer == 0 {
+                self.timer = self.period();
+            }
+            let elapsed = std::cmp::min(clocks, self.timer);
+            self.timer -= elapsed;
+            clocks -= elapsed;
+            if self.timer == 0 {
+                self.duty_position = (self.duty_position + 1) % 8;
+            }
+        }
+    }
+
+    /// Current digital output level (0-15).
+    pub fn output (&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_PATTERNS[self.duty as usize][self.duty_position as usize] * self.envelope.get_volume()
+    }
+}
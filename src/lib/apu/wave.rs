@@ -0,0 +1,176 @@
+use super::length::LengthCounter;
+
+const LENGTH_MAX: u16 = 256;
+const WAVE_RAM_SIZE: usize = 16;
+// Right shift applied to the 4-bit samples for each NR32 volume code.
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+/// Wave channel (NR30-NR34) playing 32 4-bit samples from wave RAM.
+pub struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    volume_code: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    sample_buffer: u8,
+    // Clocks elapsed since the channel last fetched a byte from wave RAM.
+    clocks_since_fetch: u32,
+    wave_ram: [u8; WAVE_RAM_SIZE],
+    // CGB hardware lets the CPU access wave RAM at any time while playing.
+    cgb: bool,
+}
+
+impl WaveChannel {
+    pub fn new () -> WaveChannel {
+        WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(LENGTH_MAX),
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            clocks_since_fetch: u32::MAX,
+            wave_ram: [0; WAVE_RAM_SIZE],
+            cgb: false,
+        }
+    }
+
+    pub fn is_enabled (&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_dac_enabled (&self) -> bool {
+        self.dac_enabled
+    }
+
+    pub fn set_cgb (&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    /// Clears every register, keeping wave RAM. DMG keeps the length
+    /// counter, CGB clears it too.
+    pub fn power_off (&mut self, keep_length: bool) {
+        if !keep_length {
+            *self = WaveChannel { wave_ram: self.wave_ram, cgb: self.cgb, ..WaveChannel::new() };
+            return;
+        }
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(LENGTH_MAX));
+        length.power_off();
+        *self = WaveChannel { length, wave_ram: self.wave_ram, cgb: self.cgb, ..WaveChannel::new() };
+    }
+
+    /// Reads register `reg` (0-4, NR30-NR34). Write-only bits read as 1.
+    pub fn rb (&self, reg: u16) -> u8 {
+        match reg {
+            0 => 0x7F | ((self.dac_enabled as u8) << 7),
+            2 => 0x9F | (self.volume_code << 5),
+            4 => 0xBF | ((self.length.is_enabled() as u8) << 6),
+            _ => 0xFF
+        }
+    }
+
+    pub fn wb (&mut self, reg: u16, value: u8, length_clocked: bool) {
+        match reg {
+            0 => {
+                self.dac_enabled = value & 0x80 > 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.volume_code = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+                if !self.length.set_enabled(value & 0x40 > 0, length_clocked) && value & 0x80 == 0 {
+                    self.enabled = false;
+                }
+                if value & 0x80 > 0 {
+                    self.enabled = self.dac_enabled;
+                    self.length.trigger(length_clocked);
+                    self.position = 0;
+                    // The first sample is fetched after a short delay.
+                    self.timer = self.period() + 6;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// While the channel is playing, wave RAM accesses go to the byte the
+    /// channel is reading, and only succeed right after the channel fetched
+    /// it on DMG, at any time on CGB. Otherwise reads return 0xFF and writes
+    /// are lost.
+    fn playing_ram_index (&self) -> Option<usize> {
+        if self.cgb || self.clocks_since_fetch < 2 {
+            Some((self.position / 2) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn read_wave_ram (&self, index: usize) -> u8 {
+        if !self.enabled {
+            return self.wave_ram[index];
+        }
+        match self.playing_ram_index() {
+            Some(current) => self.wave_ram[current],
+            None => 0xFF
+        }
+    }
+
+    pub fn write_wave_ram (&mut self, index: usize, value: u8) {
+        if !self.enabled {
+            self.wave_ram[index] = value;
+            return;
+        }
+        if let Some(current) = self.playing_ram_index() {
+            self.wave_ram[current] = value;
+        }
+    }
+
+    fn period (&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub fn clock_length (&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn step (&mut self, clocks: u32) {
+        let mut clocks = clocks;
+        while clocks > 0 {
+            if self.timer == 0 {
+                self.timer = self.period();
+            }
+            let elapsed = std::cmp::min(clocks, self.timer);
+            self.timer -= elapsed;
+            clocks -= elapsed;
+            self.clocks_since_fetch = self.clocks_since_fetch.saturating_add(elapsed);
+            if self.timer == 0 && self.enabled {
+                self.position = (self.position + 1) % 32;
+                self.sample_buffer = self.wave_ram[(self.position / 2) as usize];
+                self.clocks_since_fetch = clocks;
+            }
+        }
+    }
+
+    /// Current digital output level (0-15).
+    pub fn output (&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let sample = if self.position.is_multiple_of(2) {
+            self.sample_buffer >> 4
+        } else {
+            self.sample_buffer & 0x0F
+        };
+        sample >> VOLUME_SHIFTS[self.volume_code as usize]
+    }
+}
@@ -0,0 +1,147 @@
+//! Colors the CGB boot ROM gives to DMG games: games from Nintendo are
+//! recognized by the checksum of their title, the rest get a default
+//! palette.
+
+const ADDR_TITLE: usize = 0x0134;
+const TITLE_SIZE: usize = 16;
+const ADDR_NEW_LICENSEE: usize = 0x0144;
+const ADDR_OLD_LICENSEE: usize = 0x014B;
+const LICENSEE_NINTENDO: u8 = 0x01;
+const OLD_LICENSEE_NEW: u8 = 0x33;
+
+// Title checksums known to the boot ROM. Checksums from FIRST_AMBIGUOUS on
+// are shared by several games, which are told apart by the fourth letter of
+// their title.
+const FIRST_AMBIGUOUS: usize = 65;
+const TITLE_CHECKSUMS: [u8; 79] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4
+];
+// Fourth title letters of the games sharing a checksum, in rows of one
+// letter per ambiguous checksum.
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+const AMBIGUOUS_CHECKSUMS: usize = TITLE_CHECKSUMS.len() - FIRST_AMBIGUOUS;
+
+// Palette combination of every recognized game, indexed like the
+// checksums, followed by one entry per fourth letter.
+// TODO: the checksums and fourth letters match the titles of the games they
+// stand for, but only the default and Pokemon combinations have been
+// checked; compare the rest against a boot ROM dump.
+const GAME_COMBINATIONS: [u8; FIRST_AMBIGUOUS + FOURTH_LETTERS.len()] = [
+    0, 4, 6, 9, 17, 16, 7, 19, 5, 6, 17, 1, 6, 18, 17, 15,
+    1, 1, 16, 18, 2, 1, 13, 15, 2, 17, 7, 1, 3, 1, 4, 9,
+    17, 17, 10, 9, 9, 19, 5, 6, 1, 4, 1, 19, 17, 18, 1, 17,
+    6, 5, 11, 1, 18, 11, 8, 11, 3, 16, 7, 1, 8, 8, 1, 16,
+    18,
+    1, 12, 17, 11, 9, 17, 1, 14, 18, 1, 18, 7, 16, 1,
+    17, 1, 11, 3, 17, 1, 17, 2, 1, 11, 17, 17, 19, 1,
+    16
+];
+
+// BG, OBJ0 and OBJ1 palettes, as indices into PALETTES.
+const COMBINATIONS: [[usize; 3]; 20] = [
+    [10, 1, 1],
+    [0, 0, 0],
+    [1, 1, 1],
+    [2, 2, 2],
+    [3, 1, 1],
+    [4, 1, 0],
+    [5, 5, 5],
+    [6, 6, 6],
+    [7, 7, 7],
+    [8, 3, 9],
+    [11, 11, 11],
+    [12, 12, 12],
+    [13, 14, 15],
+    [1, 9, 3],
+    [3, 1, 9],
+    [9, 1, 3],
+    [7, 7, 15],
+    [9, 0, 1],
+    [0, 1, 3],
+    [6, 1, 3]
+];
+
+// Colors as 0xRRGGBB, from lightest to darkest shade.
+const PALETTES: [[u32; 4]; 16] = [
+    [0xFFFFFF, 0xFFAD63, 0x843100, 0x000000],
+    [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000],
+    [0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108],
+    [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000],
+    [0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000],
+    [0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000],
+    [0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000],
+    [0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000],
+    [0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000],
+    [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000],
+    [0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000],
+    [0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000],
+    [0x000000, 0x008484, 0xFFDE00, 0xFFFFFF],
+    [0xFFFFFF, 0xADAD84, 0x42737B, 0x000000],
+    [0xFFFFFF, 0xFF7300, 0x944200, 0x000000],
+    [0xFFFFFF, 0x5ABDFF, 0xFF0000, 0x0000FF]
+];
+
+/// BG, OBJ0 and OBJ1 palettes as RGB555 colors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompatibilityPalettes {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4]
+}
+
+fn to_rgb555 (palette: [u32; 4]) -> [u16; 4] {
+    palette.map(|rgb| {
+        let channel = |shift: u32| ((rgb >> shift) & 0xFF) as u16 >> 3;
+        channel(16) | channel(8) << 5 | channel(0) << 10
+    })
+}
+
+/// Sum of the bytes of the 16 byte title area of the header.
+pub fn title_checksum (rom: &[u8]) -> u8 {
+    rom[ADDR_TITLE..ADDR_TITLE + TITLE_SIZE].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+// Index into GAME_COMBINATIONS, if the game is one the boot ROM knows.
+fn game_index (rom: &[u8]) -> Option<usize> {
+    let nintendo = match rom[ADDR_OLD_LICENSEE] {
+        LICENSEE_NINTENDO => true,
+        OLD_LICENSEE_NEW => &rom[ADDR_NEW_LICENSEE..ADDR_NEW_LICENSEE + 2] == b"01",
+        _ => false
+    };
+    if !nintendo {
+        return None;
+    }
+    let checksum = title_checksum(rom);
+    let index = TITLE_CHECKSUMS.iter().position(|&known| known == checksum)?;
+    if index < FIRST_AMBIGUOUS {
+        return Some(index);
+    }
+    let fourth_letter = rom[ADDR_TITLE + 3];
+    (index - FIRST_AMBIGUOUS..FOURTH_LETTERS.len())
+        .step_by(AMBIGUOUS_CHECKSUMS)
+        .find(|&letter| FOURTH_LETTERS[letter] == fourth_letter)
+        .map(|letter| FIRST_AMBIGUOUS + letter)
+}
+
+/// Looks up the palettes for the cartridge `rom`, which must hold at least
+/// the header.
+pub fn palettes_for (rom: &[u8]) -> CompatibilityPalettes {
+    let combination = match game_index(rom) {
+        Some(index) => GAME_COMBINATIONS[index] as usize,
+        None => 0
+    };
+    let [bg, obj0, obj1] = COMBINATIONS[combination];
+    CompatibilityPalettes {
+        bg: to_rgb555(PALETTES[bg]),
+        obj0: to_rgb555(PALETTES[obj0]),
+        obj1: to_rgb555(PALETTES[obj1])
+    }
+}
+
+#[cfg(test)]
+#[path = "./colorization_test.rs"]
+mod colorization_test;
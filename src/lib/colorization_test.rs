@@ -0,0 +1,62 @@
+use super::*;
+
+fn header(title: &[u8], old_licensee: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x150];
+    rom[ADDR_TITLE..ADDR_TITLE + title.len()].copy_from_slice(title);
+    rom[ADDR_OLD_LICENSEE] = old_licensee;
+    rom
+}
+
+#[test]
+fn test_title_checksum() {
+    assert_eq!(title_checksum(&header(b"POKEMON RED", LICENSEE_NINTENDO)), 0x14);
+    assert_eq!(title_checksum(&header(b"SUPER MARIOLAND", LICENSEE_NINTENDO)), 0x46);
+}
+
+#[test]
+fn test_known_game_palettes() {
+    let palettes = palettes_for(&header(b"POKEMON RED", LICENSEE_NINTENDO));
+    assert_eq!(palettes.bg, to_rgb555(PALETTES[1]));
+    assert_eq!(palettes.obj0, to_rgb555(PALETTES[9]));
+    assert_eq!(palettes.obj1, to_rgb555(PALETTES[3]));
+    // White, then 0xFF8484 as RGB555.
+    assert_eq!(palettes.bg[0], 0x7FFF);
+    assert_eq!(palettes.bg[1], 0x421F);
+}
+
+#[test]
+fn test_fourth_letter_disambiguation() {
+    // Both titles sum to 0x61, but only one has a known fourth letter.
+    let blue = palettes_for(&header(b"POKEMON BLUE", LICENSEE_NINTENDO));
+    assert_eq!(blue.bg, to_rgb555(PALETTES[3]));
+    let unknown = palettes_for(&header(b"POKMEON BLUE", LICENSEE_NINTENDO));
+    assert_eq!(title_checksum(&header(b"POKMEON BLUE", LICENSEE_NINTENDO)), 0x61);
+    assert_eq!(unknown.bg, to_rgb555(PALETTES[10]));
+}
+
+#[test]
+fn test_licensee() {
+    let default = palettes_for(&header(b"", 0x00));
+    assert_eq!(default, palettes_for(&header(b"POKEMON RED", 0x08)));
+    let mut rom = header(b"POKEMON RED", OLD_LICENSEE_NEW);
+    assert_eq!(palettes_for(&rom), default);
+    rom[ADDR_NEW_LICENSEE..ADDR_NEW_LICENSEE + 2].copy_from_slice(b"01");
+    assert_eq!(palettes_for(&rom), palettes_for(&header(b"POKEMON RED", LICENSEE_NINTENDO)));
+}
+
+#[test]
+fn test_ambiguous_checksums() {
+    let palettes = |title: &[u8]| palettes_for(&header(title, LICENSEE_NINTENDO));
+    // 0x46: the first row of fourth letters.
+    assert_eq!(palettes(b"SUPER MARIOLAND").bg, to_rgb555(PALETTES[13]));
+    assert_eq!(palettes(b"SUPER MARIOLAND").obj0, to_rgb555(PALETTES[14]));
+    // 0x61: the first and second rows.
+    assert_eq!(title_checksum(&header(b"VEGAS STAKES", LICENSEE_NINTENDO)), 0x61);
+    assert_eq!(palettes(b"VEGAS STAKES").bg, to_rgb555(PALETTES[1]));
+    assert_ne!(palettes(b"VEGAS STAKES"), palettes(b"POKEMON BLUE"));
+    // 0xB3: the second row and the third, which only this checksum has.
+    assert_eq!(title_checksum(&header(b"MOGURANYA", LICENSEE_NINTENDO)), 0xB3);
+    assert_eq!(title_checksum(&header(b"TETRIS ATTACK", LICENSEE_NINTENDO)), 0xB3);
+    assert_eq!(palettes(b"MOGURANYA").bg, to_rgb555(PALETTES[9]));
+    assert_eq!(palettes(b"TETRIS ATTACK"), palettes(b"TETRIS"));
+}
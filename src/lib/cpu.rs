@@ -1,14 +1,21 @@
 use super::cpu_registers;
-use super::gpu;
+use super::debugger;
 use super::mmu;
-use std::io::stdin;
+use super::model::Model;
+use std::hash::{Hash, Hasher};
+use std::io::{stdin, stdout};
 
 const SP_INITIAL_VALUE: u16 = 0xFFFE;
+const PC_INITIAL_VALUE: u16 = 0x0100;
+const ADDR_HEADER_CHECKSUM: u16 = 0x014D;
 
 const REG_U8_COUNT: usize = 8;
 
+// Return address pushed by `call`. No code can run there, since it is the
+// interrupt enable register.
+const CALL_RETURN_ADDR: u16 = 0xFFFF;
+
 pub struct CPU {
-    gpu: gpu::GPU,
     mmu: mmu::MMU,
     registers: cpu_registers::CPURegisters,
 }
@@ -21,7 +28,6 @@ impl CPU {
     pub fn new() -> CPU {
         debug!("Creating new CPU...");
         CPU {
-            gpu: gpu::GPU::new(),
             mmu: mmu::MMU::new(),
             registers: cpu_registers::CPURegisters::new(),
         }
@@ -33,23 +39,63 @@ impl CPU {
         cycles
     }
 
-    pub fn dump_status(&mut self) {
-        debug!("[CPU Status]:");
-        debug!("[Registers] A: {:02x}, B: {:02x}, C: {:02x}, D: {:02x}, E: {:02x}, F: {:02x}, H: {:02x}, L: {:02x}, ",
-            self.registers.get_a(),
-            self.registers.get_b(),
-            self.registers.get_c(),
-            self.registers.get_d(),
-            self.registers.get_e(),
-            self.registers.get_f(),
-            self.registers.get_h(),
-            self.registers.get_l(),
-        );
-        debug!(
-            "[Registers] SP: {:02x}, PC: {:02x}, ",
-            self.registers.get_sp(),
-            self.registers.get_pc(),
-        );
+    /// Executes one instruction and advances the rest of the system by the
+    /// cycles it took. Returns the machine cycles elapsed.
+    pub fn step(&mut self) -> u32 {
+        // Unimplemented opcodes report 0 cycles; count them as one so time
+        // keeps moving forward.
+        let mut cycles = std::cmp::max(self.exec_inst(), 1) as u32;
+        self.mmu.step(cycles);
+        // VRAM DMA and speed switches halt the CPU while the rest of the
+        // system keeps running.
+        loop {
+            let stall = self.mmu.take_stall_cycles();
+            if stall == 0 {
+                break;
+            }
+            self.mmu.step(stall);
+            cycles += stall;
+        }
+        cycles
+    }
+
+    pub fn get_mmu(&self) -> &mmu::MMU {
+        &self.mmu
+    }
+
+    pub fn get_mmu_mut(&mut self) -> &mut mmu::MMU {
+        &mut self.mmu
+    }
+
+    pub fn get_registers(&self) -> &cpu_registers::CPURegisters {
+        &self.registers
+    }
+
+    pub fn get_registers_mut(&mut self) -> &mut cpu_registers::CPURegisters {
+        &mut self.registers
+    }
+
+    /// Feeds the registers and everything visible on the bus to `hasher`,
+    /// to tell whether two runs are still in sync.
+    pub fn hash_state(&self, hasher: &mut impl Hasher) {
+        self.registers.hash(hasher);
+        self.mmu.hash_state(hasher);
+    }
+
+    /// Calls the subroutine at `addr` like a CALL instruction would and runs
+    /// it until it returns, or until `max_cycles` machine cycles elapse.
+    /// Returns the machine cycles spent.
+    pub fn call(&mut self, addr: u16, max_cycles: u32) -> u32 {
+        let sp = self.registers.get_sp().wrapping_sub(2);
+        self.mmu.wb(sp, CALL_RETURN_ADDR as u8);
+        self.mmu.wb(sp.wrapping_add(1), (CALL_RETURN_ADDR >> 8) as u8);
+        self.registers.set_sp(sp);
+        self.registers.set_pc(addr);
+        let mut cycles = 0;
+        while cycles < max_cycles && self.registers.get_pc() != CALL_RETURN_ADDR {
+            cycles += self.step();
+        }
+        cycles
     }
 
     pub fn read_rom(&mut self, rom: &Vec<u8>) {
@@ -57,23 +103,37 @@ impl CPU {
         let _ = &self.mmu.read_rom(&rom);
     }
 
-    pub fn start(&mut self) {
-        let mut input = String::new();
-        loop {
-            if true
-            /* TODO: interactive check */
-            {
-                if input.eq("d\n") {
-                    self.dump_status();
-                } else {
-                    self.exec_inst();
-                }
-                input.clear();
-                match stdin().read_line(&mut input) {
-                    Ok(read_count) => {}
-                    Err(error) => panic!("Error reading next line."),
-                }
-            }
+    /// Sets the registers and the hardware to the state the boot ROM of
+    /// `model` leaves them in, ready to run the cartridge from 0x0100.
+    pub fn set_model(&mut self, model: Model) {
+        self.mmu.set_model(model);
+        let cgb_mode = self.mmu.get_gpu().is_cgb_mode();
+        let [af, bc, de, hl] = model.boot_registers(cgb_mode, self.mmu.rb(ADDR_HEADER_CHECKSUM));
+        self.registers.set_a((af >> 8) as u8);
+        self.registers.set_f(af as u8);
+        self.registers.set_bc(bc);
+        self.registers.set_de(de);
+        self.registers.set_hl(hl);
+        self.registers.set_sp(SP_INITIAL_VALUE);
+        self.registers.set_pc(PC_INITIAL_VALUE);
+    }
+
+    /// Maps `boot_rom` over the cartridge and starts executing it from
+    /// 0x0000 with the hardware in its power on state.
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.mmu.set_boot_rom(boot_rom);
+        self.registers = cpu_registers::CPURegisters::new();
+        self.registers.set_sp(0x0000);
+        self.registers.set_pc(0x0000);
+    }
+
+    /// Runs the debugger on stdin and stdout until the user quits or stdin
+    /// ends. `on_frame` is called every time the PPU completes a frame.
+    pub fn start(&mut self, on_frame: &mut dyn FnMut(&mmu::MMU)) {
+        let mut debugger = debugger::Debugger::new(self);
+        debugger.set_interrupt(debugger::catch_interrupts());
+        if let Err(error) = debugger.repl(self, &mut stdin().lock(), &mut stdout(), on_frame) {
+            panic!("Debugger I/O failed: {}", error);
         }
     }
 
@@ -238,6 +298,13 @@ impl CPU {
                 2
             }
             // G1X
+            0x10 => {
+                // "STOP"
+                self.nextb();
+                self.debug_instr(String::from("STOP"));
+                self.mmu.stop();
+                1
+            }
             0x11 => {
                 // "LD DE,nn"
                 let value_lo = self.nextb();
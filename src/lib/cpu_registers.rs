@@ -6,9 +6,9 @@ pub const FLG_CARRY: u8 = 0b00010000;
 const SP_INITIAL_VALUE: u16 = 0xFFFE;
 const PC_INITIAL_VALUE: u16 = 0x0100;
 
+#[derive(Hash)]
 pub struct CPURegisters {
     a: u8,
-    f: u8,
     b: u8,
     c: u8,
     d: u8,
@@ -24,7 +24,6 @@ impl CPURegisters {
     pub fn new() -> CPURegisters {
         CPURegisters {
             a: 0,
-            f: 0,
             b: 0,
             c: 0,
             d: 0,
@@ -41,7 +40,7 @@ impl CPURegisters {
         self.a
     }
     pub fn get_f(&self) -> u8 {
-        self.f
+        self.flg.get_value()
     }
     pub fn get_b(&self) -> u8 {
         self.b
@@ -74,7 +73,7 @@ impl CPURegisters {
         self.a = value
     }
     pub fn set_f(&mut self, value: u8) {
-        self.f = value
+        self.flg.set_value(value)
     }
     pub fn set_b(&mut self, value: u8) {
         self.b = value
@@ -140,6 +139,7 @@ impl CPURegisters {
     }
 }
 
+#[derive(Hash)]
 pub struct CPUFlags {
     value: u8,
 }
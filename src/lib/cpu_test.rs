@@ -2098,3 +2098,39 @@ fn test_cpu_opcode_0xfa() {
     assert_eq!(cpu.registers.get_a(), value);
     assert_eq!(nops, 4);
 }
+
+#[test]
+fn test_set_model_sets_boot_registers() {
+    let mut rom = vec![0; 0x200];
+    rom[0x143] = 0x80;
+    rom[0x14D] = 0x42;
+    let mut cpu = super::CPU::new();
+    cpu.read_rom(&rom);
+    cpu.set_model(super::Model::Cgb);
+    assert_eq!(cpu.registers.get_a(), 0x11);
+    assert_eq!(cpu.registers.get_de(), 0xFF56);
+    assert_eq!(cpu.registers.get_pc(), 0x0100);
+    cpu.set_model(super::Model::Dmg);
+    assert_eq!(cpu.registers.get_a(), 0x01);
+    assert_eq!(cpu.registers.get_flg().get_value(), 0xB0);
+    assert_eq!(cpu.registers.get_hl(), 0x014D);
+}
+
+#[test]
+fn test_f_reads_flags_set_by_instructions() {
+    let mut cpu = cpu_from_data(&mut vec![0x80]);
+    cpu.registers.set_a(0xFF);
+    cpu.registers.set_b(0xFF);
+    cpu.exec_inst();
+    assert_eq!(cpu.registers.get_f(), super::cpu_registers::FLG_HCARRY | super::cpu_registers::FLG_CARRY);
+}
+
+#[test]
+fn test_set_f_sets_the_flags_instructions_read() {
+    // ADC A,B
+    let mut cpu = cpu_from_data(&mut vec![0x88]);
+    cpu.registers.set_f(0xFF);
+    assert_eq!(cpu.registers.get_f(), 0xF0);
+    cpu.exec_inst();
+    assert_eq!(cpu.registers.get_a(), 0x01);
+}
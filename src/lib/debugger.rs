@@ -0,0 +1,479 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::cpu::CPU;
+use super::disassembler;
+use super::mmu;
+use super::watchpoint::{Access, WatchHit, Watchpoint};
+
+const PROMPT: &str = "(gb) ";
+const DEFAULT_READ_SIZE: u16 = 0x40;
+const DEFAULT_DISASSEMBLE_COUNT: u16 = 8;
+const DEFAULT_STACK_DEPTH: u16 = 8;
+const BYTES_PER_LINE: u16 = 16;
+
+// Set by the SIGINT handler `catch_interrupts` installs.
+static INTERRUPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+// Interrupt flag of debuggers nothing can interrupt.
+static NEVER_INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+const HELP: &str = "Commands (addresses and values in hex, counts in decimal):
+    s, step [N]             Execute N instructions (default: 1, also on an empty line)
+    c, continue             Run until a breakpoint is hit
+    u, until <ADDR>         Run until PC reaches ADDR or a breakpoint is hit
+                            (Ctrl-C stops running and returns to the prompt)
+    b, break <ADDR>         Set a breakpoint at ADDR
+    delete <ADDR>           Remove the breakpoint at ADDR
+    breaks                  List the breakpoints
+    watch <ACCESS> <ADDR>[-<END>] [<VALUE>[/<MASK>]]
+                            Break on read, write or change of memory from ADDR to END,
+                            optionally only when the byte masked by MASK equals VALUE
+    unwatch <N>             Remove watchpoint N
+    watches                 List the watchpoints
+    r, regs                 Print the registers
+    set <REG> <VALUE>       Set A, F, B, C, D, E, H, L, AF, BC, DE, HL, SP or PC
+    x <ADDR> [N]            Print N bytes of memory from ADDR (default: 64)
+    w <ADDR> <BYTE>...      Write bytes to memory from ADDR
+    dis [ADDR] [N]          Disassemble N instructions from ADDR (default: 8 from PC)
+    stack [N]               Print N words from the top of the stack (default: 8)
+    h, help                 Print this message
+    q, quit                 Leave the debugger";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    A, F, B, C, D, E, H, L,
+    AF, BC, DE, HL, SP, PC
+}
+
+impl Register {
+    pub fn from_name (name: &str) -> Option<Register> {
+        let register = match name.to_ascii_uppercase().as_str() {
+            "A" => Register::A,
+            "F" => Register::F,
+            "B" => Register::B,
+            "C" => Register::C,
+            "D" => Register::D,
+            "E" => Register::E,
+            "H" => Register::H,
+            "L" => Register::L,
+            "AF" => Register::AF,
+            "BC" => Register::BC,
+            "DE" => Register::DE,
+            "HL" => Register::HL,
+            "SP" => Register::SP,
+            "PC" => Register::PC,
+            _ => return None
+        };
+        Some(register)
+    }
+
+    fn is_pair (self) -> bool {
+        matches!(self, Register::AF | Register::BC | Register::DE | Register::HL | Register::SP | Register::PC)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Step(u32),
+    Continue,
+    RunTo(u16),
+    Break(u16),
+    Delete(u16),
+    Breakpoints,
+    Watch(Watchpoint),
+    Unwatch(usize),
+    Watchpoints,
+    Registers,
+    Set(Register, u16),
+    Read(u16, u16),
+    Write(u16, Vec<u8>),
+    Disassemble(Option<u16>, u16),
+    Stack(u16),
+    Help,
+    Quit
+}
+
+fn parse_hex (value: &str) -> Result<u16, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid hex value: {}", value))
+}
+
+fn parse_byte (value: &str) -> Result<u8, String> {
+    match parse_hex(value)? {
+        byte if byte <= 0xFF => Ok(byte as u8),
+        _ => Err(format!("Value does not fit in a byte: {}", value))
+    }
+}
+
+fn parse_count<T: std::str::FromStr> (value: Option<&&str>, default: T) -> Result<T, String> {
+    match value {
+        Some(value) => value.parse().map_err(|_| format!("Invalid count: {}", value)),
+        None => Ok(default)
+    }
+}
+
+// Parses `<ACCESS> <ADDR>[-<END>] [<VALUE>[/<MASK>]]`.
+fn parse_watchpoint (args: &[&str]) -> Result<Watchpoint, String> {
+    let name = required(args, 0, "access")?;
+    let access = Access::from_name(name).ok_or_else(|| format!("Unknown access: {}", name))?;
+    let range = required(args, 1, "address")?;
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_hex(start)?, parse_hex(end)?),
+        None => (parse_hex(range)?, parse_hex(range)?)
+    };
+    if end < start {
+        return Err(format!("Invalid range: {}", range));
+    }
+    let mut watchpoint = Watchpoint::new(access, start, end);
+    if let Some(value) = args.get(2) {
+        let (expected, mask) = match value.split_once('/') {
+            Some((expected, mask)) => (parse_byte(expected)?, parse_byte(mask)?),
+            None => (parse_byte(value)?, 0xFF)
+        };
+        watchpoint.expected = expected;
+        watchpoint.mask = mask;
+    }
+    Ok(watchpoint)
+}
+
+fn required<'a> (args: &[&'a str], index: usize, name: &str) -> Result<&'a str, String> {
+    args.get(index).copied().ok_or_else(|| format!("Missing {}", name))
+}
+
+impl Command {
+    /// Parses a line typed at the prompt. An empty line steps one
+    /// instruction and `d` prints the registers, as the debugger used to.
+    pub fn parse (line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Ok(Command::Step(1))
+        };
+        let command = match name {
+            "s" | "step" => Command::Step(parse_count(args.first(), 1)?),
+            "c" | "continue" => Command::Continue,
+            "u" | "until" => Command::RunTo(parse_hex(required(args, 0, "address")?)?),
+            "b" | "break" => Command::Break(parse_hex(required(args, 0, "address")?)?),
+            "delete" => Command::Delete(parse_hex(required(args, 0, "address")?)?),
+            "breaks" => Command::Breakpoints,
+            "watch" => Command::Watch(parse_watchpoint(args)?),
+            "unwatch" => {
+                let index = required(args, 0, "watchpoint")?;
+                Command::Unwatch(index.parse().map_err(|_| format!("Invalid watchpoint: {}", index))?)
+            },
+            "watches" => Command::Watchpoints,
+            "r" | "regs" | "d" => Command::Registers,
+            "set" => {
+                let name = required(args, 0, "register")?;
+                let register = Register::from_name(name).ok_or_else(|| format!("Unknown register: {}", name))?;
+                let value = required(args, 1, "value")?;
+                let value = if register.is_pair() { parse_hex(value)? } else { parse_byte(value)? as u16 };
+                Command::Set(register, value)
+            },
+            "x" => Command::Read(parse_hex(required(args, 0, "address")?)?, parse_count(args.get(1), DEFAULT_READ_SIZE)?),
+            "w" => {
+                let addr = parse_hex(required(args, 0, "address")?)?;
+                required(args, 1, "bytes")?;
+                Command::Write(addr, args[1..].iter().map(|byte| parse_byte(byte)).collect::<Result<_, _>>()?)
+            },
+            "dis" => match args.first() {
+                Some(addr) => Command::Disassemble(Some(parse_hex(addr)?), parse_count(args.get(1), DEFAULT_DISASSEMBLE_COUNT)?),
+                None => Command::Disassemble(None, DEFAULT_DISASSEMBLE_COUNT)
+            },
+            "stack" => Command::Stack(parse_count(args.first(), DEFAULT_STACK_DEPTH)?),
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("Unknown command: {}", name))
+        };
+        Ok(command)
+    }
+}
+
+/// Why execution stopped.
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(u16),
+    Target(u16),
+    Watchpoint(WatchHit),
+    Interrupted
+}
+
+/// Makes Ctrl-C set the returned flag instead of ending the process, for
+/// `Debugger::set_interrupt`.
+pub fn catch_interrupts () -> &'static AtomicBool {
+    #[cfg(unix)]
+    {
+        extern "C" fn on_interrupt (_: libc::c_int) {
+            INTERRUPT_REQUESTED.store(true, Ordering::Relaxed);
+        }
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+        unsafe {
+            libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+    &INTERRUPT_REQUESTED
+}
+
+/// Runs the CPU under user control. While running, instructions execute
+/// exactly as they do without the debugger; breakpoints are only checked
+/// between instructions.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    frame: u64,
+    interrupt: &'static AtomicBool
+}
+
+impl Debugger {
+    pub fn new (cpu: &CPU) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            frame: cpu.get_mmu().get_gpu().get_frame_count(),
+            interrupt: &NEVER_INTERRUPTED
+        }
+    }
+
+    /// Stops `run` whenever `interrupt` gets set, clearing it.
+    pub fn set_interrupt (&mut self, interrupt: &'static AtomicBool) {
+        self.interrupt = interrupt;
+    }
+
+    pub fn add_breakpoint (&mut self, addr: u16) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint (&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Executes up to `max_steps` instructions, or without limit when it is
+    /// None, stopping before a breakpoint or `target`, after the instruction
+    /// that triggered a watchpoint, or when interrupted. The instruction at
+    /// PC always executes, so execution can resume from a breakpoint.
+    pub fn run (&mut self, cpu: &mut CPU, max_steps: Option<u32>, target: Option<u16>, on_frame: &mut dyn FnMut(&mmu::MMU)) -> Stop {
+        let mut steps = 0;
+        cpu.get_mmu().take_watch_hit();
+        // Ctrl-C at the prompt does not stop the next run.
+        self.interrupt.store(false, Ordering::Relaxed);
+        loop {
+            cpu.step();
+            let frame = cpu.get_mmu().get_gpu().get_frame_count();
+            if frame != self.frame {
+                self.frame = frame;
+                on_frame(cpu.get_mmu());
+            }
+            steps += 1;
+            if let Some(hit) = cpu.get_mmu().take_watch_hit() {
+                return Stop::Watchpoint(hit);
+            }
+            let pc = cpu.get_registers().get_pc();
+            if Some(pc) == target {
+                return Stop::Target(pc);
+            }
+            if self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+            if max_steps == Some(steps) {
+                return Stop::Stepped;
+            }
+            if self.interrupt.swap(false, Ordering::Relaxed) {
+                return Stop::Interrupted;
+            }
+        }
+    }
+
+    /// Reads commands from `input` until it ends or the user quits, showing
+    /// the instruction about to execute whenever execution stops.
+    pub fn repl (&mut self, cpu: &mut CPU, input: &mut dyn BufRead, output: &mut dyn Write, on_frame: &mut dyn FnMut(&mmu::MMU)) -> io::Result<()> {
+        self.print_current(cpu, output)?;
+        let mut line = String::new();
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            match Command::parse(&line) {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(command) => self.execute(cpu, command, output, on_frame)?,
+                Err(message) => writeln!(output, "{}", message)?
+            }
+        }
+    }
+
+    fn execute (&mut self, cpu: &mut CPU, command: Command, output: &mut dyn Write, on_frame: &mut dyn FnMut(&mmu::MMU)) -> io::Result<()> {
+        match command {
+            Command::Step(0) => {},
+            Command::Step(count) => self.resume(cpu, Some(count), None, output, on_frame)?,
+            Command::Continue => self.resume(cpu, None, None, output, on_frame)?,
+            Command::RunTo(addr) => self.resume(cpu, None, Some(addr), output, on_frame)?,
+            Command::Break(addr) => {
+                self.add_breakpoint(addr);
+                writeln!(output, "Breakpoint at ${:04X}", addr)?;
+            },
+            Command::Delete(addr) => {
+                if !self.remove_breakpoint(addr) {
+                    writeln!(output, "No breakpoint at ${:04X}", addr)?;
+                }
+            },
+            Command::Breakpoints => {
+                for addr in &self.breakpoints {
+                    writeln!(output, "${:04X}", addr)?;
+                }
+            },
+            Command::Watch(watchpoint) => {
+                let index = cpu.get_mmu_mut().add_watchpoint(watchpoint);
+                writeln!(output, "Watchpoint {}: {}", index, describe_watchpoint(&watchpoint))?;
+            },
+            Command::Unwatch(index) => {
+                if cpu.get_mmu_mut().remove_watchpoint(index).is_none() {
+                    writeln!(output, "No watchpoint {}", index)?;
+                }
+            },
+            Command::Watchpoints => {
+                for (index, watchpoint) in cpu.get_mmu().get_watchpoints().iter().enumerate() {
+                    writeln!(output, "{}: {}", index, describe_watchpoint(watchpoint))?;
+                }
+            },
+            Command::Registers => print_registers(cpu, output)?,
+            Command::Set(register, value) => set_register(cpu, register, value),
+            Command::Read(addr, size) => {
+                for line_start in (0..size).step_by(BYTES_PER_LINE as usize) {
+                    let start = addr.wrapping_add(line_start);
+                    write!(output, "${:04X}:", start)?;
+                    for offset in 0..std::cmp::min(BYTES_PER_LINE, size - line_start) {
+                        write!(output, " {:02X}", cpu.get_mmu().rb(start.wrapping_add(offset)))?;
+                    }
+                    writeln!(output)?;
+                }
+            },
+            Command::Write(addr, bytes) => {
+                for (offset, byte) in bytes.iter().enumerate() {
+                    cpu.get_mmu_mut().wb(addr.wrapping_add(offset as u16), *byte);
+                }
+            },
+            Command::Disassemble(addr, count) => {
+                let mut addr = addr.unwrap_or_else(|| cpu.get_registers().get_pc());
+                for _ in 0..count {
+                    addr = addr.wrapping_add(print_instruction(cpu, addr, output)?);
+                }
+            },
+            Command::Stack(depth) => {
+                let sp = cpu.get_registers().get_sp();
+                for i in 0..depth {
+                    let addr = sp.wrapping_add(i * 2);
+                    let mmu = cpu.get_mmu();
+                    let word = (mmu.rb(addr.wrapping_add(1)) as u16) << 8 | mmu.rb(addr) as u16;
+                    writeln!(output, "${:04X}: ${:04X}", addr, word)?;
+                }
+            },
+            Command::Help => writeln!(output, "{}", HELP)?,
+            Command::Quit => {}
+        }
+        Ok(())
+    }
+
+    fn resume (&mut self, cpu: &mut CPU, max_steps: Option<u32>, target: Option<u16>, output: &mut dyn Write, on_frame: &mut dyn FnMut(&mmu::MMU)) -> io::Result<()> {
+        match self.run(cpu, max_steps, target, on_frame) {
+            Stop::Breakpoint(addr) => writeln!(output, "Breakpoint at ${:04X}", addr)?,
+            Stop::Interrupted => writeln!(output, "Interrupted")?,
+            Stop::Watchpoint(hit) => {
+                write!(output, "Watchpoint {}: {} ${:04X} = ${:02X}", hit.index, hit.access.get_name(), hit.addr, hit.value)?;
+                if hit.access != Access::Read {
+                    write!(output, " (was ${:02X})", hit.old)?;
+                }
+                writeln!(output)?;
+            },
+            _ => {}
+        }
+        self.print_current(cpu, output)
+    }
+
+    fn print_current (&self, cpu: &CPU, output: &mut dyn Write) -> io::Result<()> {
+        print_instruction(cpu, cpu.get_registers().get_pc(), output)?;
+        Ok(())
+    }
+}
+
+fn describe_watchpoint (watchpoint: &Watchpoint) -> String {
+    let mut text = format!("{} ${:04X}", watchpoint.access.get_name(), watchpoint.start);
+    if watchpoint.end != watchpoint.start {
+        text += &format!("-${:04X}", watchpoint.end);
+    }
+    if watchpoint.mask != 0 {
+        text += &format!(" ${:02X}/${:02X}", watchpoint.expected, watchpoint.mask);
+    }
+    text
+}
+
+// Prints the instruction at `addr` with its bytes. Returns its size.
+fn print_instruction (cpu: &CPU, addr: u16, output: &mut dyn Write) -> io::Result<u16> {
+    let mut bytes = [0; disassembler::MAX_INSTRUCTION_SIZE];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = cpu.get_mmu().rb(addr.wrapping_add(i as u16));
+    }
+    let (text, size) = disassembler::disassemble(addr, bytes);
+    let hex: Vec<String> = bytes[..size as usize].iter().map(|byte| format!("{:02X}", byte)).collect();
+    writeln!(output, "${:04X}: {:<9} {}", addr, hex.join(" "), text)?;
+    Ok(size)
+}
+
+fn print_registers (cpu: &CPU, output: &mut dyn Write) -> io::Result<()> {
+    let registers = cpu.get_registers();
+    let f = registers.get_f();
+    let flags: String = ['Z', 'N', 'H', 'C'].iter().enumerate()
+        .map(|(i, name)| if f & (0x80 >> i) > 0 { *name } else { '-' })
+        .collect();
+    writeln!(output, "A=${:02X} F=${:02X} [{}] B=${:02X} C=${:02X} D=${:02X} E=${:02X} H=${:02X} L=${:02X} SP=${:04X} PC=${:04X}",
+        registers.get_a(), f, flags,
+        registers.get_b(), registers.get_c(), registers.get_d(), registers.get_e(),
+        registers.get_h(), registers.get_l(), registers.get_sp(), registers.get_pc())
+}
+
+pub fn get_register (cpu: &CPU, register: Register) -> u16 {
+    let registers = cpu.get_registers();
+    match register {
+        Register::A => registers.get_a() as u16,
+        Register::F => registers.get_f() as u16,
+        Register::B => registers.get_b() as u16,
+        Register::C => registers.get_c() as u16,
+        Register::D => registers.get_d() as u16,
+        Register::E => registers.get_e() as u16,
+        Register::H => registers.get_h() as u16,
+        Register::L => registers.get_l() as u16,
+        Register::AF => (registers.get_a() as u16) << 8 | registers.get_f() as u16,
+        Register::BC => registers.get_bc(),
+        Register::DE => registers.get_de(),
+        Register::HL => registers.get_hl(),
+        Register::SP => registers.get_sp(),
+        Register::PC => registers.get_pc()
+    }
+}
+
+pub fn set_register (cpu: &mut CPU, register: Register, value: u16) {
+    let registers = cpu.get_registers_mut();
+    match register {
+        Register::A => registers.set_a(value as u8),
+        Register::F => registers.set_f(value as u8),
+        Register::B => registers.set_b(value as u8),
+        Register::C => registers.set_c(value as u8),
+        Register::D => registers.set_d(value as u8),
+        Register::E => registers.set_e(value as u8),
+        Register::H => registers.set_h(value as u8),
+        Register::L => registers.set_l(value as u8),
+        Register::AF => {
+            registers.set_a((value >> 8) as u8);
+            registers.set_f(value as u8);
+        },
+        Register::BC => { registers.set_bc(value); },
+        Register::DE => { registers.set_de(value); },
+        Register::HL => { registers.set_hl(value); },
+        Register::SP => registers.set_sp(value),
+        Register::PC => registers.set_pc(value)
+    }
+}
+
+#[cfg(test)]
+#[path = "./debugger_test.rs"]
+mod debugger_test;
@@ -0,0 +1,80 @@
+use super::*;
+use std::io::Cursor;
+
+// A ROM of NOPs, so every step advances PC by one.
+fn debugged_cpu() -> (CPU, Debugger) {
+    let mut cpu = CPU::new();
+    cpu.read_rom(&vec![0; 0x8000]);
+    cpu.get_registers_mut().set_pc(0x0100);
+    let debugger = Debugger::new(&cpu);
+    (cpu, debugger)
+}
+
+fn session(cpu: &mut CPU, debugger: &mut Debugger, input: &str) -> String {
+    let mut output = Vec::new();
+    debugger.repl(cpu, &mut Cursor::new(input), &mut output, &mut |_| {}).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(Command::parse("\n"), Ok(Command::Step(1)));
+    assert_eq!(Command::parse("s 10"), Ok(Command::Step(10)));
+    assert_eq!(Command::parse("until 0x0150"), Ok(Command::RunTo(0x0150)));
+    assert_eq!(Command::parse("set hl c000"), Ok(Command::Set(Register::HL, 0xC000)));
+    assert_eq!(Command::parse("w $C000 12 34"), Ok(Command::Write(0xC000, vec![0x12, 0x34])));
+    assert_eq!(Command::parse("dis"), Ok(Command::Disassemble(None, DEFAULT_DISASSEMBLE_COUNT)));
+    assert!(Command::parse("set a 100").is_err());
+    assert!(Command::parse("break").is_err());
+    assert!(Command::parse("jump").is_err());
+}
+
+#[test]
+fn test_breakpoints_and_run_to() {
+    let (mut cpu, mut debugger) = debugged_cpu();
+    debugger.add_breakpoint(0x0110);
+    assert_eq!(debugger.run(&mut cpu, Some(4), None, &mut |_| {}), Stop::Stepped);
+    assert_eq!(cpu.get_registers().get_pc(), 0x0104);
+    assert_eq!(debugger.run(&mut cpu, None, Some(0x0108), &mut |_| {}), Stop::Target(0x0108));
+    assert_eq!(debugger.run(&mut cpu, Some(100), None, &mut |_| {}), Stop::Breakpoint(0x0110));
+    // Continuing from a breakpoint executes the instruction under it.
+    assert_eq!(debugger.run(&mut cpu, Some(1), None, &mut |_| {}), Stop::Stepped);
+    assert_eq!(cpu.get_registers().get_pc(), 0x0111);
+}
+
+#[test]
+fn test_session() {
+    let (mut cpu, mut debugger) = debugged_cpu();
+    let output = session(&mut cpu, &mut debugger, "w c000 3e 12\nset pc c000\ndis\nb c002\nc\nregs\nx c000 2\nq\ns\n");
+    assert!(output.starts_with("$0100: 00        NOP\n(gb) "));
+    assert!(output.contains("$C000: 3E 12     LD A,$12\n"));
+    assert!(output.contains("Breakpoint at $C002\n$C002: 00        NOP\n"));
+    assert!(output.contains("A=$12"));
+    assert!(output.contains("$C000: 3E 12\n"));
+    // Nothing runs after quitting.
+    assert_eq!(cpu.get_registers().get_pc(), 0xC002);
+}
+
+#[test]
+fn test_watchpoint_session() {
+    assert_eq!(Command::parse("watch write ff40 00/80"), Ok(Command::Watch(Watchpoint { access: Access::Write, start: 0xFF40, end: 0xFF40, expected: 0x00, mask: 0x80 })));
+    assert!(Command::parse("watch write c100-c000").is_err());
+    assert!(Command::parse("watch jump c000").is_err());
+
+    let (mut cpu, mut debugger) = debugged_cpu();
+    // LD HL,$C100; LD A,$05; LD (HL+),A
+    let input = "w c000 21 00 c1 3e 05 22\nset pc c000\nwatch change c100-c1ff\nwatches\nc\nq\n";
+    let output = session(&mut cpu, &mut debugger, input);
+    assert!(output.contains("0: change $C100-$C1FF\n"));
+    assert!(output.contains("Watchpoint 0: change $C100 = $05 (was $00)\n$C006: 00        NOP\n"));
+}
+
+#[test]
+fn test_interrupt_stops_continue() {
+    static INTERRUPT: AtomicBool = AtomicBool::new(false);
+    let (mut cpu, mut debugger) = debugged_cpu();
+    debugger.set_interrupt(&INTERRUPT);
+    // Ctrl-C while the PPU completes a frame, with no breakpoint to hit.
+    assert_eq!(debugger.run(&mut cpu, None, None, &mut |_| INTERRUPT.store(true, Ordering::Relaxed)), Stop::Interrupted);
+    assert!(!INTERRUPT.load(Ordering::Relaxed));
+}
@@ -0,0 +1,98 @@
+//! Decodes SM83 instructions into assembly text, for the debugger.
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const REGISTER_PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const STACK_PAIRS: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = ["ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP "];
+const ACCUMULATOR: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+const INDIRECT: [&str; 4] = ["(BC)", "(DE)", "(HL+)", "(HL-)"];
+const SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const BIT_OPERATIONS: [&str; 3] = ["BIT", "RES", "SET"];
+
+pub const MAX_INSTRUCTION_SIZE: usize = 3;
+
+/// Disassembles the instruction at `addr`, whose first bytes are `bytes`.
+/// Returns its text and its size in bytes.
+pub fn disassemble (addr: u16, bytes: [u8; MAX_INSTRUCTION_SIZE]) -> (String, u16) {
+    let opcode = bytes[0];
+    let n = bytes[1];
+    let nn = (bytes[2] as u16) << 8 | bytes[1] as u16;
+    let relative = addr.wrapping_add(2).wrapping_add(n as i8 as u16);
+    let x = opcode >> 6;
+    let y = ((opcode >> 3) & 0b111) as usize;
+    let z = opcode & 0b111;
+    let p = y >> 1;
+    let q = y & 1;
+
+    let with_n = |text: String| (text, 2);
+    let with_nn = |text: String| (text, 3);
+    match (x, z) {
+        (0, 0) => match y {
+            0 => (String::from("NOP"), 1),
+            1 => with_nn(format!("LD (${:04X}),SP", nn)),
+            2 => with_n(String::from("STOP")),
+            3 => with_n(format!("JR ${:04X}", relative)),
+            _ => with_n(format!("JR {},${:04X}", CONDITIONS[y - 4], relative))
+        },
+        (0, 1) if q == 0 => with_nn(format!("LD {},${:04X}", REGISTER_PAIRS[p], nn)),
+        (0, 1) => (format!("ADD HL,{}", REGISTER_PAIRS[p]), 1),
+        (0, 2) if q == 0 => (format!("LD {},A", INDIRECT[p]), 1),
+        (0, 2) => (format!("LD A,{}", INDIRECT[p]), 1),
+        (0, 3) if q == 0 => (format!("INC {}", REGISTER_PAIRS[p]), 1),
+        (0, 3) => (format!("DEC {}", REGISTER_PAIRS[p]), 1),
+        (0, 4) => (format!("INC {}", REGISTERS[y]), 1),
+        (0, 5) => (format!("DEC {}", REGISTERS[y]), 1),
+        (0, 6) => with_n(format!("LD {},${:02X}", REGISTERS[y], n)),
+        (0, _) => (String::from(ACCUMULATOR[y]), 1),
+        (1, _) if opcode == 0x76 => (String::from("HALT"), 1),
+        (1, _) => (format!("LD {},{}", REGISTERS[y], REGISTERS[z as usize]), 1),
+        (2, _) => (format!("{}{}", ALU[y], REGISTERS[z as usize]), 1),
+        (_, 0) => match y {
+            0..=3 => (format!("RET {}", CONDITIONS[y]), 1),
+            4 => with_n(format!("LDH (${:02X}),A", n)),
+            5 => with_n(format!("ADD SP,{}", n as i8)),
+            6 => with_n(format!("LDH A,(${:02X})", n)),
+            _ => with_n(format!("LD HL,SP{:+}", n as i8))
+        },
+        (_, 1) if q == 0 => (format!("POP {}", STACK_PAIRS[p]), 1),
+        (_, 1) => (String::from(["RET", "RETI", "JP HL", "LD SP,HL"][p]), 1),
+        (_, 2) => match y {
+            0..=3 => with_nn(format!("JP {},${:04X}", CONDITIONS[y], nn)),
+            4 => (String::from("LD (C),A"), 1),
+            5 => with_nn(format!("LD (${:04X}),A", nn)),
+            6 => (String::from("LD A,(C)"), 1),
+            _ => with_nn(format!("LD A,(${:04X})", nn))
+        },
+        (_, 3) => match y {
+            0 => with_nn(format!("JP ${:04X}", nn)),
+            1 => with_n(disassemble_cb(n)),
+            6 => (String::from("DI"), 1),
+            7 => (String::from("EI"), 1),
+            _ => invalid(opcode)
+        },
+        (_, 4) if y < 4 => with_nn(format!("CALL {},${:04X}", CONDITIONS[y], nn)),
+        (_, 5) if q == 0 => (format!("PUSH {}", STACK_PAIRS[p]), 1),
+        (_, 5) if p == 0 => with_nn(format!("CALL ${:04X}", nn)),
+        (_, 6) => with_n(format!("{}${:02X}", ALU[y], n)),
+        (_, 7) => (format!("RST ${:02X}", y * 8), 1),
+        _ => invalid(opcode)
+    }
+}
+
+fn disassemble_cb (opcode: u8) -> String {
+    let y = ((opcode >> 3) & 0b111) as usize;
+    let register = REGISTERS[(opcode & 0b111) as usize];
+    match opcode >> 6 {
+        0 => format!("{} {}", SHIFTS[y], register),
+        operation => format!("{} {},{}", BIT_OPERATIONS[operation as usize - 1], y, register)
+    }
+}
+
+fn invalid (opcode: u8) -> (String, u16) {
+    (format!("DB ${:02X}", opcode), 1)
+}
+
+#[cfg(test)]
+#[path = "./disassembler_test.rs"]
+mod disassembler_test;
@@ -0,0 +1,29 @@
+use super::*;
+
+#[test]
+fn test_operands_and_sizes() {
+    assert_eq!(disassemble(0x0100, [0x00, 0xC3, 0x50]), (String::from("NOP"), 1));
+    assert_eq!(disassemble(0x0100, [0xC3, 0x50, 0x01]), (String::from("JP $0150"), 3));
+    assert_eq!(disassemble(0x0150, [0x3E, 0x12, 0x00]), (String::from("LD A,$12"), 2));
+    assert_eq!(disassemble(0x0150, [0xE0, 0x40, 0x00]), (String::from("LDH ($40),A"), 2));
+    assert_eq!(disassemble(0x0150, [0x7E, 0x00, 0x00]), (String::from("LD A,(HL)"), 1));
+    assert_eq!(disassemble(0x0150, [0x22, 0x00, 0x00]), (String::from("LD (HL+),A"), 1));
+    assert_eq!(disassemble(0x0150, [0xAF, 0x00, 0x00]), (String::from("XOR A"), 1));
+    assert_eq!(disassemble(0x0150, [0xF8, 0xFE, 0x00]), (String::from("LD HL,SP-2"), 2));
+    assert_eq!(disassemble(0x0150, [0xF5, 0x00, 0x00]), (String::from("PUSH AF"), 1));
+    assert_eq!(disassemble(0x0150, [0xFF, 0x00, 0x00]), (String::from("RST $38"), 1));
+    assert_eq!(disassemble(0x0150, [0xD3, 0x00, 0x00]), (String::from("DB $D3"), 1));
+}
+
+#[test]
+fn test_relative_jumps_show_target() {
+    assert_eq!(disassemble(0x0150, [0x20, 0xFE, 0x00]), (String::from("JR NZ,$0150"), 2));
+    assert_eq!(disassemble(0x0150, [0x18, 0x10, 0x00]), (String::from("JR $0162"), 2));
+}
+
+#[test]
+fn test_cb_prefix() {
+    assert_eq!(disassemble(0x0150, [0xCB, 0x37, 0x00]), (String::from("SWAP A"), 2));
+    assert_eq!(disassemble(0x0150, [0xCB, 0x7C, 0x00]), (String::from("BIT 7,H"), 2));
+    assert_eq!(disassemble(0x0150, [0xCB, 0xC6, 0x00]), (String::from("SET 0,(HL)"), 2));
+}
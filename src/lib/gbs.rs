@@ -0,0 +1,94 @@
+use std::io;
+
+pub const HEADER_SIZE: usize = 0x70;
+const MAGIC: &[u8] = b"GBS";
+
+// Machine cycles between two VBlank interrupts.
+const VBLANK_PERIOD: u32 = 17556;
+// Machine cycles per timer increment for each TAC clock select value.
+const TIMER_PERIODS: [u32; 4] = [256, 4, 16, 64];
+const TAC_ENABLE: u8 = 0b100;
+const TAC_DOUBLE_SPEED: u8 = 0b10000000;
+
+/// Header of a GBS sound rip, followed in the file by the code loaded at
+/// `load_addr`.
+pub struct GbsHeader {
+    pub version: u8,
+    pub song_count: u8,
+    // 1-based, like the track numbers shown to the user.
+    pub first_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String
+}
+
+fn read_u16 (data: &[u8], offset: usize) -> u16 {
+    (data[offset + 1] as u16) << 8 | data[offset] as u16
+}
+
+fn read_string (data: &[u8], offset: usize) -> String {
+    let field = &data[offset..offset + 32];
+    let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn invalid (message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn is_gbs (data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+impl GbsHeader {
+    pub fn parse (data: &[u8]) -> io::Result<GbsHeader> {
+        if data.len() < HEADER_SIZE || !is_gbs(data) {
+            return Err(invalid("Not a GBS file"));
+        }
+        let header = GbsHeader {
+            version: data[0x03],
+            song_count: data[0x04],
+            first_song: data[0x05],
+            load_addr: read_u16(data, 0x06),
+            init_addr: read_u16(data, 0x08),
+            play_addr: read_u16(data, 0x0A),
+            stack_pointer: read_u16(data, 0x0C),
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+            title: read_string(data, 0x10),
+            author: read_string(data, 0x30),
+            copyright: read_string(data, 0x50)
+        };
+        if header.version != 1 {
+            return Err(invalid(&format!("Unsupported GBS version: {}", header.version)));
+        }
+        if header.load_addr < 0x400 || header.load_addr >= 0x8000 {
+            return Err(invalid(&format!("Invalid GBS load address: 0x{:04x}", header.load_addr)));
+        }
+        Ok(header)
+    }
+
+    /// Machine cycles between two calls of the play routine: the timer
+    /// overflow period if the timer is enabled, VBlank otherwise.
+    pub fn play_period (&self) -> u32 {
+        if self.timer_control & TAC_ENABLE == 0 {
+            return VBLANK_PERIOD;
+        }
+        let period = TIMER_PERIODS[(self.timer_control & 0x03) as usize] * (256 - self.timer_modulo as u32);
+        if self.timer_control & TAC_DOUBLE_SPEED > 0 {
+            period / 2
+        } else {
+            period
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./gbs_test.rs"]
+mod gbs_test;
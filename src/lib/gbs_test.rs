@@ -0,0 +1,79 @@
+use super::*;
+use crate::lib::cpu::CPU;
+use crate::lib::mbc::MbcType;
+use crate::lib::mbc::gbs::GbsRom;
+
+fn gbs_file(code: &[u8]) -> Vec<u8> {
+    let mut data = vec![0; HEADER_SIZE];
+    data[0..3].copy_from_slice(b"GBS");
+    data[0x03] = 1;
+    data[0x04] = 3;
+    data[0x05] = 2;
+    data[0x06..0x08].copy_from_slice(&[0x00, 0x04]);
+    data[0x08..0x0A].copy_from_slice(&[0x00, 0x04]);
+    data[0x0A..0x0C].copy_from_slice(&[0x10, 0x04]);
+    data[0x0C..0x0E].copy_from_slice(&[0xFE, 0xFF]);
+    data[0x10..0x15].copy_from_slice(b"Title");
+    data.extend_from_slice(code);
+    data
+}
+
+#[test]
+fn test_parse_header() {
+    let header = GbsHeader::parse(&gbs_file(&[])).unwrap();
+    assert_eq!(header.song_count, 3);
+    assert_eq!(header.first_song, 2);
+    assert_eq!(header.load_addr, 0x400);
+    assert_eq!(header.play_addr, 0x410);
+    assert_eq!(header.stack_pointer, 0xFFFE);
+    assert_eq!(header.title, "Title");
+    assert_eq!(header.author, "");
+    assert!(GbsHeader::parse(b"GBX").is_err());
+}
+
+#[test]
+fn test_play_period() {
+    let mut header = GbsHeader::parse(&gbs_file(&[])).unwrap();
+    assert_eq!(header.play_period(), 17556);
+    // 4096 Hz timer overflowing every 256 - 0xC0 = 64 increments.
+    header.timer_modulo = 0xC0;
+    header.timer_control = 0x04;
+    assert_eq!(header.play_period(), 256 * 64);
+    header.timer_control = 0x84;
+    assert_eq!(header.play_period(), 128 * 64);
+}
+
+#[test]
+fn test_rom_banks_and_restart_vectors() {
+    let mut code = vec![0; 0x8000];
+    code[0x4000 - 0x400] = 0x11;
+    code[0x8000 - 0x400] = 0x22;
+    let mut cpu = CPU::new();
+    let mmu = cpu.get_mmu_mut();
+    mmu.set_mbc(MbcType::GBS(GbsRom::from_code(0x400, &code)));
+    assert_eq!(mmu.rb(0x0008), 0xC3);
+    assert_eq!(mmu.rb(0x0009), 0x08);
+    assert_eq!(mmu.rb(0x000A), 0x04);
+    assert_eq!(mmu.rb(0x4000), 0x11);
+    mmu.wb(0x2000, 2);
+    assert_eq!(mmu.rb(0x4000), 0x22);
+    mmu.wb(0x2000, 0);
+    assert_eq!(mmu.rb(0x4000), 0x11);
+}
+
+#[test]
+fn test_call_runs_routine() {
+    // LD A,0x35; LDH (0x24),A; then the next routine at 0x0410
+    let data = gbs_file(&[0x3E, 0x35, 0xE0, 0x24]);
+    let header = GbsHeader::parse(&data).unwrap();
+    let mut cpu = CPU::new();
+    cpu.get_mmu_mut().set_mbc(MbcType::GBS(GbsRom::from_code(header.load_addr, &data[HEADER_SIZE..])));
+    cpu.get_mmu_mut().wb(0xFF26, 0x80);
+    cpu.get_registers_mut().set_sp(header.stack_pointer);
+    let cycles = cpu.call(header.init_addr, 5);
+    assert_eq!(cycles, 5);
+    assert_eq!(cpu.get_mmu().rb(0xFF24), 0x35);
+    // The return address was pushed on the stack.
+    assert_eq!(cpu.get_mmu().rb(0xFFFC), 0xFF);
+    assert_eq!(cpu.get_mmu().rb(0xFFFD), 0xFF);
+}
@@ -0,0 +1,341 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use super::cpu::CPU;
+use super::debugger::{self, Debugger, Register, Stop};
+use super::mmu;
+use super::watchpoint::{Access, Watchpoint};
+
+// Registers in the order of `g` packets, each 16 bits little endian, like
+// the first registers of GDB's Z80 target.
+const REGISTERS: [Register; 6] = [Register::AF, Register::BC, Register::DE, Register::HL, Register::SP, Register::PC];
+const REGISTER_NAMES: [&str; 6] = ["af", "bc", "de", "hl", "sp", "pc"];
+
+// Instructions run between checks for an interrupt from GDB while
+// continuing.
+const INTERRUPT_POLL_STEPS: u32 = 10_000;
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const PACKET_SIZE: usize = 0x4000;
+// Longest memory read whose reply, two hex digits per byte plus the
+// framing, fits in a packet.
+const MAX_READ_LENGTH: u32 = (PACKET_SIZE as u32 - 4) / 2;
+
+fn checksum (data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+/// Frames `data` as a `$data#checksum` packet, escaping the characters
+/// the protocol reserves.
+pub fn encode_packet (data: &str) -> String {
+    let mut escaped = String::new();
+    for c in data.chars() {
+        if matches!(c, '$' | '#' | '}' | '*') {
+            escaped.push('}');
+            escaped.push((c as u8 ^ 0x20) as char);
+        } else {
+            escaped.push(c);
+        }
+    }
+    format!("${}#{:02x}", escaped, checksum(&escaped))
+}
+
+fn to_hex (bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex (hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_number (hex: &str) -> Option<u32> {
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// Splits "addr,length" into numbers.
+fn parse_range (args: &str) -> Option<(u16, u32)> {
+    let (addr, length) = args.split_once(',')?;
+    Some((parse_number(addr)? as u16, parse_number(length)?))
+}
+
+fn target_description () -> String {
+    let registers: String = REGISTER_NAMES.iter()
+        .map(|name| format!("<reg name=\"{}\" bitsize=\"16\" type=\"{}\"/>", name, if *name == "pc" { "code_ptr" } else { "int" }))
+        .collect();
+    format!("<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target><feature name=\"org.gnu.gdb.sm83.cpu\">{}</feature></target>", registers)
+}
+
+/// Serves GDB's remote serial protocol: registers, memory, breakpoints,
+/// write and read watchpoints, single step and continue.
+pub struct GdbStub {
+    debugger: Debugger,
+    no_ack: bool,
+    attached: bool
+}
+
+impl GdbStub {
+    pub fn new (cpu: &CPU) -> GdbStub {
+        GdbStub {
+            debugger: Debugger::new(cpu),
+            no_ack: false,
+            attached: true
+        }
+    }
+
+    /// Waits for GDB to connect on `port` of localhost and serves it until
+    /// it detaches, kills the program or disconnects.
+    pub fn listen (&mut self, cpu: &mut CPU, port: u16, on_frame: &mut dyn FnMut(&mmu::MMU)) -> io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        debug!("[GDB] Waiting for a connection on port {}", port);
+        let (mut stream, address) = listener.accept()?;
+        debug!("[GDB] Connected to {}", address);
+        stream.set_nodelay(true)?;
+        while self.attached {
+            let packet = match self.read_packet(&mut stream)? {
+                Some(packet) => packet,
+                None => break
+            };
+            let reply = {
+                let poll_stream = &stream;
+                self.handle_packet(cpu, &packet, on_frame, &mut || interrupt_received(poll_stream))
+            };
+            match reply {
+                Some(reply) => stream.write_all(encode_packet(&reply).as_bytes())?,
+                None => break
+            }
+        }
+        debug!("[GDB] Session ended");
+        Ok(())
+    }
+
+    // Reads the next packet, acknowledging it unless GDB turned acks off.
+    // Returns None once the connection is closed.
+    fn read_packet (&mut self, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        let mut byte = [0];
+        loop {
+            // Acks, retransmission requests and stray interrupts are ignored.
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                match byte[0] {
+                    b'#' => break,
+                    b'}' => {
+                        if stream.read(&mut byte)? == 0 {
+                            return Ok(None);
+                        }
+                        data.push(b'}');
+                        data.push(byte[0]);
+                    },
+                    value => data.push(value)
+                }
+            }
+            let mut sum = [0; 2];
+            stream.read_exact(&mut sum)?;
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let valid = std::str::from_utf8(&sum).ok().and_then(|sum| u8::from_str_radix(sum, 16).ok()) == Some(checksum(&data));
+            if !self.no_ack {
+                stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid || self.no_ack {
+                return Ok(Some(unescape(&data)));
+            }
+        }
+    }
+
+    /// Executes `packet`, without its framing, and returns the reply, or
+    /// None when the session is over. `interrupted` tells whether GDB asked
+    /// to stop a running program.
+    pub fn handle_packet (&mut self, cpu: &mut CPU, packet: &str, on_frame: &mut dyn FnMut(&mmu::MMU), interrupted: &mut dyn FnMut() -> bool) -> Option<String> {
+        let command = match packet.chars().next() {
+            Some(command) => command,
+            None => return Some(String::new())
+        };
+        let args = &packet[command.len_utf8()..];
+        let reply = match command {
+            '?' => format!("S{:02x}", SIGTRAP),
+            'g' => {
+                let bytes: Vec<u8> = REGISTERS.iter().flat_map(|register| debugger::get_register(cpu, *register).to_le_bytes()).collect();
+                to_hex(&bytes)
+            },
+            'G' => match from_hex(args) {
+                Some(bytes) if bytes.len() == REGISTERS.len() * 2 => {
+                    for (register, value) in REGISTERS.iter().zip(bytes.chunks(2)) {
+                        debugger::set_register(cpu, *register, u16::from_le_bytes([value[0], value[1]]));
+                    }
+                    String::from("OK")
+                },
+                _ => String::from("E01")
+            },
+            'p' => match parse_number(args).and_then(|index| REGISTERS.get(index as usize)) {
+                Some(register) => to_hex(&debugger::get_register(cpu, *register).to_le_bytes()),
+                None => String::from("E01")
+            },
+            'P' => {
+                let register = args.split_once('=')
+                    .and_then(|(index, value)| Some((*REGISTERS.get(parse_number(index)? as usize)?, from_hex(value)?)));
+                match register {
+                    Some((register, value)) if value.len() == 2 => {
+                        debugger::set_register(cpu, register, u16::from_le_bytes([value[0], value[1]]));
+                        String::from("OK")
+                    },
+                    _ => String::from("E01")
+                }
+            },
+            'm' => match parse_range(args) {
+                Some((addr, length)) if length <= MAX_READ_LENGTH => {
+                    let bytes: Vec<u8> = (0..length).map(|offset| cpu.get_mmu().rb(addr.wrapping_add(offset as u16))).collect();
+                    to_hex(&bytes)
+                },
+                _ => String::from("E01")
+            },
+            'M' => {
+                let write = args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+                match write {
+                    Some(((addr, length), bytes)) if bytes.len() == length as usize => {
+                        for (offset, byte) in bytes.iter().enumerate() {
+                            cpu.get_mmu_mut().wb(addr.wrapping_add(offset as u16), *byte);
+                        }
+                        String::from("OK")
+                    },
+                    _ => String::from("E01")
+                }
+            },
+            'Z' | 'z' => self.set_breakpoint(cpu, command == 'Z', args),
+            's' => self.resume(cpu, Some(1), on_frame, interrupted),
+            'c' => self.resume(cpu, None, on_frame, interrupted),
+            'H' => String::from("OK"),
+            'D' => {
+                self.attached = false;
+                String::from("OK")
+            },
+            'k' => return None,
+            'q' | 'Q' => self.query(packet),
+            _ => String::new()
+        };
+        Some(reply)
+    }
+
+    fn query (&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE)
+        } else if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+            String::from("OK")
+        } else if packet == "qAttached" {
+            String::from("1")
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let description = target_description();
+            match parse_range(range) {
+                Some((offset, length)) => {
+                    let start = std::cmp::min(offset as usize, description.len());
+                    let end = std::cmp::min(start + length as usize, description.len());
+                    let marker = if end == description.len() { 'l' } else { 'm' };
+                    format!("{}{}", marker, &description[start..end])
+                },
+                None => String::from("E01")
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    // Handles Z and z packets: type 0 and 1 breakpoints, type 2 write and
+    // type 3 read watchpoints.
+    fn set_breakpoint (&mut self, cpu: &mut CPU, insert: bool, args: &str) -> String {
+        let fields: Vec<&str> = args.split(',').collect();
+        let (kind, addr, length) = match fields.as_slice() {
+            [kind, addr, length] => match (parse_number(addr), parse_number(length)) {
+                (Some(addr), Some(length)) => (*kind, addr as u16, std::cmp::max(length, 1) as u16),
+                _ => return String::from("E01")
+            },
+            _ => return String::from("E01")
+        };
+        let access = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return String::from("OK");
+            },
+            "2" => Access::Write,
+            "3" => Access::Read,
+            _ => return String::new()
+        };
+        let watchpoint = Watchpoint::new(access, addr, addr.wrapping_add(length - 1));
+        if insert {
+            cpu.get_mmu_mut().add_watchpoint(watchpoint);
+        } else if let Some(index) = cpu.get_mmu().get_watchpoints().iter().position(|other| *other == watchpoint) {
+            cpu.get_mmu_mut().remove_watchpoint(index);
+        }
+        String::from("OK")
+    }
+
+    fn resume (&mut self, cpu: &mut CPU, max_steps: Option<u32>, on_frame: &mut dyn FnMut(&mmu::MMU), interrupted: &mut dyn FnMut() -> bool) -> String {
+        let stop = match max_steps {
+            Some(steps) => self.debugger.run(cpu, Some(steps), None, on_frame),
+            None => loop {
+                match self.debugger.run(cpu, Some(INTERRUPT_POLL_STEPS), None, on_frame) {
+                    Stop::Stepped if interrupted() => return format!("S{:02x}", SIGINT),
+                    Stop::Stepped => {},
+                    stop => break stop
+                }
+            }
+        };
+        match stop {
+            Stop::Watchpoint(hit) => {
+                let kind = if hit.access == Access::Read { "rwatch" } else { "watch" };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.addr)
+            },
+            _ => format!("S{:02x}", SIGTRAP)
+        }
+    }
+}
+
+fn unescape (data: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' => if let Some(escaped) = chars.next() {
+                unescaped.push((escaped as u8 ^ 0x20) as char);
+            },
+            _ => unescaped.push(c)
+        }
+    }
+    unescaped
+}
+
+// Checks without blocking whether GDB sent an interrupt. Anything else GDB
+// sent is left for `read_packet`.
+fn interrupt_received (stream: &TcpStream) -> bool {
+    let mut byte = [0];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let received = matches!(stream.peek(&mut byte), Ok(1) if byte[0] == INTERRUPT);
+    let _ = stream.set_nonblocking(false);
+    if received {
+        let mut reader = stream;
+        let _ = reader.read(&mut byte);
+    }
+    received
+}
+
+#[cfg(test)]
+#[path = "./gdb_test.rs"]
+mod gdb_test;
@@ -0,0 +1,96 @@
+use super::*;
+
+// A ROM of NOPs, so every step advances PC by one.
+fn stub_cpu() -> (CPU, GdbStub) {
+    let mut cpu = CPU::new();
+    cpu.read_rom(&vec![0; 0x8000]);
+    cpu.get_registers_mut().set_pc(0x0100);
+    let stub = GdbStub::new(&cpu);
+    (cpu, stub)
+}
+
+fn request(stub: &mut GdbStub, cpu: &mut CPU, packet: &str) -> String {
+    stub.handle_packet(cpu, packet, &mut |_| {}, &mut || false).unwrap()
+}
+
+#[test]
+fn test_packet_framing() {
+    assert_eq!(encode_packet("OK"), "$OK#9a");
+    assert_eq!(encode_packet("a#b"), "$a}\x03b#43");
+    assert_eq!(unescape("a}\x03b"), "a#b");
+}
+
+#[test]
+fn test_registers() {
+    let (mut cpu, mut stub) = stub_cpu();
+    cpu.get_registers_mut().set_bc(0x1234);
+    assert_eq!(request(&mut stub, &mut cpu, "g"), "0000341200000000feff0001");
+    assert_eq!(request(&mut stub, &mut cpu, "P5=5001"), "OK");
+    assert_eq!(cpu.get_registers().get_pc(), 0x0150);
+    assert_eq!(request(&mut stub, &mut cpu, "G b0011300d8004d01feff0002"), "E01");
+    assert_eq!(request(&mut stub, &mut cpu, "Gb0011300d8004d01feff0002"), "OK");
+    assert_eq!(cpu.get_registers().get_a(), 0x01);
+    assert_eq!(cpu.get_registers().get_f(), 0xB0);
+    assert_eq!(request(&mut stub, &mut cpu, "p3"), "4d01");
+}
+
+#[test]
+fn test_memory() {
+    let (mut cpu, mut stub) = stub_cpu();
+    assert_eq!(request(&mut stub, &mut cpu, "Mc000,2:abcd"), "OK");
+    assert_eq!(request(&mut stub, &mut cpu, "mc000,3"), "abcd00");
+    assert_eq!(request(&mut stub, &mut cpu, "Mc000,2:ab"), "E01");
+    assert_eq!(request(&mut stub, &mut cpu, &format!("m0,{:x}", MAX_READ_LENGTH)).len(), MAX_READ_LENGTH as usize * 2);
+    assert_eq!(request(&mut stub, &mut cpu, "m0,ffffffff"), "E01");
+}
+
+#[test]
+fn test_breakpoints_and_watchpoints() {
+    let (mut cpu, mut stub) = stub_cpu();
+    assert_eq!(request(&mut stub, &mut cpu, "s"), "S05");
+    assert_eq!(cpu.get_registers().get_pc(), 0x0101);
+    assert_eq!(request(&mut stub, &mut cpu, "Z0,110,1"), "OK");
+    assert_eq!(request(&mut stub, &mut cpu, "c"), "S05");
+    assert_eq!(cpu.get_registers().get_pc(), 0x0110);
+    assert_eq!(request(&mut stub, &mut cpu, "z0,110,1"), "OK");
+
+    // LD HL,$C100; LD A,$05; LD (HL+),A
+    request(&mut stub, &mut cpu, "Mc000,6:2100c13e0522");
+    request(&mut stub, &mut cpu, "P5=00c0");
+    assert_eq!(request(&mut stub, &mut cpu, "Z2,c100,2"), "OK");
+    assert_eq!(request(&mut stub, &mut cpu, "c"), "T05watch:c100;");
+    assert_eq!(request(&mut stub, &mut cpu, "z2,c100,2"), "OK");
+    assert!(cpu.get_mmu().get_watchpoints().is_empty());
+}
+
+#[test]
+fn test_interrupt_and_session() {
+    let (mut cpu, mut stub) = stub_cpu();
+    let reply = stub.handle_packet(&mut cpu, "c", &mut |_| {}, &mut || true);
+    assert_eq!(reply, Some(String::from("S02")));
+    assert!(request(&mut stub, &mut cpu, "qSupported:multiprocess+").contains("qXfer:features:read+"));
+    assert!(request(&mut stub, &mut cpu, "qXfer:features:read:target.xml:0,ffff").starts_with("l<?xml"));
+    assert_eq!(request(&mut stub, &mut cpu, "vMustReplyEmpty"), "");
+    assert_eq!(stub.handle_packet(&mut cpu, "k", &mut |_| {}, &mut || false), None);
+}
+
+#[test]
+fn test_polling_for_interrupts_keeps_packets() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    let (_, mut stub) = stub_cpu();
+    stub.no_ack = true;
+
+    client.write_all(encode_packet("g").as_bytes()).unwrap();
+    client.flush().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!interrupt_received(&server));
+    assert_eq!(stub.read_packet(&mut server).unwrap(), Some(String::from("g")));
+
+    client.write_all(&[INTERRUPT]).unwrap();
+    client.write_all(encode_packet("?").as_bytes()).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(interrupt_received(&server));
+    assert_eq!(stub.read_packet(&mut server).unwrap(), Some(String::from("?")));
+}
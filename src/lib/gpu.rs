@@ -1,13 +1,522 @@
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
 const VRAM_SIZE: usize = 8192;
+const VRAM_BANKS: usize = 2;
+pub const TILE_COUNT: usize = 384;
+const PALETTE_RAM_SIZE: usize = 64;
+const OAM_SIZE: usize = 160;
+
+// Timings are expressed in machine cycles, the same unit returned by CPU::exec_inst.
+const OAM_SEARCH_CYCLES: u32 = 20;
+const PIXEL_TRANSFER_CYCLES: u32 = 43;
+const HBLANK_CYCLES: u32 = 51;
+const LINE_CYCLES: u32 = OAM_SEARCH_CYCLES + PIXEL_TRANSFER_CYCLES + HBLANK_CYCLES;
+const LINES_PER_FRAME: u8 = 154;
+pub const FRAME_CYCLES: u32 = LINE_CYCLES * LINES_PER_FRAME as u32;
+
+pub const INT_VBLANK: u8 = 0b00001;
+pub const INT_STAT: u8 = 0b00010;
+
+const LCDC_ENABLE: u8 = 0b10000000;
+const LCDC_WINDOW_MAP: u8 = 0b01000000;
+const LCDC_WINDOW_ENABLE: u8 = 0b00100000;
+const LCDC_TILE_DATA: u8 = 0b00010000;
+const LCDC_BG_MAP: u8 = 0b00001000;
+pub const LCDC_OBJ_SIZE: u8 = 0b00000100;
+const LCDC_OBJ_ENABLE: u8 = 0b00000010;
+const LCDC_BG_ENABLE: u8 = 0b00000001;
+
+const STAT_LYC_INT: u8 = 0b01000000;
+const STAT_OAM_INT: u8 = 0b00100000;
+const STAT_VBLANK_INT: u8 = 0b00010000;
+const STAT_HBLANK_INT: u8 = 0b00001000;
+const STAT_COINCIDENCE: u8 = 0b00000100;
+
+const MODE_HBLANK: u8 = 0;
+const MODE_VBLANK: u8 = 1;
+const MODE_OAM_SEARCH: u8 = 2;
+const MODE_PIXEL_TRANSFER: u8 = 3;
+
+pub const OBJ_ATTR_PRIORITY: u8 = 0b10000000;
+pub const OBJ_ATTR_Y_FLIP: u8 = 0b01000000;
+pub const OBJ_ATTR_X_FLIP: u8 = 0b00100000;
+pub const OBJ_ATTR_PALETTE: u8 = 0b00010000;
+pub const OBJ_ATTR_CGB_BANK: u8 = 0b00001000;
+pub const OBJ_ATTR_CGB_PALETTE: u8 = 0b00000111;
+const MAX_OBJS_PER_LINE: usize = 10;
+
+pub const BG_ATTR_PRIORITY: u8 = 0b10000000;
+pub const BG_ATTR_Y_FLIP: u8 = 0b01000000;
+pub const BG_ATTR_X_FLIP: u8 = 0b00100000;
+pub const BG_ATTR_BANK: u8 = 0b00001000;
+pub const BG_ATTR_PALETTE: u8 = 0b00000111;
+
+const PALETTE_AUTO_INCREMENT: u8 = 0b10000000;
+const WHITE_RGB555: u16 = 0x7FFF;
+
 pub struct GPU {
-    data: [u8; VRAM_SIZE]
+    cgb: bool,
+    // A CGB running a DMG game colors the DMG shades through palette RAM:
+    // BG palette 0 for the background, object palettes 0 and 1 for OBP0
+    // and OBP1.
+    compatibility: bool,
+    data: [u8; VRAM_SIZE * VRAM_BANKS],
+    vram_bank: usize,
+    oam: [u8; OAM_SIZE],
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    bcps: u8,
+    ocps: u8,
+    opri: u8,
+    bg_palettes: [u8; PALETTE_RAM_SIZE],
+    obj_palettes: [u8; PALETTE_RAM_SIZE],
+    window_line: u8,
+    mode_clock: u32,
+    frame_count: u64,
+    // HBlank periods entered with the LCD on, which clock HBlank DMA.
+    hblank_count: u64,
+    // In DMG mode, shades (0-3) after applying BGP/OBP0/OBP1.
+    // In CGB mode, 15-bit RGB555 colors from palette RAM.
+    framebuffer: [u16; SCREEN_WIDTH * SCREEN_HEIGHT],
 }
 
 impl GPU {
     pub fn new () -> GPU {
         debug!("Creating new GPU ({}KB)...", VRAM_SIZE/1024);
         GPU {
-            data: [0;VRAM_SIZE]
+            cgb: false,
+            compatibility: false,
+            data: [0; VRAM_SIZE * VRAM_BANKS],
+            vram_bank: 0,
+            oam: [0; OAM_SIZE],
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            bcps: 0,
+            ocps: 0,
+            opri: 0,
+            bg_palettes: [0xFF; PALETTE_RAM_SIZE],
+            obj_palettes: [0; PALETTE_RAM_SIZE],
+            window_line: 0,
+            mode_clock: 0,
+            frame_count: 0,
+            hblank_count: 0,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
         }
     }
-}
\ No newline at end of file
+
+    /// Enables the Game Boy Color PPU: two VRAM banks, BG map attributes,
+    /// color palette RAM and OAM-index object priority.
+    pub fn set_cgb_mode(&mut self, cgb: bool) {
+        self.cgb = cgb;
+        self.compatibility = false;
+        self.vram_bank = 0;
+    }
+
+    pub fn is_cgb_mode(&self) -> bool {
+        self.cgb
+    }
+
+    /// Enables the DMG compatibility mode of the CGB, where the palettes set
+    /// up by the boot ROM color the game.
+    pub fn set_compatibility_mode(&mut self, compatibility: bool) {
+        self.compatibility = compatibility;
+    }
+
+    /// Loads the colors of the compatibility mode: BG palette 0 and object
+    /// palettes 0 and 1, as RGB555 colors.
+    pub fn set_compatibility_palettes(&mut self, bg: [u16; 4], obj0: [u16; 4], obj1: [u16; 4]) {
+        set_palette_colors(&mut self.bg_palettes, 0, bg);
+        set_palette_colors(&mut self.obj_palettes, 0, obj0);
+        set_palette_colors(&mut self.obj_palettes, 1, obj1);
+    }
+
+    /// Whether the framebuffer holds RGB555 colors rather than DMG shades.
+    pub fn has_color_output(&self) -> bool {
+        self.cgb || self.compatibility
+    }
+
+    pub fn get_framebuffer(&self) -> &[u16] {
+        &self.framebuffer
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn get_hblank_count(&self) -> u64 {
+        self.hblank_count
+    }
+
+    pub fn rb (&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF => self.data[self.vram_bank * VRAM_SIZE + (addr - 0x8000) as usize],
+            0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize],
+            0xFF40 => self.lcdc,
+            0xFF41 => 0x80 | self.stat,
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.ly,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            0xFF4F if self.cgb => 0xFE | self.vram_bank as u8,
+            0xFF68 if self.cgb => 0x40 | self.bcps,
+            0xFF69 if self.cgb => self.bg_palettes[(self.bcps & 0x3F) as usize],
+            0xFF6A if self.cgb => 0x40 | self.ocps,
+            0xFF6B if self.cgb => self.obj_palettes[(self.ocps & 0x3F) as usize],
+            0xFF6C if self.cgb => 0xFE | self.opri,
+            _ => 0xFF
+        }
+    }
+
+    pub fn wb (&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.data[self.vram_bank * VRAM_SIZE + (addr - 0x8000) as usize] = value,
+            0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize] = value,
+            0xFF40 => {
+                let was_enabled = self.lcdc & LCDC_ENABLE > 0;
+                self.lcdc = value;
+                if was_enabled && value & LCDC_ENABLE == 0 {
+                    // Turning the LCD off resets LY and leaves the PPU in HBlank.
+                    self.ly = 0;
+                    self.window_line = 0;
+                    self.mode_clock = 0;
+                    self.set_mode(MODE_HBLANK);
+                } else if !was_enabled && value & LCDC_ENABLE > 0 {
+                    self.mode_clock = 0;
+                    self.set_mode(MODE_OAM_SEARCH);
+                }
+            }
+            // Mode and coincidence bits are read-only.
+            0xFF41 => self.stat = (value & 0x78) | (self.stat & 0x07),
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => {}
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            0xFF4F if self.cgb => self.vram_bank = (value & 0x01) as usize,
+            0xFF68 if self.cgb => self.bcps = value & 0xBF,
+            0xFF69 if self.cgb => {
+                self.bg_palettes[(self.bcps & 0x3F) as usize] = value;
+                self.bcps = next_palette_index(self.bcps);
+            }
+            0xFF6A if self.cgb => self.ocps = value & 0xBF,
+            0xFF6B if self.cgb => {
+                self.obj_palettes[(self.ocps & 0x3F) as usize] = value;
+                self.ocps = next_palette_index(self.ocps);
+            }
+            0xFF6C if self.cgb => self.opri = value & 0x01,
+            _ => {}
+        }
+    }
+
+    /// Advances the PPU by the given amount of machine cycles and returns the
+    /// interrupt flags (INT_VBLANK, INT_STAT) that should be requested.
+    pub fn step (&mut self, cycles: u32) -> u8 {
+        let mut interrupts = 0;
+        if self.lcdc & LCDC_ENABLE == 0 {
+            // With the LCD off nothing is drawn, but frames still elapse so
+            // that callers waiting on frame boundaries keep making progress.
+            self.mode_clock += cycles;
+            if self.mode_clock >= FRAME_CYCLES {
+                self.mode_clock -= FRAME_CYCLES;
+                self.framebuffer = [self.blank_pixel(); SCREEN_WIDTH * SCREEN_HEIGHT];
+                self.frame_count += 1;
+            }
+            return interrupts;
+        }
+
+        self.mode_clock += cycles;
+        loop {
+            match self.get_mode() {
+                MODE_OAM_SEARCH if self.mode_clock >= OAM_SEARCH_CYCLES => {
+                    self.mode_clock -= OAM_SEARCH_CYCLES;
+                    self.set_mode(MODE_PIXEL_TRANSFER);
+                }
+                MODE_PIXEL_TRANSFER if self.mode_clock >= PIXEL_TRANSFER_CYCLES => {
+                    self.mode_clock -= PIXEL_TRANSFER_CYCLES;
+                    self.render_scanline();
+                    self.set_mode(MODE_HBLANK);
+                    self.hblank_count += 1;
+                    if self.stat & STAT_HBLANK_INT > 0 {
+                        interrupts |= INT_STAT;
+                    }
+                }
+                MODE_HBLANK if self.mode_clock >= HBLANK_CYCLES => {
+                    self.mode_clock -= HBLANK_CYCLES;
+                    interrupts |= self.next_line();
+                    if self.ly == SCREEN_HEIGHT as u8 {
+                        self.set_mode(MODE_VBLANK);
+                        self.frame_count += 1;
+                        interrupts |= INT_VBLANK;
+                        if self.stat & STAT_VBLANK_INT > 0 {
+                            interrupts |= INT_STAT;
+                        }
+                    } else {
+                        self.set_mode(MODE_OAM_SEARCH);
+                        if self.stat & STAT_OAM_INT > 0 {
+                            interrupts |= INT_STAT;
+                        }
+                    }
+                }
+                MODE_VBLANK if self.mode_clock >= LINE_CYCLES => {
+                    self.mode_clock -= LINE_CYCLES;
+                    interrupts |= self.next_line();
+                    if self.ly == 0 {
+                        self.window_line = 0;
+                        self.set_mode(MODE_OAM_SEARCH);
+                        if self.stat & STAT_OAM_INT > 0 {
+                            interrupts |= INT_STAT;
+                        }
+                    }
+                }
+                _ => break
+            }
+        }
+        interrupts
+    }
+
+    fn get_mode(&self) -> u8 {
+        self.stat & 0x03
+    }
+
+    fn set_mode(&mut self, mode: u8) {
+        self.stat = (self.stat & 0xFC) | mode;
+    }
+
+    fn next_line(&mut self) -> u8 {
+        self.ly = (self.ly + 1) % LINES_PER_FRAME;
+        if self.ly == self.lyc {
+            self.stat |= STAT_COINCIDENCE;
+            if self.stat & STAT_LYC_INT > 0 {
+                return INT_STAT;
+            }
+        } else {
+            self.stat &= !STAT_COINCIDENCE;
+        }
+        0
+    }
+
+    fn blank_pixel(&self) -> u16 {
+        if self.has_color_output() { WHITE_RGB555 } else { 0 }
+    }
+
+    /// Returns the 2-bit color index of pixel (x, y) of the given tile.
+    /// Tile numbers are absolute indices into the 384 tiles of a VRAM bank.
+    pub fn tile_pixel(&self, bank: usize, tile: usize, x: u8, y: u8) -> u8 {
+        let addr = bank * VRAM_SIZE + tile * 16 + (y as usize) * 2;
+        let lo = self.data[addr];
+        let hi = self.data[addr + 1];
+        let bit = 7 - x;
+        (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+    }
+
+    /// Resolves a tile number from a BG/window map to an absolute tile index,
+    /// honoring the tile data area selected by LCDC bit 4.
+    pub fn bg_tile_index(&self, tile_number: u8) -> usize {
+        if self.lcdc & LCDC_TILE_DATA > 0 {
+            tile_number as usize
+        } else {
+            (256 + (tile_number as i8) as i16) as usize
+        }
+    }
+
+    /// Returns the tile number of a BG map entry, from VRAM bank 0 whatever
+    /// bank VBK selects. `map_offset` is relative to the start of VRAM.
+    pub fn bg_tile_number(&self, map_offset: usize) -> u8 {
+        self.data[map_offset]
+    }
+
+    /// Returns the CGB attributes of a BG map entry (always 0 in DMG mode).
+    /// `map_offset` is relative to the start of VRAM.
+    pub fn bg_attributes(&self, map_offset: usize) -> u8 {
+        if self.cgb { self.data[VRAM_SIZE + map_offset] } else { 0 }
+    }
+
+    /// Color index and attributes of pixel (x, y) of a 256x256 BG map.
+    fn map_pixel(&self, map: usize, x: u8, y: u8) -> (u8, u8) {
+        let map_offset = map + (y as usize / 8) * 32 + (x as usize / 8);
+        let tile = self.bg_tile_index(self.bg_tile_number(map_offset));
+        let attr = self.bg_attributes(map_offset);
+        let bank = if attr & BG_ATTR_BANK > 0 { 1 } else { 0 };
+        let tx = if attr & BG_ATTR_X_FLIP > 0 { 7 - x % 8 } else { x % 8 };
+        let ty = if attr & BG_ATTR_Y_FLIP > 0 { 7 - y % 8 } else { y % 8 };
+        (self.tile_pixel(bank, tile, tx, ty), attr)
+    }
+
+    /// RGB555 color `color` (0-3) of CGB background palette `palette` (0-7).
+    pub fn bg_color(&self, palette: u8, color: u8) -> u16 {
+        palette_color(&self.bg_palettes, palette, color)
+    }
+
+    /// RGB555 color `color` (0-3) of CGB object palette `palette` (0-7).
+    pub fn obj_color(&self, palette: u8, color: u8) -> u16 {
+        palette_color(&self.obj_palettes, palette, color)
+    }
+
+    fn render_scanline(&mut self) {
+        let line = self.ly as usize;
+        if line >= SCREEN_HEIGHT {
+            return;
+        }
+        let mut bg_colors = [0u8; SCREEN_WIDTH];
+        let mut bg_attrs = [0u8; SCREEN_WIDTH];
+
+        // On CGB, LCDC bit 0 does not disable the background; it only takes
+        // away its priority over objects.
+        if self.cgb || self.lcdc & LCDC_BG_ENABLE > 0 {
+            let bg_map: usize = if self.lcdc & LCDC_BG_MAP > 0 { 0x1C00 } else { 0x1800 };
+            let y = self.scy.wrapping_add(self.ly);
+            for x in 0..SCREEN_WIDTH {
+                let px = self.scx.wrapping_add(x as u8);
+                let (color, attr) = self.map_pixel(bg_map, px, y);
+                bg_colors[x] = color;
+                bg_attrs[x] = attr;
+            }
+
+            let window_visible = self.lcdc & LCDC_WINDOW_ENABLE > 0
+                && self.ly >= self.wy
+                && self.wx <= 166;
+            if window_visible {
+                let window_map: usize = if self.lcdc & LCDC_WINDOW_MAP > 0 { 0x1C00 } else { 0x1800 };
+                let wy = self.window_line;
+                let start_x = self.wx as i16 - 7;
+                for x in 0..SCREEN_WIDTH {
+                    let wx = x as i16 - start_x;
+                    if wx < 0 {
+                        continue;
+                    }
+                    let (color, attr) = self.map_pixel(window_map, wx as u8, wy);
+                    bg_colors[x] = color;
+                    bg_attrs[x] = attr;
+                }
+                self.window_line += 1;
+            }
+        }
+
+        for x in 0..SCREEN_WIDTH {
+            let shade = (self.bgp >> (bg_colors[x] * 2)) & 0x03;
+            self.framebuffer[line * SCREEN_WIDTH + x] = if self.cgb {
+                self.bg_color(bg_attrs[x] & BG_ATTR_PALETTE, bg_colors[x])
+            } else if self.compatibility {
+                self.bg_color(0, shade)
+            } else {
+                shade as u16
+            };
+        }
+
+        if self.lcdc & LCDC_OBJ_ENABLE > 0 {
+            self.render_objs(line, &bg_colors, &bg_attrs);
+        }
+    }
+
+    fn render_objs(&mut self, line: usize, bg_colors: &[u8; SCREEN_WIDTH], bg_attrs: &[u8; SCREEN_WIDTH]) {
+        let height: i16 = if self.lcdc & LCDC_OBJ_SIZE > 0 { 16 } else { 8 };
+        let mut visible: Vec<usize> = (0..40)
+            .filter(|i| {
+                let top = self.oam[i * 4] as i16 - 16;
+                (line as i16) >= top && (line as i16) < top + height
+            })
+            .take(MAX_OBJS_PER_LINE)
+            .collect();
+        // On DMG the object with the smallest X wins, ties are won by the lowest
+        // OAM index. CGB only looks at the OAM index unless OPRI asks for the DMG
+        // behavior. Drawing in reverse priority order lets the winner overwrite
+        // the others.
+        if !self.cgb || self.opri & 0x01 > 0 {
+            visible.sort_by_key(|&i| (self.oam[i * 4 + 1], i));
+        }
+        // In CGB mode, a cleared LCDC bit 0 puts objects above everything.
+        let bg_master_priority = !self.cgb || self.lcdc & LCDC_BG_ENABLE > 0;
+        for &i in visible.iter().rev() {
+            let top = self.oam[i * 4] as i16 - 16;
+            let left = self.oam[i * 4 + 1] as i16 - 8;
+            let mut tile = self.oam[i * 4 + 2] as usize;
+            let attr = self.oam[i * 4 + 3];
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            let mut ty = line as i16 - top;
+            if attr & OBJ_ATTR_Y_FLIP > 0 {
+                ty = height - 1 - ty;
+            }
+            let bank = if self.cgb && attr & OBJ_ATTR_CGB_BANK > 0 { 1 } else { 0 };
+            let palette = if attr & OBJ_ATTR_PALETTE > 0 { self.obp1 } else { self.obp0 };
+            for tx in 0..8i16 {
+                let x = left + tx;
+                if x < 0 || x >= SCREEN_WIDTH as i16 {
+                    continue;
+                }
+                let px = if attr & OBJ_ATTR_X_FLIP > 0 { 7 - tx } else { tx };
+                let color = self.tile_pixel(bank, tile + (ty as usize / 8), px as u8, (ty % 8) as u8);
+                if color == 0 {
+                    continue;
+                }
+                let x = x as usize;
+                let bg_on_top = attr & OBJ_ATTR_PRIORITY > 0 || bg_attrs[x] & BG_ATTR_PRIORITY > 0;
+                if bg_master_priority && bg_on_top && bg_colors[x] != 0 {
+                    continue;
+                }
+                let shade = (palette >> (color * 2)) & 0x03;
+                self.framebuffer[line * SCREEN_WIDTH + x] = if self.cgb {
+                    self.obj_color(attr & OBJ_ATTR_CGB_PALETTE, color)
+                } else if self.compatibility {
+                    self.obj_color((attr & OBJ_ATTR_PALETTE > 0) as u8, shade)
+                } else {
+                    shade as u16
+                };
+            }
+        }
+    }
+}
+
+fn next_palette_index(spec: u8) -> u8 {
+    if spec & PALETTE_AUTO_INCREMENT > 0 {
+        PALETTE_AUTO_INCREMENT | ((spec + 1) & 0x3F)
+    } else {
+        spec
+    }
+}
+
+fn palette_color(palette_ram: &[u8; PALETTE_RAM_SIZE], palette: u8, color: u8) -> u16 {
+    let i = (palette as usize) * 8 + (color as usize) * 2;
+    ((palette_ram[i + 1] as u16) << 8 | palette_ram[i] as u16) & 0x7FFF
+}
+
+fn set_palette_colors(palette_ram: &mut [u8; PALETTE_RAM_SIZE], palette: usize, colors: [u16; 4]) {
+    for (color, value) in colors.iter().enumerate() {
+        let i = palette * 8 + color * 2;
+        palette_ram[i] = *value as u8;
+        palette_ram[i + 1] = (*value >> 8) as u8;
+    }
+}
+
+#[cfg(test)]
+#[path = "./gpu_test.rs"]
+mod gpu_test;
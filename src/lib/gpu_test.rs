@@ -0,0 +1,107 @@
+use super::*;
+
+fn cgb_gpu() -> GPU {
+    let mut gpu = GPU::new();
+    gpu.set_cgb_mode(true);
+    gpu
+}
+
+fn write_palette(gpu: &mut GPU, spec_addr: u16, palette: u8, colors: [u16; 4]) {
+    gpu.wb(spec_addr, PALETTE_AUTO_INCREMENT | (palette * 8));
+    for color in colors.iter() {
+        gpu.wb(spec_addr + 1, *color as u8);
+        gpu.wb(spec_addr + 1, (*color >> 8) as u8);
+    }
+}
+
+fn run_until_line_rendered(gpu: &mut GPU) {
+    gpu.wb(0xFF40, gpu.rb(0xFF40) | LCDC_ENABLE);
+    gpu.step(OAM_SEARCH_CYCLES + PIXEL_TRANSFER_CYCLES);
+}
+
+#[test]
+fn test_palette_auto_increment() {
+    let mut gpu = cgb_gpu();
+    gpu.wb(0xFF68, 0x80 | 0x3E);
+    gpu.wb(0xFF69, 0x12);
+    gpu.wb(0xFF69, 0x34);
+    // Index wraps around after 0x3F and keeps auto-increment enabled.
+    assert_eq!(gpu.rb(0xFF68), 0x80 | 0x40);
+    gpu.wb(0xFF68, 0x3E);
+    assert_eq!(gpu.rb(0xFF69), 0x12);
+    gpu.wb(0xFF69, 0x56);
+    assert_eq!(gpu.rb(0xFF68), 0x40 | 0x3E);
+    assert_eq!(gpu.bg_color(7, 3), 0x3456);
+}
+
+#[test]
+fn test_vram_banks() {
+    let mut gpu = cgb_gpu();
+    gpu.wb(0x8000, 0x11);
+    gpu.wb(0xFF4F, 0x01);
+    assert_eq!(gpu.rb(0xFF4F), 0xFF);
+    assert_eq!(gpu.rb(0x8000), 0x00);
+    gpu.wb(0x8000, 0x22);
+    gpu.wb(0xFF4F, 0x00);
+    assert_eq!(gpu.rb(0x8000), 0x11);
+}
+
+#[test]
+fn test_vram_bank_ignored_in_dmg_mode() {
+    let mut gpu = GPU::new();
+    gpu.wb(0x8000, 0x11);
+    gpu.wb(0xFF4F, 0x01);
+    assert_eq!(gpu.rb(0x8000), 0x11);
+    assert_eq!(gpu.rb(0xFF4F), 0xFF);
+}
+
+#[test]
+fn test_bg_attributes_select_bank_palette_and_flip() {
+    let mut gpu = cgb_gpu();
+    write_palette(&mut gpu, 0xFF68, 2, [0x0000, 0x001F, 0x03E0, 0x7C00]);
+    // Tile 1 in bank 1: first row is color 1 on the leftmost pixel only.
+    gpu.wb(0xFF4F, 0x01);
+    gpu.wb(0x8010, 0b10000000);
+    gpu.wb(0x9800, BG_ATTR_BANK | BG_ATTR_X_FLIP | 2);
+    gpu.wb(0xFF4F, 0x00);
+    gpu.wb(0x9800, 0x01);
+    gpu.wb(0xFF40, LCDC_TILE_DATA);
+    run_until_line_rendered(&mut gpu);
+    let line = &gpu.get_framebuffer()[0..8];
+    assert_eq!(line[7], 0x001F);
+    assert_eq!(line[0], 0x0000);
+}
+
+#[test]
+fn test_cgb_objects_prioritized_by_oam_index() {
+    let mut gpu = cgb_gpu();
+    write_palette(&mut gpu, 0xFF6A, 0, [0, 0x001F, 0x001F, 0x001F]);
+    write_palette(&mut gpu, 0xFF6A, 1, [0, 0x03E0, 0x03E0, 0x03E0]);
+    // Tile 0 is solid color 1.
+    for row in 0..8 {
+        gpu.wb(0x8000 + row * 2, 0xFF);
+    }
+    // Object 0 at X=12 with palette 0, object 1 at X=8 with palette 1.
+    for (i, (x, palette)) in [(12u8, 0u8), (8, 1)].iter().enumerate() {
+        let entry = 0xFE00 + (i as u16) * 4;
+        gpu.wb(entry, 16);
+        gpu.wb(entry + 1, *x);
+        gpu.wb(entry + 2, 0x00);
+        gpu.wb(entry + 3, *palette);
+    }
+    gpu.wb(0xFF40, LCDC_OBJ_ENABLE);
+    run_until_line_rendered(&mut gpu);
+    // Overlapping pixels belong to object 0 even though object 1 has a lower X.
+    assert_eq!(gpu.get_framebuffer()[5], 0x001F);
+    assert_eq!(gpu.get_framebuffer()[1], 0x03E0);
+}
+
+#[test]
+fn test_bg_map_entries_ignore_the_vram_bank() {
+    let mut gpu = cgb_gpu();
+    gpu.wb(0x9800, 0x01);
+    gpu.wb(0xFF4F, 0x01);
+    gpu.wb(0x9800, BG_ATTR_BANK | 2);
+    assert_eq!(gpu.bg_tile_number(0x1800), 0x01);
+    assert_eq!(gpu.bg_attributes(0x1800), BG_ATTR_BANK | 2);
+}
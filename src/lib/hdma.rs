@@ -0,0 +1,82 @@
+// Game Boy Color VRAM DMA, copying blocks of 16 bytes to VRAM either all at
+// once (general purpose DMA) or one block per HBlank (HBlank DMA).
+pub const BLOCK_SIZE: u16 = 0x10;
+// Machine cycles the CPU is halted for each block, in normal speed mode.
+pub const BLOCK_CYCLES: u32 = 8;
+
+const HDMA5_HBLANK: u8 = 0b10000000;
+
+pub struct Hdma {
+    source: u16,
+    dest: u16,
+    // Blocks left to copy.
+    blocks: u8,
+    hblank_active: bool
+}
+
+impl Hdma {
+    pub fn new () -> Hdma {
+        Hdma {
+            source: 0,
+            dest: 0,
+            blocks: 0,
+            hblank_active: false
+        }
+    }
+
+    pub fn is_hblank_active (&self) -> bool {
+        self.hblank_active
+    }
+
+    pub fn rb (&self, addr: u16) -> u8 {
+        match addr {
+            // Reads 0xFF once done, bit 7 is clear while an HBlank DMA runs.
+            0xFF55 => {
+                let active = if self.hblank_active { 0 } else { HDMA5_HBLANK };
+                active | (self.blocks.wrapping_sub(1) & 0x7F)
+            }
+            _ => 0xFF
+        }
+    }
+
+    /// Writes an HDMA register. Returns the number of blocks to copy right
+    /// away when a general purpose DMA is started.
+    pub fn wb (&mut self, addr: u16, value: u8) -> u8 {
+        match addr {
+            0xFF51 => self.source = (self.source & 0x00F0) | (value as u16) << 8,
+            0xFF52 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.dest = (self.dest & 0x00F0) | ((value & 0x1F) as u16) << 8,
+            0xFF54 => self.dest = (self.dest & 0x1F00) | (value & 0xF0) as u16,
+            0xFF55 => {
+                if self.hblank_active && value & HDMA5_HBLANK == 0 {
+                    debug!("[HDMA] HBlank DMA cancelled with {} blocks left", self.blocks);
+                    self.hblank_active = false;
+                    return 0;
+                }
+                self.blocks = (value & 0x7F) + 1;
+                if value & HDMA5_HBLANK > 0 {
+                    debug!("[HDMA] HBlank DMA of {} blocks from 0x{:04x}", self.blocks, self.source);
+                    self.hblank_active = true;
+                    return 0;
+                }
+                debug!("[HDMA] General DMA of {} blocks from 0x{:04x}", self.blocks, self.source);
+                return self.blocks;
+            }
+            _ => {}
+        }
+        0
+    }
+
+    /// Returns the source and VRAM destination of the next block, and moves
+    /// past it.
+    pub fn next_block (&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.dest);
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.dest = (self.dest + BLOCK_SIZE) & 0x1FF0;
+        self.blocks -= 1;
+        if self.blocks == 0 {
+            self.hblank_active = false;
+        }
+        block
+    }
+}
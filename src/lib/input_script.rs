@@ -0,0 +1,126 @@
+use std::fs;
+use std::io;
+use super::joypad::Button;
+
+const MAX_PLAYERS: u8 = 4;
+
+/// Button presses to replay during an unattended run, one command per line:
+///
+/// ```text
+/// # Comments start with '#'
+/// frame 120: press START for 5 frames
+/// frame 300: press A+RIGHT
+/// frame 360: release RIGHT
+/// frame 400: player 2 press A
+/// ```
+///
+/// Frames are counted from 0 from the start of the run. A press without a
+/// duration holds the buttons until they are released. Commands apply to
+/// player 1 unless prefixed with `player N`, for the SGB multiplayer mode.
+pub struct InputScript {
+    // Sorted by frame; later events win over earlier ones.
+    events: Vec<InputEvent>
+}
+
+struct InputEvent {
+    frame: u64,
+    player: u8,
+    press: u8,
+    release: u8
+}
+
+fn invalid (line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+}
+
+fn parse_buttons (line: usize, names: &str) -> io::Result<u8> {
+    let mut mask = 0;
+    for name in names.split('+') {
+        match Button::from_name(name.trim()) {
+            Some(button) => mask |= button.mask(),
+            None => return Err(invalid(line, &format!("unknown button {}", name)))
+        }
+    }
+    Ok(mask)
+}
+
+impl InputScript {
+    pub fn from_file (file_name: &str) -> io::Result<InputScript> {
+        InputScript::parse(&fs::read_to_string(file_name)?)
+    }
+
+    pub fn parse (text: &str) -> io::Result<InputScript> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (time, command) = match line.find(':') {
+                Some(colon) => (&line[..colon], &line[colon + 1..]),
+                None => return Err(invalid(number, "expected 'frame N: command'"))
+            };
+            let frame = match time.trim().strip_prefix("frame").map(|n| n.trim().parse::<u64>()) {
+                Some(Ok(frame)) => frame,
+                _ => return Err(invalid(number, &format!("invalid frame '{}'", time.trim())))
+            };
+            let mut words: Vec<&str> = command.split_whitespace().collect();
+            let player = match words.as_slice() {
+                ["player", player, ..] => match player.parse::<u8>() {
+                    Ok(player @ 1..=MAX_PLAYERS) => {
+                        words.drain(..2);
+                        player - 1
+                    }
+                    _ => return Err(invalid(number, &format!("invalid player '{}'", player)))
+                },
+                _ => 0
+            };
+            match words.as_slice() {
+                ["press", buttons] => {
+                    events.push(InputEvent { frame, player, press: parse_buttons(number, buttons)?, release: 0 });
+                }
+                ["press", buttons, "for", duration, unit] if unit.starts_with("frame") => {
+                    let mask = parse_buttons(number, buttons)?;
+                    let duration = match duration.parse::<u64>() {
+                        Ok(duration) => duration,
+                        Err(_) => return Err(invalid(number, &format!("invalid duration '{}'", duration)))
+                    };
+                    events.push(InputEvent { frame, player, press: mask, release: 0 });
+                    events.push(InputEvent { frame: frame + duration, player, press: 0, release: mask });
+                }
+                ["release", buttons] => {
+                    events.push(InputEvent { frame, player, press: 0, release: parse_buttons(number, buttons)? });
+                }
+                _ => return Err(invalid(number, &format!("unknown command '{}'", command.trim())))
+            }
+        }
+        // The sort is stable, so events of the same frame keep the order of
+        // the script.
+        events.sort_by_key(|event| event.frame);
+        Ok(InputScript { events })
+    }
+
+    /// Number of players the script presses buttons for.
+    pub fn get_player_count (&self) -> u8 {
+        self.events.iter().map(|event| event.player + 1).max().unwrap_or(1)
+    }
+
+    /// Returns the mask of the buttons held down by player 1 during `frame`.
+    pub fn buttons_at (&self, frame: u64) -> u8 {
+        self.player_buttons_at(0, frame)
+    }
+
+    /// Returns the mask of the buttons held down by `player` (0 for player 1)
+    /// during `frame`.
+    pub fn player_buttons_at (&self, player: u8, frame: u64) -> u8 {
+        self.events.iter()
+            .take_while(|event| event.frame <= frame)
+            .filter(|event| event.player == player)
+            .fold(0, |pressed, event| (pressed & !event.release) | event.press)
+    }
+}
+
+#[cfg(test)]
+#[path = "./input_script_test.rs"]
+mod input_script_test;
@@ -0,0 +1,48 @@
+use super::*;
+
+#[test]
+fn test_press_for_frames() {
+    let script = InputScript::parse("frame 120: press START for 5 frames\n").unwrap();
+    assert_eq!(script.buttons_at(119), 0);
+    assert_eq!(script.buttons_at(120), Button::Start.mask());
+    assert_eq!(script.buttons_at(124), Button::Start.mask());
+    assert_eq!(script.buttons_at(125), 0);
+}
+
+#[test]
+fn test_hold_and_release() {
+    let script = InputScript::parse("
+        # Walk right while jumping once
+        frame 10: press RIGHT
+        frame 20: press a+b for 1 frame  # both at once
+        frame 30: release right
+    ").unwrap();
+    let right = Button::Right.mask();
+    assert_eq!(script.buttons_at(15), right);
+    assert_eq!(script.buttons_at(20), right | Button::A.mask() | Button::B.mask());
+    assert_eq!(script.buttons_at(21), right);
+    assert_eq!(script.buttons_at(30), 0);
+}
+
+#[test]
+fn test_errors_report_line() {
+    let error = InputScript::parse("frame 1: press START\nframe x: press A").err().unwrap();
+    assert_eq!(error.to_string(), "line 2: invalid frame 'frame x'");
+    assert!(InputScript::parse("frame 1: press TURBO").is_err());
+    assert!(InputScript::parse("frame 1: jump").is_err());
+    assert!(InputScript::parse("press A").is_err());
+}
+
+#[test]
+fn test_players() {
+    let script = InputScript::parse("
+        frame 0: press A
+        frame 5: player 3 press START for 2 frames
+    ").unwrap();
+    assert_eq!(script.get_player_count(), 3);
+    assert_eq!(script.buttons_at(5), Button::A.mask());
+    assert_eq!(script.player_buttons_at(2, 5), Button::Start.mask());
+    assert_eq!(script.player_buttons_at(2, 7), 0);
+    assert_eq!(script.player_buttons_at(1, 5), 0);
+    assert!(InputScript::parse("frame 1: player 5 press A").is_err());
+}
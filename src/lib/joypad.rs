@@ -0,0 +1,113 @@
+pub const INT_JOYPAD: u8 = 0b10000;
+
+// Select lines of P1, active low.
+const SELECT_DIRECTIONS: u8 = 0b010000;
+const SELECT_BUTTONS: u8 = 0b100000;
+
+/// The eight buttons. Directions are the low nibble of a button mask and
+/// the action buttons the high nibble, in the order of the P1 input lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right, Button::Left, Button::Up, Button::Down,
+        Button::A, Button::B, Button::Select, Button::Start
+    ];
+
+    pub fn from_name (name: &str) -> Option<Button> {
+        Button::ALL.iter().copied().find(|button| button.get_name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_name (self) -> &'static str {
+        match self {
+            Button::Right => "RIGHT",
+            Button::Left => "LEFT",
+            Button::Up => "UP",
+            Button::Down => "DOWN",
+            Button::A => "A",
+            Button::B => "B",
+            Button::Select => "SELECT",
+            Button::Start => "START"
+        }
+    }
+
+    pub fn mask (self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+pub struct Joypad {
+    select: u8,
+    // Mask of the buttons held down.
+    pressed: u8
+}
+
+impl Joypad {
+    pub fn new () -> Joypad {
+        debug!("Creating new Joypad...");
+        Joypad {
+            select: SELECT_DIRECTIONS | SELECT_BUTTONS,
+            pressed: 0
+        }
+    }
+
+    pub fn get_pressed (&self) -> u8 {
+        self.pressed
+    }
+
+    pub fn rb (&self) -> u8 {
+        0xC0 | self.select | self.input_lines()
+    }
+
+    /// Writes the select lines. Returns the interrupt flags to request, as
+    /// selecting a group with a button held pulls an input line low.
+    pub fn wb (&mut self, value: u8) -> u8 {
+        let before = self.input_lines();
+        self.select = value & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+        self.interrupts(before)
+    }
+
+    /// Sets the mask of the buttons held down and returns the interrupt
+    /// flags to request.
+    pub fn set_pressed (&mut self, pressed: u8) -> u8 {
+        let before = self.input_lines();
+        self.pressed = pressed;
+        self.interrupts(before)
+    }
+
+    // The low nibble of P1: a line is low if a button is pressed in one of
+    // the selected groups.
+    fn input_lines (&self) -> u8 {
+        let mut lines = 0x0F;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            lines &= !self.pressed & 0x0F;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            lines &= !(self.pressed >> 4) & 0x0F;
+        }
+        lines
+    }
+
+    // The interrupt is requested when any input line goes from high to low.
+    fn interrupts (&self, before: u8) -> u8 {
+        if before & !self.input_lines() & 0x0F > 0 {
+            INT_JOYPAD
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./joypad_test.rs"]
+mod joypad_test;
@@ -0,0 +1,36 @@
+use super::*;
+
+#[test]
+fn test_select_lines() {
+    let mut joypad = Joypad::new();
+    joypad.set_pressed(Button::Start.mask() | Button::Left.mask());
+    assert_eq!(joypad.rb(), 0xFF);
+    joypad.wb(0x20);
+    assert_eq!(joypad.rb(), 0xE0 | 0b1101);
+    joypad.wb(0x10);
+    assert_eq!(joypad.rb(), 0xD0 | 0b0111);
+    joypad.wb(0x00);
+    assert_eq!(joypad.rb(), 0xC0 | 0b0101);
+}
+
+#[test]
+fn test_interrupt_on_falling_edge() {
+    let mut joypad = Joypad::new();
+    joypad.wb(0x10);
+    // Directions are not selected.
+    assert_eq!(joypad.set_pressed(Button::Up.mask()), 0);
+    assert_eq!(joypad.set_pressed(Button::Up.mask() | Button::A.mask()), INT_JOYPAD);
+    // Releasing a button raises the line again.
+    assert_eq!(joypad.set_pressed(Button::Up.mask()), 0);
+    // Selecting a group with a button held pulls its line low.
+    assert_eq!(joypad.wb(0x20), INT_JOYPAD);
+    assert_eq!(joypad.wb(0x20), 0);
+}
+
+#[test]
+fn test_button_names() {
+    assert_eq!(Button::from_name("start"), Some(Button::Start));
+    assert_eq!(Button::from_name("Down"), Some(Button::Down));
+    assert_eq!(Button::from_name("X"), None);
+    assert_eq!(Button::Select.mask(), 0x40);
+}
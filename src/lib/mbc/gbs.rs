@@ -0,0 +1,61 @@
+use super::MBC;
+
+const BANK_SIZE: usize = 0x4000;
+// Restart vectors jump to the same offset from the load address.
+const RST_VECTORS: [u16; 8] = [0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38];
+const OPCODE_JP: u8 = 0xC3;
+
+/// Memory map of a GBS sound rip: the code is placed at its load address,
+/// and writes to 0x2000-0x3FFF select the bank at 0x4000-0x7FFF like an MBC1.
+pub struct GbsRom {
+    data: Vec<u8>,
+    bank: usize
+}
+
+impl GbsRom {
+    pub fn from_code (load_addr: u16, code: &[u8]) -> GbsRom {
+        debug!("Creating new GBS ROM mapping at 0x{:04x}...", load_addr);
+        let mut data = vec![0; load_addr as usize];
+        data.extend_from_slice(code);
+        let bank_count = std::cmp::max(data.len().div_ceil(BANK_SIZE), 2);
+        data.resize(bank_count * BANK_SIZE, 0xFF);
+        if load_addr >= 0x40 {
+            for vector in RST_VECTORS.iter() {
+                let target = load_addr + vector;
+                let addr = *vector as usize;
+                data[addr] = OPCODE_JP;
+                data[addr + 1] = target as u8;
+                data[addr + 2] = (target >> 8) as u8;
+            }
+        }
+        GbsRom {
+            data,
+            bank: 1
+        }
+    }
+
+    fn bank_count (&self) -> usize {
+        self.data.len() / BANK_SIZE
+    }
+}
+
+impl MBC for GbsRom {
+    fn read(&self, addr: u16) -> u8 {
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            _ => self.bank * BANK_SIZE + (addr as usize - BANK_SIZE)
+        };
+        self.data[offset]
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        if let 0x2000..=0x3FFF = addr {
+            let bank = std::cmp::max(byte as usize, 1);
+            self.bank = bank % self.bank_count();
+        }
+    }
+
+    fn get_type(&self) -> &str {
+        "GBS"
+    }
+}
@@ -30,11 +30,9 @@ impl MBC for MBC0 {
         self.data[addr as usize]
     }
 
-    fn write(&mut self, addr: u16, byte: u8) {
-        if (addr as u32) >= self.rom_size {
-            panic!("MBC::MBC0 tried to write to unaccessible memory location")
-        } 
-        self.data[addr as usize] = byte
+    fn write(&mut self, _addr: u16, _byte: u8) {
+        // There are no registers on a ROM only cartridge and the ROM itself
+        // cannot be written, so writes are ignored.
     }
 
     fn get_type(&self) -> &str {
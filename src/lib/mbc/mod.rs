@@ -1,4 +1,6 @@
+pub mod gbs;
 pub mod mbc0;
+use gbs::GbsRom;
 use mbc0::MBC0;
 
 pub const ADDR_CARTRIDGE_TYPE: u16 = 0x0147;
@@ -16,25 +18,29 @@ pub trait MBC {
 }
 
 pub enum MbcType {
-    MBC0(MBC0)
+    MBC0(MBC0),
+    GBS(GbsRom)
 }
 
 impl MBC for MbcType {
     fn read(&self, addr: u16) -> u8 {
         match *self {
-            MbcType::MBC0(ref mbc0) => mbc0.read(addr)
+            MbcType::MBC0(ref mbc0) => mbc0.read(addr),
+            MbcType::GBS(ref gbs) => gbs.read(addr)
         }
     }
 
     fn write(&mut self, addr: u16, byte: u8) {
         match *self {
-            MbcType::MBC0(ref mut mbc0) => mbc0.write(addr, byte)
+            MbcType::MBC0(ref mut mbc0) => mbc0.write(addr, byte),
+            MbcType::GBS(ref mut gbs) => gbs.write(addr, byte)
         }
     }
 
     fn get_type(&self) -> &str {
         match * self {
-            MbcType::MBC0(ref mbc0) => mbc0.get_type()
+            MbcType::MBC0(ref mbc0) => mbc0.get_type(),
+            MbcType::GBS(ref gbs) => gbs.get_type()
         }
     }
 }
@@ -1,15 +1,89 @@
 //use std::convert::TryFrom;
+use std::cell::Cell;
+use std::hash::{Hash, Hasher};
+use super::apu;
+use super::colorization;
+use super::gpu;
+use super::hdma;
+use super::joypad;
+use super::model::Model;
 use super::ram;
+use super::serial;
+use super::sgb;
+use super::timer;
+use super::watchpoint::{Access, WatchHit, Watchpoint};
 use super::mbc::MBCBuilder;
 use super::mbc::MBC;
 use super::mbc::MbcType;
 
-const MEMORY_SIZE: usize = 65535;
+const MEMORY_SIZE: usize = 0x10000;
+
+const ADDR_CGB_FLAG: usize = 0x0143;
+const CGB_FLAG_SUPPORTED: u8 = 0x80;
+const ADDR_SGB_FLAG: u16 = 0x0146;
+const SGB_FLAG_SUPPORTED: u8 = 0x03;
+const ADDR_OLD_LICENSEE: u16 = 0x014B;
+// Old licensee code telling that the new licensee code is used, which the
+// SGB requires as well.
+const OLD_LICENSEE_NEW: u8 = 0x33;
+const ADDR_LOGO: u16 = 0x0104;
+const HEADER_END: u16 = 0x0150;
+const LOGO_SIZE: u16 = 48;
+
+// Tiles and map entries the DMG boot ROM leaves in VRAM.
+const ADDR_LOGO_TILES: u16 = 0x8010;
+const ADDR_REGISTERED_TILE: u16 = 0x8190;
+const REGISTERED_MARK: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+const ADDR_LOGO_MAP: [u16; 2] = [0x9904, 0x9924];
+const ADDR_REGISTERED_MAP: u16 = 0x9910;
+
+pub const BOOT_ROM_DMG_SIZE: usize = 0x100;
+pub const BOOT_ROM_CGB_SIZE: usize = 0x900;
+
+const ADDR_TAC: u16 = 0xFF07;
+const ADDR_IF: u16 = 0xFF0F;
+const ADDR_DMA: u16 = 0xFF46;
+const ADDR_KEY0: u16 = 0xFF4C;
+const ADDR_KEY1: u16 = 0xFF4D;
+const ADDR_BOOT: u16 = 0xFF50;
+const ADDR_SVBK: u16 = 0xFF70;
+
+const KEY0_DMG_COMPATIBILITY: u8 = 0b100;
+const KEY1_PREPARE: u8 = 0b1;
+// Machine cycles the CPU stays stopped while switching speed.
+const SPEED_SWITCH_CYCLES: u32 = 2050;
 
 pub struct MMU {
     mbc: Option<MbcType>,
     ram: ram::RAM,
-    mmap: [u8; 0xffff]
+    gpu: gpu::GPU,
+    apu: apu::APU,
+    joypad: joypad::Joypad,
+    serial: serial::Serial,
+    timer: timer::Timer,
+    hdma: hdma::Hdma,
+    // Present on SGB models when the cartridge header enables SGB functions.
+    sgb: Option<sgb::Sgb>,
+    // Overlays the cartridge until a write to 0xFF50.
+    boot_rom: Option<Vec<u8>>,
+    // Set by the CGB boot ROM to run a DMG game in compatibility mode.
+    key0: u8,
+    model: Model,
+    // Whether the cartridge header declares Game Boy Color support.
+    cgb_supported: bool,
+    cgb: bool,
+    double_speed: bool,
+    speed_switch_prepared: bool,
+    // Machine cycles the CPU has to wait for, e.g. during VRAM DMA.
+    stall_cycles: u32,
+    // Odd machine cycle left over in double speed mode, where the PPU and
+    // APU only advance every other CPU cycle.
+    half_cycle: u32,
+    // Only checked on bus accesses when there are any.
+    watchpoints: Vec<Watchpoint>,
+    // Set by `rb`, which cannot borrow the MMU mutably.
+    watch_hit: Cell<Option<WatchHit>>,
+    mmap: [u8; MEMORY_SIZE]
 }
 
 impl MMU {
@@ -24,6 +98,11 @@ impl MMU {
             Some(_mbc) => debug!("MBC created for ROM. Type: {}.", _mbc.get_type()),
             None => println!("No MBC could be created for this rom.") 
         }
+        if rom.len() > ADDR_CGB_FLAG && rom[ADDR_CGB_FLAG] & CGB_FLAG_SUPPORTED > 0 {
+            debug!("ROM supports the Game Boy Color (flag 0x{:02x}).", rom[ADDR_CGB_FLAG]);
+            self.cgb_supported = true;
+            self.set_cgb_mode(true);
+        }
     }
 
     /*fn set(&self, location: u32, value: u8) -> u8 {
@@ -35,11 +114,347 @@ impl MMU {
 
     }
 
+    /// Replaces the cartridge, e.g. with the memory map of a sound rip.
+    pub fn set_mbc (&mut self, mbc: MbcType) {
+        debug!("MBC set. Type: {}.", mbc.get_type());
+        self.mbc = Some(mbc);
+    }
+
+    pub fn set_cgb_mode (&mut self, cgb: bool) {
+        self.cgb = cgb;
+        self.gpu.set_cgb_mode(cgb);
+        self.serial.set_cgb_mode(cgb);
+        self.ram.set_bank(1);
+        self.set_double_speed(false);
+    }
+
+    pub fn is_cgb_supported (&self) -> bool {
+        self.cgb_supported
+    }
+
+    pub fn get_model (&self) -> Model {
+        self.model
+    }
+
+    /// Puts the hardware in the state the boot ROM of `model` leaves it in.
+    /// Color models only run the game in CGB mode if its header supports it.
+    pub fn set_model (&mut self, model: Model) {
+        debug!("Hardware model set. Model: {}.", model.get_name());
+        self.model = model;
+        self.boot_rom = None;
+        self.key0 = 0;
+        self.sgb = if model.is_sgb() && self.rb(ADDR_SGB_FLAG) == SGB_FLAG_SUPPORTED
+                && self.rb(ADDR_OLD_LICENSEE) == OLD_LICENSEE_NEW {
+            Some(sgb::Sgb::new())
+        } else {
+            None
+        };
+        self.set_cgb_mode(model.is_cgb() && self.cgb_supported);
+        self.apu.set_cgb(model.is_cgb());
+        self.timer.set_counter(model.boot_div_counter(self.cgb));
+
+        self.wb(0xFF00, 0x00);
+        self.wb(0xFF02, 0x00);
+        self.mmap[ADDR_TAC as usize] = 0xF8;
+        self.mmap[ADDR_IF as usize] = 0xE1;
+        self.mmap[ADDR_DMA as usize] = if model.is_cgb() { 0x00 } else { 0xFF };
+        self.mmap[0xFFFF] = 0x00;
+
+        self.wb(0xFF26, 0x00);
+        self.wb(0xFF26, 0x80);
+        self.wb(0xFF10, 0x00);
+        self.wb(0xFF11, 0x80);
+        self.wb(0xFF12, 0xF3);
+        self.wb(0xFF24, 0x77);
+        self.wb(0xFF25, 0xF3);
+        if !model.is_sgb() {
+            // The last note of the boot sound is still fading out.
+            self.wb(0xFF13, 0xC1);
+            self.wb(0xFF14, 0x87);
+        }
+
+        self.wb(0xFF40, 0x91);
+        self.wb(0xFF47, 0xFC);
+        for addr in 0x8000..=0x9FFF {
+            self.gpu.wb(addr, 0x00);
+        }
+        if matches!(model, Model::Dmg0 | Model::Dmg | Model::Mgb) {
+            self.load_boot_logo();
+        }
+        if model.is_cgb() && !self.cgb {
+            self.load_compatibility_palettes();
+        }
+    }
+
+    // Colors a DMG game with the palettes the CGB boot ROM would pick for it.
+    fn load_compatibility_palettes (&mut self) {
+        let header: Vec<u8> = (0..HEADER_END).map(|addr| self.rb(addr)).collect();
+        let palettes = colorization::palettes_for(&header);
+        debug!("Compatibility palettes loaded. Title checksum: {:#04X}.", colorization::title_checksum(&header));
+        self.gpu.set_compatibility_mode(true);
+        self.gpu.set_compatibility_palettes(palettes.bg, palettes.obj0, palettes.obj1);
+    }
+
+    /// Maps `boot_rom` over the cartridge and leaves the hardware as it is
+    /// at power on, for the boot ROM to initialize. A CGB boot ROM runs in
+    /// CGB mode and picks the mode of the game itself.
+    pub fn set_boot_rom (&mut self, boot_rom: Vec<u8>) {
+        debug!("Boot ROM mapped. Size: {} bytes.", boot_rom.len());
+        self.set_cgb_mode(boot_rom.len() == BOOT_ROM_CGB_SIZE);
+        self.boot_rom = Some(boot_rom);
+        self.key0 = 0;
+        self.timer.set_counter(0);
+        self.wb(0xFF26, 0x00);
+        self.wb(0xFF40, 0x00);
+        for addr in [0xFF42, 0xFF43, 0xFF47, 0xFF48, 0xFF49] {
+            self.wb(addr, 0x00);
+        }
+        for addr in 0x8000..=0x9FFF {
+            self.gpu.wb(addr, 0x00);
+        }
+        self.mmap[ADDR_TAC as usize] = 0x00;
+        self.mmap[ADDR_IF as usize] = 0x00;
+    }
+
+    fn unmap_boot_rom (&mut self) {
+        if self.boot_rom.take().is_some() {
+            debug!("Boot ROM unmapped.");
+            if self.cgb && self.key0 & KEY0_DMG_COMPATIBILITY > 0 {
+                self.set_cgb_mode(false);
+                // The boot ROM has already written the palettes.
+                self.gpu.set_compatibility_mode(true);
+            }
+        }
+    }
+
+    // Copies the logo from the cartridge header to VRAM the way the DMG boot
+    // ROM does: every nibble becomes a row of 4 pixels, doubled in both
+    // directions, followed by the registered mark.
+    fn load_boot_logo (&mut self) {
+        for i in 0..LOGO_SIZE {
+            let byte = self.rb(ADDR_LOGO + i);
+            for (half, nibble) in [byte >> 4, byte & 0x0F].iter().enumerate() {
+                let doubled = (0..4).fold(0u8, |row, bit| row | ((((nibble >> bit) & 1) * 0b11) << (bit * 2)));
+                let addr = ADDR_LOGO_TILES + i * 8 + half as u16 * 4;
+                self.gpu.wb(addr, doubled);
+                self.gpu.wb(addr + 2, doubled);
+            }
+        }
+        for (row, value) in REGISTERED_MARK.iter().enumerate() {
+            self.gpu.wb(ADDR_REGISTERED_TILE + row as u16 * 2, *value);
+        }
+        for (line, addr) in ADDR_LOGO_MAP.iter().enumerate() {
+            for column in 0..12 {
+                self.gpu.wb(addr + column, (line as u16 * 12 + column + 1) as u8);
+            }
+        }
+        self.gpu.wb(ADDR_REGISTERED_MAP, 25);
+    }
+
+    pub fn get_gpu (&self) -> &gpu::GPU {
+        &self.gpu
+    }
+
+    pub fn get_sgb (&self) -> Option<&sgb::Sgb> {
+        self.sgb.as_ref()
+    }
+
+    pub fn get_apu_mut (&mut self) -> &mut apu::APU {
+        &mut self.apu
+    }
+
+    pub fn get_serial_mut (&mut self) -> &mut serial::Serial {
+        &mut self.serial
+    }
+
+    /// Returns the bytes sent over the serial port since the last call.
+    pub fn take_serial_output (&mut self) -> Vec<u8> {
+        self.serial.take_output()
+    }
+
+    /// Sets the mask of the buttons held down (see `joypad::Button`).
+    pub fn set_buttons (&mut self, pressed: u8) {
+        self.set_player_buttons(0, pressed);
+    }
+
+    /// Sets the buttons of one of the four controllers of an SGB. Only the
+    /// first one exists on other models.
+    pub fn set_player_buttons (&mut self, player: u8, pressed: u8) {
+        let current = match &mut self.sgb {
+            Some(sgb) => {
+                sgb.set_buttons(player, pressed);
+                sgb.get_player()
+            }
+            None => 0
+        };
+        if player == current {
+            let interrupts = self.joypad.set_pressed(pressed);
+            self.request_interrupt(interrupts);
+        }
+    }
+
+    /// Returns the buttons of one of the four controllers of an SGB, none
+    /// pressed for the ones other models lack.
+    pub fn get_player_buttons (&self, player: u8) -> u8 {
+        match &self.sgb {
+            Some(sgb) => sgb.get_buttons(player),
+            None if player == 0 => self.joypad.get_pressed(),
+            None => 0
+        }
+    }
+
+    fn set_double_speed (&mut self, double_speed: bool) {
+        self.double_speed = double_speed;
+        self.speed_switch_prepared = false;
+        self.half_cycle = 0;
+        self.timer.set_double_speed(double_speed);
+    }
+
+    /// Executes STOP, which switches the CPU speed on the Game Boy Color if
+    /// prepared through KEY1. The low power mode is not emulated.
+    pub fn stop (&mut self) {
+        for _ in 0..self.timer.wb(0xFF04, 0) {
+            self.apu.clock_frame_sequencer();
+        }
+        if self.cgb && self.speed_switch_prepared {
+            let double_speed = !self.double_speed;
+            debug!("Switching to {} speed.", if double_speed { "double" } else { "normal" });
+            self.set_double_speed(double_speed);
+            self.stall_cycles += SPEED_SWITCH_CYCLES;
+        }
+    }
+
+    /// Returns the machine cycles the CPU has to wait for since the last call,
+    /// during which the rest of the system keeps running.
+    pub fn take_stall_cycles (&mut self) -> u32 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    /// Advances the devices attached to the bus by the given machine cycles.
+    /// The timer and serial port run at the CPU speed, the PPU and APU at the
+    /// same rate in both speed modes.
+    pub fn step (&mut self, cycles: u32) {
+        let interrupts = self.serial.step(cycles);
+        self.request_interrupt(interrupts);
+        for _ in 0..self.timer.step(cycles) {
+            self.apu.clock_frame_sequencer();
+        }
+        let cycles = if self.double_speed {
+            self.half_cycle += cycles;
+            let cycles = self.half_cycle / 2;
+            self.half_cycle %= 2;
+            cycles
+        } else {
+            cycles
+        };
+        let hblanks = self.gpu.get_hblank_count();
+        let frame = self.gpu.get_frame_count();
+        let interrupts = self.gpu.step(cycles);
+        self.request_interrupt(interrupts);
+        if let Some(sgb) = &mut self.sgb {
+            if self.gpu.get_frame_count() != frame {
+                sgb.on_frame(&self.gpu);
+            }
+        }
+        if self.hdma.is_hblank_active() && self.gpu.get_hblank_count() != hblanks {
+            self.vram_dma_block();
+        }
+        self.apu.step(cycles);
+    }
+
+    /// Feeds every byte readable on the bus and the PPU output to `hasher`.
+    pub fn hash_state (&self, hasher: &mut impl Hasher) {
+        for addr in 0..=0xFFFF {
+            hasher.write_u8(self.read_byte(addr));
+        }
+        self.gpu.get_framebuffer().hash(hasher);
+        hasher.write_u64(self.gpu.get_frame_count());
+    }
+
+    pub fn request_interrupt (&mut self, flags: u8) {
+        self.mmap[ADDR_IF as usize] |= flags;
+    }
+
+    pub fn add_watchpoint (&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint (&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_watchpoints (&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the first watchpoint hit since the last call, if any.
+    pub fn take_watch_hit (&self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn record_watch_hit (&self, hit: WatchHit) {
+        if self.watch_hit.get().is_none() {
+            self.watch_hit.set(Some(hit));
+        }
+    }
+
     pub fn rb (&self, addr: u16) -> u8 {
+        let value = self.read_byte(addr);
+        if !self.watchpoints.is_empty() {
+            if let Some(index) = self.watchpoints.iter().position(|watchpoint| watchpoint.check_read(addr, value)) {
+                self.record_watch_hit(WatchHit { index, access: Access::Read, addr, value, old: value });
+            }
+        }
+        value
+    }
+
+    pub fn wb (&mut self, addr: u16, value: u8) -> u8 {
+        if self.watchpoints.is_empty() {
+            return self.write_byte(addr, value);
+        }
+        let old = self.read_byte(addr);
+        self.write_byte(addr, value);
+        let new = self.read_byte(addr);
+        if let Some(index) = self.watchpoints.iter().position(|watchpoint| watchpoint.check_write(addr, value, old, new)) {
+            let access = self.watchpoints[index].access;
+            let value = if access == Access::Change { new } else { value };
+            self.record_watch_hit(WatchHit { index, access, addr, value, old });
+        }
+        value
+    }
+
+    fn read_byte (&self, addr: u16) -> u8 {
         match &self.mbc {
             Some(_mbc) => {
+                if let Some(boot_rom) = &self.boot_rom {
+                    // The CGB boot ROM leaves a gap for the cartridge header.
+                    let index = addr as usize;
+                    if index < BOOT_ROM_DMG_SIZE || (0x200..boot_rom.len()).contains(&index) {
+                        return boot_rom[index];
+                    }
+                }
                 return match addr {
-                    0x0000..=0x3FFF => _mbc.read(addr),
+                    0x0000..=0x7FFF => _mbc.read(addr),
+                    0xFF00 => match &self.sgb {
+                        // With no group selected, the SGB tells which
+                        // controller is read.
+                        Some(sgb) if self.joypad.rb() & 0x30 == 0x30 => 0xF0 | sgb.get_joypad_id(),
+                        _ => self.joypad.rb()
+                    },
+                    0xFF01 | 0xFF02 => self.serial.rb(addr),
+                    0xFF04 => self.timer.rb(addr),
+                    0xFF10..=0xFF26 | 0xFF30..=0xFF3F => self.apu.rb(addr),
+                    0xC000..=0xFDFF => self.ram.rb(addr),
+                    ADDR_KEY1 | 0xFF51..=0xFF55 | ADDR_SVBK if !self.cgb => 0xFF,
+                    ADDR_KEY1 => 0x7E | (self.double_speed as u8) << 7 | self.speed_switch_prepared as u8,
+                    0xFF51..=0xFF55 => self.hdma.rb(addr),
+                    ADDR_SVBK => 0xF8 | self.ram.get_bank(),
+                    0x8000..=0x9FFF | 0xFE00..=0xFE9F | 0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF4F | 0xFF68..=0xFF6C => self.gpu.rb(addr),
                     _ => self.mmap[addr as usize]
                 }
             },
@@ -47,10 +462,76 @@ impl MMU {
         };
     }
 
-    pub fn wb (&mut self, addr: u16, value: u8) -> u8 {
-        self.mmap[addr as usize] = value;
+    fn write_byte (&mut self, addr: u16, value: u8) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => {
+                if let Some(mbc) = &mut self.mbc {
+                    mbc.write(addr, value);
+                }
+            }
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F | 0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF4F | 0xFF68..=0xFF6C => self.gpu.wb(addr, value),
+            0xFF00 => {
+                let interrupts = self.joypad.wb(value);
+                self.request_interrupt(interrupts);
+                if let Some(sgb) = &mut self.sgb {
+                    if sgb.write_joypad(value) {
+                        let pressed = sgb.get_buttons(sgb.get_player());
+                        let interrupts = self.joypad.set_pressed(pressed);
+                        self.request_interrupt(interrupts);
+                    }
+                }
+            }
+            0xFF01 | 0xFF02 => self.serial.wb(addr, value),
+            0xFF04 => {
+                for _ in 0..self.timer.wb(addr, value) {
+                    self.apu.clock_frame_sequencer();
+                }
+            }
+            0xFF10..=0xFF26 | 0xFF30..=0xFF3F => self.apu.wb(addr, value),
+            0xC000..=0xFDFF => self.ram.wb(addr, value),
+            ADDR_KEY1 | 0xFF51..=0xFF55 | ADDR_SVBK if !self.cgb => {}
+            ADDR_KEY1 => self.speed_switch_prepared = value & KEY1_PREPARE > 0,
+            0xFF51..=0xFF55 => {
+                for _ in 0..self.hdma.wb(addr, value) {
+                    self.vram_dma_block();
+                }
+                // With the LCD off, an HBlank DMA copies its first block at
+                // once.
+                if addr == 0xFF55 && self.hdma.is_hblank_active() && self.gpu.rb(0xFF40) & 0x80 == 0 {
+                    self.vram_dma_block();
+                }
+            }
+            ADDR_SVBK => self.ram.set_bank(value & 0x07),
+            // KEY0 can only be written by the boot ROM.
+            ADDR_KEY0 if self.boot_rom.is_some() => self.key0 = value,
+            ADDR_BOOT if value > 0 => self.unmap_boot_rom(),
+            ADDR_DMA => {
+                self.mmap[addr as usize] = value;
+                self.oam_dma(value);
+            }
+            _ => self.mmap[addr as usize] = value
+        }
         return value;
     }
+
+    // Copies the next block of a VRAM DMA and halts the CPU meanwhile.
+    fn vram_dma_block (&mut self) {
+        let (source, dest) = self.hdma.next_block();
+        for offset in 0..hdma::BLOCK_SIZE {
+            let value = self.rb(source.wrapping_add(offset));
+            self.gpu.wb(dest + offset, value);
+        }
+        self.stall_cycles += hdma::BLOCK_CYCLES << self.double_speed as u32;
+    }
+
+    // OAM DMA is performed instantly instead of over 160 machine cycles.
+    fn oam_dma (&mut self, source_hi: u8) {
+        let source = (source_hi as u16) << 8;
+        for offset in 0..0xA0 {
+            let value = self.rb(source + offset);
+            self.gpu.wb(0xFE00 + offset, value);
+        }
+    }
 }
 
 impl Default for MMU {
@@ -58,7 +539,29 @@ impl Default for MMU {
         MMU {
             mbc: None,
             ram: ram::RAM::new(),
-            mmap: [0; 0xffff]
+            gpu: gpu::GPU::new(),
+            apu: apu::APU::new(),
+            joypad: joypad::Joypad::new(),
+            serial: serial::Serial::new(),
+            timer: timer::Timer::new(),
+            hdma: hdma::Hdma::new(),
+            sgb: None,
+            boot_rom: None,
+            key0: 0,
+            model: Model::Dmg,
+            cgb_supported: false,
+            cgb: false,
+            double_speed: false,
+            speed_switch_prepared: false,
+            stall_cycles: 0,
+            half_cycle: 0,
+            watchpoints: Vec::new(),
+            watch_hit: Cell::new(None),
+            mmap: [0; MEMORY_SIZE]
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+#[path = "./mmu_test.rs"]
+mod mmu_test;
@@ -0,0 +1,213 @@
+use super::*;
+
+fn mmu_from_rom(cgb_flag: u8) -> MMU {
+    let mut rom = vec![0; 1024];
+    rom[ADDR_CGB_FLAG] = cgb_flag;
+    let mut mmu = MMU::new();
+    mmu.read_rom(&rom);
+    mmu
+}
+
+#[test]
+fn test_cgb_flag_detection() {
+    assert!(!mmu_from_rom(0x00).get_gpu().is_cgb_mode());
+    assert!(mmu_from_rom(0x80).get_gpu().is_cgb_mode());
+    assert!(mmu_from_rom(0xC0).get_gpu().is_cgb_mode());
+}
+
+#[test]
+fn test_model_boot_state() {
+    let mut mmu = mmu_from_rom(0x00);
+    mmu.set_model(Model::Dmg);
+    assert_eq!(mmu.rb(0xFF00), 0xCF);
+    assert_eq!(mmu.rb(0xFF04), 0xAB);
+    assert_eq!(mmu.rb(0xFF07), 0xF8);
+    assert_eq!(mmu.rb(0xFF0F), 0xE1);
+    assert_eq!(mmu.rb(0xFF24), 0x77);
+    assert_eq!(mmu.rb(0xFF25), 0xF3);
+    assert_eq!(mmu.rb(0xFF26), 0xF1);
+    assert_eq!(mmu.rb(0xFF40), 0x91);
+    assert_eq!(mmu.rb(0xFF46), 0xFF);
+    assert_eq!(mmu.rb(0xFF47), 0xFC);
+
+    // The SGB boot ROM plays no sound.
+    mmu.set_model(Model::Sgb);
+    assert_eq!(mmu.rb(0xFF26) & 0x0F, 0x00);
+    // A color model runs DMG games in compatibility mode.
+    mmu.set_model(Model::Cgb);
+    assert!(!mmu.get_gpu().is_cgb_mode());
+    assert!(mmu.get_gpu().has_color_output());
+    assert_eq!(mmu.rb(0xFF46), 0x00);
+}
+
+#[test]
+fn test_dmg_boot_logo_in_vram() {
+    let mut rom = vec![0; 1024];
+    rom[ADDR_LOGO as usize] = 0xCE;
+    let mut mmu = MMU::new();
+    mmu.read_rom(&rom);
+    mmu.set_model(Model::Dmg);
+    assert_eq!(mmu.rb(0x8010), 0xF0);
+    assert_eq!(mmu.rb(0x8011), 0x00);
+    assert_eq!(mmu.rb(0x8012), 0xF0);
+    assert_eq!(mmu.rb(0x8014), 0xFC);
+    assert_eq!(mmu.rb(0x8190), 0x3C);
+    assert_eq!(mmu.rb(0x9904), 1);
+    assert_eq!(mmu.rb(0x990F), 12);
+    assert_eq!(mmu.rb(0x9924), 13);
+    assert_eq!(mmu.rb(0x992F), 24);
+    assert_eq!(mmu.rb(0x9910), 25);
+}
+
+#[test]
+fn test_boot_rom_overlay_until_unmapped() {
+    let mut rom = vec![0; 0x1000];
+    rom[0x0000] = 0x11;
+    rom[0x0100] = 0x22;
+    rom[0x0200] = 0x33;
+    let mut mmu = MMU::new();
+    mmu.read_rom(&rom);
+    mmu.set_boot_rom(vec![0xAA; BOOT_ROM_CGB_SIZE]);
+    assert!(mmu.get_gpu().is_cgb_mode());
+    assert_eq!(mmu.rb(0x0000), 0xAA);
+    // The cartridge header shows through.
+    assert_eq!(mmu.rb(0x0100), 0x22);
+    assert_eq!(mmu.rb(0x0200), 0xAA);
+    assert_eq!(mmu.rb(0x0900), 0x00);
+    // The boot ROM selects DMG compatibility mode for a DMG game.
+    mmu.wb(ADDR_KEY0, KEY0_DMG_COMPATIBILITY);
+    mmu.wb(ADDR_BOOT, 0x00);
+    assert!(mmu.boot_rom.is_some());
+    mmu.wb(ADDR_BOOT, 0x11);
+    assert!(mmu.boot_rom.is_none());
+    assert!(!mmu.get_gpu().is_cgb_mode());
+    assert_eq!(mmu.rb(0x0000), 0x11);
+    assert_eq!(mmu.rb(0x0200), 0x33);
+}
+
+#[test]
+fn test_wram_banks() {
+    let mut mmu = mmu_from_rom(0x80);
+    assert_eq!(mmu.rb(ADDR_SVBK), 0xF9);
+    mmu.wb(0xC000, 0x11);
+    mmu.wb(0xD000, 0x22);
+    mmu.wb(ADDR_SVBK, 3);
+    assert_eq!(mmu.rb(ADDR_SVBK), 0xFB);
+    assert_eq!(mmu.rb(0xD000), 0x00);
+    mmu.wb(0xD000, 0x33);
+    // Bank 0 selects bank 1, but reads back as written.
+    mmu.wb(ADDR_SVBK, 0);
+    assert_eq!(mmu.rb(ADDR_SVBK), 0xF8);
+    assert_eq!(mmu.rb(0xD000), 0x22);
+    assert_eq!(mmu.rb(0xC000), 0x11);
+    // Echo RAM
+    assert_eq!(mmu.rb(0xE000), 0x11);
+    assert_eq!(mmu.rb(0xF000), 0x22);
+    mmu.wb(ADDR_SVBK, 3);
+    assert_eq!(mmu.rb(0xF000), 0x33);
+}
+
+#[test]
+fn test_cgb_registers_in_dmg_mode() {
+    let mut mmu = mmu_from_rom(0x00);
+    mmu.wb(ADDR_SVBK, 3);
+    mmu.wb(0xD000, 0x22);
+    assert_eq!(mmu.rb(ADDR_SVBK), 0xFF);
+    assert_eq!(mmu.rb(ADDR_KEY1), 0xFF);
+    assert_eq!(mmu.rb(0xFF55), 0xFF);
+    mmu.wb(ADDR_SVBK, 1);
+    assert_eq!(mmu.rb(0xD000), 0x22);
+}
+
+#[test]
+fn test_general_dma() {
+    let mut mmu = mmu_from_rom(0x80);
+    for offset in 0..0x20 {
+        mmu.wb(0xC100 + offset, offset as u8);
+    }
+    mmu.wb(0xFF51, 0xC1);
+    mmu.wb(0xFF52, 0x0F);
+    mmu.wb(0xFF53, 0xE8);
+    mmu.wb(0xFF54, 0x10);
+    mmu.wb(0xFF55, 0x01);
+    // The low bits of the addresses are ignored, the destination is in VRAM.
+    assert_eq!(mmu.rb(0x8810), 0x00);
+    assert_eq!(mmu.rb(0x882F), 0x1F);
+    assert_eq!(mmu.rb(0xFF55), 0xFF);
+    assert_eq!(mmu.take_stall_cycles(), 16);
+}
+
+#[test]
+fn test_hblank_dma() {
+    let mut mmu = mmu_from_rom(0x80);
+    for offset in 0..0x30 {
+        mmu.wb(0xC000 + offset, 0xA0 + offset as u8);
+    }
+    mmu.wb(0xFF40, 0x80);
+    mmu.wb(0xFF51, 0xC0);
+    mmu.wb(0xFF52, 0x00);
+    mmu.wb(0xFF53, 0x00);
+    mmu.wb(0xFF54, 0x00);
+    mmu.wb(0xFF55, 0x82);
+    assert_eq!(mmu.rb(0xFF55), 0x02);
+    assert_eq!(mmu.rb(0x8000), 0x00);
+    // OAM search and pixel transfer, then HBlank starts.
+    mmu.step(20 + 43);
+    assert_eq!(mmu.rb(0x8000), 0xA0);
+    assert_eq!(mmu.rb(0x8010), 0x00);
+    assert_eq!(mmu.rb(0xFF55), 0x01);
+    assert_eq!(mmu.take_stall_cycles(), 8);
+    mmu.step(114);
+    assert_eq!(mmu.rb(0x801F), 0xBF);
+    // Cancelled before the last block.
+    mmu.wb(0xFF55, 0x00);
+    assert_eq!(mmu.rb(0xFF55), 0x80);
+    mmu.step(114);
+    assert_eq!(mmu.rb(0x8020), 0x00);
+}
+
+#[test]
+fn test_speed_switch() {
+    let mut mmu = mmu_from_rom(0x80);
+    mmu.stop();
+    assert_eq!(mmu.rb(ADDR_KEY1), 0x7E);
+    assert_eq!(mmu.take_stall_cycles(), 0);
+    mmu.wb(ADDR_KEY1, 0x01);
+    assert_eq!(mmu.rb(ADDR_KEY1), 0x7F);
+    mmu.stop();
+    assert_eq!(mmu.rb(ADDR_KEY1), 0xFE);
+    assert_eq!(mmu.take_stall_cycles(), SPEED_SWITCH_CYCLES);
+    // The PPU runs at half the CPU rate.
+    mmu.wb(0xFF40, 0x80);
+    mmu.step(114 * 2 - 1);
+    assert_eq!(mmu.rb(0xFF44), 0);
+    mmu.step(1);
+    assert_eq!(mmu.rb(0xFF44), 1);
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut mmu = mmu_from_rom(0x00);
+    // LCDC written with bit 7 cleared.
+    let mut lcd_off = Watchpoint::new(Access::Write, 0xFF40, 0xFF40);
+    lcd_off.mask = 0x80;
+    mmu.add_watchpoint(lcd_off);
+    mmu.add_watchpoint(Watchpoint::new(Access::Change, 0xC000, 0xC0FF));
+    mmu.add_watchpoint(Watchpoint::new(Access::Read, 0xFF44, 0xFF44));
+
+    mmu.wb(0xFF40, 0x91);
+    assert_eq!(mmu.take_watch_hit(), None);
+    mmu.wb(0xFF40, 0x11);
+    assert_eq!(mmu.take_watch_hit(), Some(WatchHit { index: 0, access: Access::Write, addr: 0xFF40, value: 0x11, old: 0x91 }));
+    mmu.wb(0xC010, 0x00);
+    assert_eq!(mmu.take_watch_hit(), None);
+    mmu.wb(0xC010, 0x42);
+    mmu.rb(0xFF44);
+    // Only the first hit is kept.
+    assert_eq!(mmu.take_watch_hit(), Some(WatchHit { index: 1, access: Access::Change, addr: 0xC010, value: 0x42, old: 0x00 }));
+    mmu.rb(0xFF44);
+    assert_eq!(mmu.take_watch_hit().map(|hit| hit.access), Some(Access::Read));
+    assert_eq!(mmu.remove_watchpoint(2), Some(Watchpoint::new(Access::Read, 0xFF44, 0xFF44)));
+    mmu.rb(0xFF44);
+    assert_eq!(mmu.take_watch_hit(), None);
+}
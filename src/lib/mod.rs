@@ -1,7 +1,28 @@
+pub mod apu;
 pub mod cpu;
+pub mod colorization;
 pub mod cpu_registers;
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb;
+pub mod gbs;
 pub mod gpu;
+pub mod hdma;
+pub mod input_script;
+pub mod joypad;
 pub mod mmu;
+pub mod model;
+pub mod movie;
+pub mod png;
+pub mod printer;
 pub mod ram;
 pub mod rom;
+pub mod screen;
+pub mod serial;
+pub mod sgb;
+pub mod timer;
+pub mod vgm;
+pub mod vram_dump;
+pub mod watchpoint;
+pub mod wav;
 pub mod mbc;
\ No newline at end of file
@@ -0,0 +1,83 @@
+/// Game Boy hardware revisions. The model decides the state the boot ROM
+/// leaves behind and which hardware quirks apply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Model {
+    Dmg0,
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    Agb
+}
+
+impl Model {
+    pub const ALL: [Model; 7] = [Model::Dmg0, Model::Dmg, Model::Mgb, Model::Sgb, Model::Sgb2, Model::Cgb, Model::Agb];
+
+    pub fn from_name (name: &str) -> Option<Model> {
+        Model::ALL.iter().copied().find(|model| model.get_name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_name (self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Sgb2 => "sgb2",
+            Model::Cgb => "cgb",
+            Model::Agb => "agb"
+        }
+    }
+
+    /// Model a ROM runs on by default: a Game Boy Color if its header
+    /// supports it, the original Game Boy otherwise.
+    pub fn default_for (cgb_supported: bool) -> Model {
+        if cgb_supported { Model::Cgb } else { Model::Dmg }
+    }
+
+    /// Whether the hardware is a Game Boy Color, even when running a game in
+    /// DMG compatibility mode.
+    pub fn is_cgb (self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn is_sgb (self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    /// AF, BC, DE and HL as left by the boot ROM. `cgb_mode` tells whether a
+    /// color model runs the game in CGB mode, and `header_checksum` is the
+    /// byte at 0x014D, which decides the half-carry and carry flags on DMG.
+    pub fn boot_registers (self, cgb_mode: bool, header_checksum: u8) -> [u16; 4] {
+        let checksum_flags = if header_checksum != 0 { 0x30 } else { 0x00 };
+        match self {
+            Model::Dmg0 => [0x0100, 0xFF13, 0x00C1, 0x8403],
+            Model::Dmg => [0x0180 | checksum_flags, 0x0013, 0x00D8, 0x014D],
+            Model::Mgb => [0xFF80 | checksum_flags, 0x0013, 0x00D8, 0x014D],
+            Model::Sgb => [0x0100, 0x0014, 0x0000, 0xC060],
+            Model::Sgb2 => [0xFF00, 0x0014, 0x0000, 0xC060],
+            Model::Cgb if cgb_mode => [0x1180, 0x0000, 0xFF56, 0x000D],
+            Model::Cgb => [0x1180, 0x0000, 0x0008, 0x007C],
+            // The GBA boot ROM ends with an INC B, which also clears Z.
+            Model::Agb if cgb_mode => [0x1100, 0x0100, 0xFF56, 0x000D],
+            Model::Agb => [0x1100, 0x0100, 0x0008, 0x007C]
+        }
+    }
+
+    /// Value of the 16-bit system counter behind DIV when the boot ROM hands
+    /// over, which depends on how long each boot ROM runs.
+    pub fn boot_div_counter (self, cgb_mode: bool) -> u16 {
+        match self {
+            Model::Dmg0 => 0x182C,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            Model::Sgb | Model::Sgb2 => 0xD85C,
+            Model::Cgb | Model::Agb if cgb_mode => 0x1EA0,
+            Model::Cgb | Model::Agb => 0x267C
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./model_test.rs"]
+mod model_test;
@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn test_from_name_round_trips() {
+    for model in Model::ALL.iter() {
+        assert_eq!(Model::from_name(model.get_name()), Some(*model));
+    }
+    assert_eq!(Model::from_name("CGB"), Some(Model::Cgb));
+    assert_eq!(Model::from_name("gbc"), None);
+}
+
+#[test]
+fn test_boot_registers_identify_the_hardware() {
+    assert_eq!(Model::Dmg.boot_registers(false, 0x4D), [0x01B0, 0x0013, 0x00D8, 0x014D]);
+    assert_eq!(Model::Dmg.boot_registers(false, 0x00)[0], 0x0180);
+    assert_eq!(Model::Mgb.boot_registers(false, 0x4D)[0] >> 8, 0xFF);
+    assert_eq!(Model::Cgb.boot_registers(true, 0x4D)[0] >> 8, 0x11);
+    // The GBA is told apart from the CGB by bit 0 of B.
+    assert_eq!(Model::Agb.boot_registers(true, 0x4D)[1] >> 8, 0x01);
+}
@@ -0,0 +1,194 @@
+use std::fmt::Write;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use super::model::Model;
+use super::sgb::PLAYER_COUNT;
+
+const VERSION: u32 = 1;
+const START_POST_BOOT: &str = "post_boot";
+const START_BOOT_ROM: &str = "boot_rom";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, its output is guaranteed to stay
+/// the same across Rust versions, so state hashes can be stored in movies.
+pub struct StateHasher {
+    hash: u64
+}
+
+impl StateHasher {
+    pub fn new () -> StateHasher {
+        StateHasher { hash: FNV_OFFSET_BASIS }
+    }
+}
+
+impl Hasher for StateHasher {
+    fn write (&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish (&self) -> u64 {
+        self.hash
+    }
+}
+
+/// State a movie starts from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Start {
+    // The state the boot ROM of the model leaves behind.
+    PostBoot,
+    // Power on, running the boot ROM with this CRC-32.
+    BootRom(u32)
+}
+
+/// The buttons held during every frame of a run, along with the ROM and
+/// settings it was recorded with and the state hash every few frames.
+///
+/// Movies are stored as text: a header, then one `input` line per frame with
+/// the button mask of every SGB player in hex, up to the last one holding
+/// buttons, and `hash` lines with the state hash after the frame before
+/// them.
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub model: Model,
+    pub start: Start,
+    inputs: Vec<[u8; PLAYER_COUNT]>,
+    // Frame count and state hash after that many frames.
+    hashes: Vec<(u64, u64)>
+}
+
+fn invalid (line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+}
+
+fn parse_hex (line: usize, value: &str) -> io::Result<u64> {
+    match u64::from_str_radix(value, 16) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(invalid(line, &format!("invalid hex number '{}'", value)))
+    }
+}
+
+impl Movie {
+    pub fn new (rom_crc32: u32, model: Model, start: Start) -> Movie {
+        Movie {
+            rom_crc32,
+            model,
+            start,
+            inputs: Vec::new(),
+            hashes: Vec::new()
+        }
+    }
+
+    pub fn get_frame_count (&self) -> u64 {
+        self.inputs.len() as u64
+    }
+
+    /// Returns the buttons of every player during `frame`.
+    pub fn get_input (&self, frame: u64) -> Option<[u8; PLAYER_COUNT]> {
+        self.inputs.get(frame as usize).copied()
+    }
+
+    /// Returns the state hash recorded after `frames` frames, if any.
+    pub fn get_hash (&self, frames: u64) -> Option<u64> {
+        self.hashes.iter().find(|(frame, _)| *frame == frames).map(|(_, hash)| *hash)
+    }
+
+    pub fn push_input (&mut self, buttons: [u8; PLAYER_COUNT]) {
+        self.inputs.push(buttons);
+    }
+
+    /// Records the state hash after the frames pushed so far.
+    pub fn push_hash (&mut self, hash: u64) {
+        self.hashes.push((self.get_frame_count(), hash));
+    }
+
+    pub fn encode (&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "gb-movie {}", VERSION);
+        let _ = writeln!(text, "rom_crc32 {:08x}", self.rom_crc32);
+        let _ = writeln!(text, "model {}", self.model.get_name());
+        let _ = match self.start {
+            Start::PostBoot => writeln!(text, "start {}", START_POST_BOOT),
+            Start::BootRom(crc32) => writeln!(text, "start {} {:08x}", START_BOOT_ROM, crc32)
+        };
+        let mut hashes = self.hashes.iter().peekable();
+        for frame in 0..=self.inputs.len() {
+            while let Some((_, hash)) = hashes.next_if(|(after, _)| *after == frame as u64) {
+                let _ = writeln!(text, "hash {:016x}", hash);
+            }
+            if let Some(buttons) = self.inputs.get(frame) {
+                let players = buttons.iter().rposition(|pressed| *pressed != 0).map_or(1, |last| last + 1);
+                let masks: Vec<String> = buttons[..players].iter().map(|pressed| format!("{:02x}", pressed)).collect();
+                let _ = writeln!(text, "input {}", masks.join(" "));
+            }
+        }
+        text
+    }
+
+    pub fn parse (text: &str) -> io::Result<Movie> {
+        let mut movie = Movie::new(0, Model::Dmg, Start::PostBoot);
+        let mut has_version = false;
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let key = words.next().unwrap_or("");
+            let value = words.next().unwrap_or("");
+            match key {
+                "gb-movie" if value == VERSION.to_string() => has_version = true,
+                "gb-movie" => return Err(invalid(number, &format!("unsupported version '{}'", value))),
+                "rom_crc32" => movie.rom_crc32 = parse_hex(number, value)? as u32,
+                "model" => {
+                    movie.model = Model::from_name(value)
+                        .ok_or_else(|| invalid(number, &format!("unknown model '{}'", value)))?;
+                }
+                "start" if value == START_POST_BOOT => movie.start = Start::PostBoot,
+                "start" if value == START_BOOT_ROM => {
+                    let crc32 = words.next().ok_or_else(|| invalid(number, "missing boot ROM CRC-32"))?;
+                    movie.start = Start::BootRom(parse_hex(number, crc32)? as u32);
+                }
+                "start" => return Err(invalid(number, &format!("unsupported starting state '{}'", value))),
+                "input" => {
+                    let mut buttons = [0; PLAYER_COUNT];
+                    buttons[0] = parse_hex(number, value)? as u8;
+                    for (player, mask) in words.enumerate() {
+                        if player + 1 >= PLAYER_COUNT {
+                            return Err(invalid(number, &format!("more than {} players", PLAYER_COUNT)));
+                        }
+                        buttons[player + 1] = parse_hex(number, mask)? as u8;
+                    }
+                    movie.push_input(buttons);
+                }
+                "hash" => {
+                    let hash = parse_hex(number, value)?;
+                    movie.push_hash(hash);
+                }
+                _ => return Err(invalid(number, &format!("unknown entry '{}'", key)))
+            }
+        }
+        if !has_version {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a movie file"));
+        }
+        Ok(movie)
+    }
+
+    pub fn from_file (file_name: &str) -> io::Result<Movie> {
+        Movie::parse(&fs::read_to_string(file_name)?)
+    }
+
+    pub fn save (&self, file_name: &str) -> io::Result<()> {
+        fs::write(file_name, self.encode())
+    }
+}
+
+#[cfg(test)]
+#[path = "./movie_test.rs"]
+mod movie_test;
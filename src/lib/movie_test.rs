@@ -0,0 +1,54 @@
+use super::*;
+
+#[test]
+fn test_state_hasher_is_fnv1a() {
+    let mut hasher = StateHasher::new();
+    assert_eq!(hasher.finish(), 0xcbf29ce484222325);
+    hasher.write(b"a");
+    assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+}
+
+#[test]
+fn test_encode_and_parse() {
+    let mut movie = Movie::new(0x1234ABCD, Model::Agb, Start::PostBoot);
+    movie.push_hash(0x11);
+    movie.push_input([0x00, 0x00, 0x00, 0x00]);
+    movie.push_input([0x80, 0x00, 0x00, 0x00]);
+    movie.push_hash(0x22);
+    movie.push_input([0x09, 0x00, 0x04, 0x00]);
+    let text = movie.encode();
+    assert_eq!(text, "gb-movie 1\nrom_crc32 1234abcd\nmodel agb\nstart post_boot\n\
+        hash 0000000000000011\ninput 00\ninput 80\nhash 0000000000000022\ninput 09 00 04\n");
+
+    let parsed = Movie::parse(&text).unwrap();
+    assert_eq!(parsed.rom_crc32, 0x1234ABCD);
+    assert_eq!(parsed.model, Model::Agb);
+    assert_eq!(parsed.start, Start::PostBoot);
+    assert_eq!(parsed.get_frame_count(), 3);
+    assert_eq!(parsed.get_input(1), Some([0x80, 0x00, 0x00, 0x00]));
+    assert_eq!(parsed.get_input(2), Some([0x09, 0x00, 0x04, 0x00]));
+    assert_eq!(parsed.get_input(3), None);
+    assert_eq!(parsed.get_hash(0), Some(0x11));
+    assert_eq!(parsed.get_hash(2), Some(0x22));
+    assert_eq!(parsed.get_hash(1), None);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Movie::parse("input 00\n").is_err());
+    assert!(Movie::parse("gb-movie 2\n").is_err());
+    assert!(Movie::parse("gb-movie 1\nstart savestate\n").is_err());
+    assert!(Movie::parse("gb-movie 1\nstart boot_rom\n").is_err());
+    assert!(Movie::parse("gb-movie 1\ninput zz\n").is_err());
+    assert!(Movie::parse("gb-movie 1\ninput 00 00 00 00 00\n").is_err());
+    assert!(Movie::parse("gb-movie 1\nmodel gbc\n").is_err());
+    assert!(Movie::parse("gb-movie 1\ncgb 1\n").is_err());
+}
+
+#[test]
+fn test_boot_rom_start() {
+    let movie = Movie::new(0x1234ABCD, Model::Dmg, Start::BootRom(0x0BAD_F00D));
+    let text = movie.encode();
+    assert!(text.contains("\nstart boot_rom 0badf00d\n"));
+    assert_eq!(Movie::parse(&text).unwrap().start, Start::BootRom(0x0BAD_F00D));
+}
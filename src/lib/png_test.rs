@@ -0,0 +1,32 @@
+use super::*;
+
+#[test]
+fn test_crc32_of_iend_chunk() {
+    assert_eq!(crc32(&[b"IEND", &[]]), 0xAE426082);
+}
+
+#[test]
+fn test_adler32() {
+    assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+}
+
+#[test]
+fn test_encode_rgba_layout() {
+    let rgba = [0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF];
+    let png = encode_rgba(2, 1, &rgba);
+    assert_eq!(&png[0..8], &PNG_SIGNATURE);
+    // IHDR length and type
+    assert_eq!(&png[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+    assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+    assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+}
+
+#[test]
+fn test_zlib_stored_splits_large_blocks() {
+    let data = vec![0xAB; MAX_STORED_BLOCK + 10];
+    let out = zlib_stored(&data);
+    // header + 2 block headers + data + adler
+    assert_eq!(out.len(), 2 + 5 * 2 + data.len() + 4);
+    assert_eq!(out[2], 0x00);
+    assert_eq!(out[2 + 5 + MAX_STORED_BLOCK], 0x01);
+}
@@ -0,0 +1,229 @@
+//! The Game Boy Printer, a serial device that receives image data in
+//! packets and prints it on thermal paper 160 pixels wide.
+
+use super::vram_dump::Image;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const DEVICE_ID: u8 = 0x81;
+
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0b1;
+const STATUS_BUSY: u8 = 0b10;
+const STATUS_IMAGE_FULL: u8 = 0b100;
+const STATUS_UNPROCESSED: u8 = 0b1000;
+
+const TILES_PER_ROW: usize = 20;
+const TILE_ROW_BYTES: usize = TILES_PER_ROW * 16;
+// The printer memory holds 9 data packets of two rows of 20 tiles.
+const BUFFER_SIZE: usize = 9 * 2 * TILE_ROW_BYTES;
+pub const PAPER_WIDTH: usize = TILES_PER_ROW * 8;
+// Status requests answered as busy after a print, standing in for the time
+// the print head takes.
+const BUSY_POLLS: u8 = 4;
+const DEFAULT_PALETTE: u8 = 0xE4;
+const PAPER_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+#[derive(Clone, Copy, PartialEq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLo,
+    LengthHi,
+    Data,
+    ChecksumLo,
+    ChecksumHi,
+    Alive,
+    Status
+}
+
+pub struct Printer {
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    // Sum of the bytes from the command to the data, and the checksum sent.
+    sum: u16,
+    checksum: u16,
+    // Byte sent back during the next transfer.
+    reply: u8,
+    status: u8,
+    busy_polls: u8,
+    buffer: Vec<u8>,
+    printouts: Vec<Image>
+}
+
+/// Expands the run-length encoding of compressed data packets: a control
+/// byte with bit 7 set repeats the next byte (control & 0x7F) + 2 times,
+/// otherwise (control + 1) bytes follow as is.
+pub fn decompress (data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 > 0 {
+            if let Some(&byte) = data.get(i) {
+                output.extend(std::iter::repeat_n(byte, (control & 0x7F) as usize + 2));
+            }
+            i += 1;
+        } else {
+            let end = std::cmp::min(i + control as usize + 1, data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    output
+}
+
+impl Printer {
+    pub fn new () -> Printer {
+        debug!("Creating new Printer...");
+        Printer {
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            sum: 0,
+            checksum: 0,
+            reply: 0,
+            status: 0,
+            busy_polls: 0,
+            buffer: Vec::new(),
+            printouts: Vec::new()
+        }
+    }
+
+    /// Receives one byte from the Game Boy and returns the byte sent back
+    /// during the same transfer.
+    pub fn exchange (&mut self, byte: u8) -> u8 {
+        let reply = self.reply;
+        self.reply = 0x00;
+        self.state = match self.state {
+            PacketState::Magic(index) if byte == MAGIC[index] => {
+                if index + 1 < MAGIC.len() { PacketState::Magic(index + 1) } else { PacketState::Command }
+            }
+            PacketState::Magic(_) if byte == MAGIC[0] => PacketState::Magic(1),
+            PacketState::Magic(_) => PacketState::Magic(0),
+            PacketState::Command => {
+                self.command = byte;
+                self.sum = byte as u16;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 1 > 0;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                PacketState::LengthLo
+            }
+            PacketState::LengthLo => {
+                self.length = byte as usize;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                PacketState::LengthHi
+            }
+            PacketState::LengthHi => {
+                self.length |= (byte as usize) << 8;
+                self.sum = self.sum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.length > 0 { PacketState::Data } else { PacketState::ChecksumLo }
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                self.sum = self.sum.wrapping_add(byte as u16);
+                if self.data.len() < self.length { PacketState::Data } else { PacketState::ChecksumLo }
+            }
+            PacketState::ChecksumLo => {
+                self.checksum = byte as u16;
+                PacketState::ChecksumHi
+            }
+            PacketState::ChecksumHi => {
+                self.checksum |= (byte as u16) << 8;
+                self.reply = DEVICE_ID;
+                PacketState::Alive
+            }
+            PacketState::Alive => {
+                if self.checksum == self.sum {
+                    self.status &= !STATUS_CHECKSUM_ERROR;
+                    self.run_command();
+                } else {
+                    self.status |= STATUS_CHECKSUM_ERROR;
+                }
+                self.reply = self.status;
+                PacketState::Status
+            }
+            PacketState::Status => PacketState::Magic(0)
+        };
+        reply
+    }
+
+    fn run_command (&mut self) {
+        match self.command {
+            CMD_INIT => {
+                debug!("[PRINTER] Init");
+                self.buffer.clear();
+                self.status = 0;
+                self.busy_polls = 0;
+            }
+            CMD_DATA if self.length == 0 => self.status |= STATUS_IMAGE_FULL,
+            CMD_DATA => {
+                let data = if self.compressed { decompress(&self.data) } else { self.data.clone() };
+                let free = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend_from_slice(&data[..std::cmp::min(data.len(), free)]);
+                self.status |= STATUS_UNPROCESSED;
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_FULL;
+                }
+            }
+            CMD_PRINT if self.data.len() >= 4 => {
+                let palette = if self.data[2] == 0 { DEFAULT_PALETTE } else { self.data[2] };
+                debug!("[PRINTER] Printing {} bytes with palette {:02x}", self.buffer.len(), palette);
+                let printout = self.render(palette);
+                self.printouts.push(printout);
+                self.buffer.clear();
+                self.status = STATUS_BUSY;
+                self.busy_polls = BUSY_POLLS;
+            }
+            CMD_STATUS if self.busy_polls > 0 => {
+                self.busy_polls -= 1;
+                if self.busy_polls == 0 {
+                    self.status &= !STATUS_BUSY;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render (&self, palette: u8) -> Image {
+        let rows = self.buffer.len() / TILE_ROW_BYTES;
+        let mut image = Image::new(PAPER_WIDTH, rows * 8);
+        for (tile_index, tile) in self.buffer[..rows * TILE_ROW_BYTES].chunks(16).enumerate() {
+            let tile_x = (tile_index % TILES_PER_ROW) * 8;
+            let tile_y = (tile_index / TILES_PER_ROW) * 8;
+            for y in 0..8 {
+                let lo = tile[y * 2];
+                let hi = tile[y * 2 + 1];
+                for x in 0..8 {
+                    let bit = 7 - x;
+                    let color = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+                    let shade = PAPER_SHADES[((palette >> (color * 2)) & 0x03) as usize];
+                    image.set_pixel(tile_x + x, tile_y + y, [shade, shade, shade]);
+                }
+            }
+        }
+        image
+    }
+
+    /// Returns the images printed since the last call.
+    pub fn take_printouts (&mut self) -> Vec<Image> {
+        std::mem::take(&mut self.printouts)
+    }
+}
+
+#[cfg(test)]
+#[path = "./printer_test.rs"]
+mod printer_test;
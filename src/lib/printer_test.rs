@@ -0,0 +1,72 @@
+use super::*;
+
+// Sends a packet and returns the device ID and status bytes sent back.
+fn send_packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+    let mut packet = vec![0x88, 0x33, command, compressed as u8, data.len() as u8, (data.len() >> 8) as u8];
+    packet.extend_from_slice(data);
+    let sum = packet[2..].iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    packet.extend_from_slice(&sum.to_le_bytes());
+    for byte in packet {
+        assert_eq!(printer.exchange(byte), 0x00);
+    }
+    (printer.exchange(0x00), printer.exchange(0x00))
+}
+
+#[test]
+fn test_decompress() {
+    assert_eq!(decompress(&[0x82, 0xAA, 0x01, 0x12, 0x34]), vec![0xAA, 0xAA, 0xAA, 0xAA, 0x12, 0x34]);
+}
+
+#[test]
+fn test_status_replies() {
+    let mut printer = Printer::new();
+    assert_eq!(send_packet(&mut printer, CMD_INIT, false, &[]), (0x81, 0x00));
+    assert_eq!(send_packet(&mut printer, CMD_DATA, false, &[0; 640]), (0x81, STATUS_UNPROCESSED));
+    assert_eq!(send_packet(&mut printer, CMD_DATA, false, &[]), (0x81, STATUS_UNPROCESSED | STATUS_IMAGE_FULL));
+    // Bad checksum
+    for byte in [0x88, 0x33, CMD_STATUS, 0, 0, 0, 0x00, 0x00].iter() {
+        printer.exchange(*byte);
+    }
+    assert_eq!(printer.exchange(0x00), 0x81);
+    assert_eq!(printer.exchange(0x00) & STATUS_CHECKSUM_ERROR, STATUS_CHECKSUM_ERROR);
+}
+
+#[test]
+fn test_print_compressed_data() {
+    let mut printer = Printer::new();
+    send_packet(&mut printer, CMD_INIT, false, &[]);
+    // Two rows of tiles: the first pixel row of the first tile has color 3,
+    // everything else color 0.
+    let mut data = vec![0x01, 0xFF, 0xFF];
+    for _ in 0..4 {
+        data.extend_from_slice(&[0xFF, 0x00]);
+    }
+    data.extend_from_slice(&[0xF8, 0x00]);
+    send_packet(&mut printer, CMD_DATA, true, &data);
+    assert_eq!(send_packet(&mut printer, CMD_PRINT, false, &[1, 0x13, 0xE4, 0x40]), (0x81, STATUS_BUSY));
+    let printouts = printer.take_printouts();
+    assert_eq!(printouts.len(), 1);
+    assert_eq!((printouts[0].width, printouts[0].height), (160, 16));
+    assert_eq!(&printouts[0].rgba[0..4], &[0x00, 0x00, 0x00, 0xFF]);
+    assert_eq!(&printouts[0].rgba[160 * 4..160 * 4 + 4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+    // Busy for a few status requests after printing.
+    for _ in 0..BUSY_POLLS - 1 {
+        assert_eq!(send_packet(&mut printer, CMD_STATUS, false, &[]).1, STATUS_BUSY);
+    }
+    assert_eq!(send_packet(&mut printer, CMD_STATUS, false, &[]).1, 0x00);
+}
+
+#[test]
+fn test_data_beyond_9_packets_is_dropped() {
+    let mut printer = Printer::new();
+    send_packet(&mut printer, CMD_INIT, false, &[]);
+    for _ in 0..8 {
+        assert_eq!(send_packet(&mut printer, CMD_DATA, false, &[0; 640]).1, STATUS_UNPROCESSED);
+    }
+    assert_eq!(send_packet(&mut printer, CMD_DATA, false, &[0; 640]).1, STATUS_UNPROCESSED | STATUS_IMAGE_FULL);
+    send_packet(&mut printer, CMD_DATA, false, &[0xFF; 640]);
+    send_packet(&mut printer, CMD_PRINT, false, &[1, 0x13, 0xE4, 0x40]);
+    let printouts = printer.take_printouts();
+    assert_eq!((printouts[0].width, printouts[0].height), (160, 144));
+    assert!(printouts[0].rgba.iter().all(|&byte| byte == 0xFF));
+}
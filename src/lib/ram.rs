@@ -1,14 +1,48 @@
-const RAM_SIZE: usize = 8192;
+// Work RAM is made of 4 KB banks: bank 0 at 0xC000-0xCFFF, and bank 1, or
+// the one selected with SVBK on the Game Boy Color, at 0xD000-0xDFFF.
+const BANK_SIZE: usize = 4096;
+const BANK_COUNT: usize = 8;
 
 pub struct RAM {
-    data: [u8; RAM_SIZE]
+    data: Vec<u8>,
+    // Value last written to SVBK, of which only the low 3 bits are kept.
+    bank: u8
 }
 
 impl RAM {
     pub fn new () -> RAM {
-        debug!("Creating new RAM ({}KB)...", RAM_SIZE/1024);
+        debug!("Creating new RAM ({}KB)...", BANK_SIZE * BANK_COUNT / 1024);
         RAM {
-            data: [0; RAM_SIZE]
+            data: vec![0; BANK_SIZE * BANK_COUNT],
+            bank: 1
         }
     }
-}
\ No newline at end of file
+
+    /// Reads work RAM or its echo at 0xE000-0xFDFF.
+    pub fn rb (&self, addr: u16) -> u8 {
+        self.data[self.offset(addr)]
+    }
+
+    pub fn wb (&mut self, addr: u16, value: u8) {
+        let offset = self.offset(addr);
+        self.data[offset] = value;
+    }
+
+    fn offset (&self, addr: u16) -> usize {
+        let addr = (addr as usize - 0xC000) % (BANK_SIZE * 2);
+        if addr < BANK_SIZE {
+            addr
+        } else {
+            std::cmp::max(self.bank as usize, 1) * BANK_SIZE + addr - BANK_SIZE
+        }
+    }
+
+    pub fn get_bank (&self) -> u8 {
+        self.bank
+    }
+
+    /// Selects the bank at 0xD000-0xDFFF. Bank 0 selects bank 1.
+    pub fn set_bank (&mut self, bank: u8) {
+        self.bank = bank % BANK_COUNT as u8;
+    }
+}
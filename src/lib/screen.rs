@@ -0,0 +1,175 @@
+pub const GREEN_PALETTE: [u32; 4] = [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F];
+pub const GRAYSCALE_PALETTE: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+/// Colors used to display the four DMG shades, lightest first.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DmgPalette {
+    #[default]
+    Green,
+    Grayscale,
+    Custom([u32; 4])
+}
+
+impl DmgPalette {
+    /// Parses "green", "grayscale" or four comma separated RRGGBB colors.
+    pub fn from_name(name: &str) -> Option<DmgPalette> {
+        match name {
+            "green" => Some(DmgPalette::Green),
+            "grayscale" | "gray" | "grey" => Some(DmgPalette::Grayscale),
+            _ => {
+                let colors: Vec<u32> = name.split(',')
+                    .filter_map(|c| u32::from_str_radix(c.trim().trim_start_matches('#'), 16).ok())
+                    .filter(|c| *c <= 0xFFFFFF)
+                    .collect();
+                if colors.len() != 4 || name.split(',').count() != 4 {
+                    return None;
+                }
+                Some(DmgPalette::Custom([colors[0], colors[1], colors[2], colors[3]]))
+            }
+        }
+    }
+
+    pub fn get_colors(&self) -> [u32; 4] {
+        match *self {
+            DmgPalette::Green => GREEN_PALETTE,
+            DmgPalette::Grayscale => GRAYSCALE_PALETTE,
+            DmgPalette::Custom(colors) => colors
+        }
+    }
+
+    pub fn get_rgb(&self, shade: u8) -> [u8; 3] {
+        let color = self.get_colors()[(shade & 0x03) as usize];
+        [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+    }
+}
+
+/// Expands a 15-bit CGB color to 8 bits per channel.
+pub fn rgb555_to_rgb(color: u16) -> [u8; 3] {
+    let expand = |c: u16| ((c << 3) | (c >> 2)) as u8;
+    [expand(color & 0x1F), expand((color >> 5) & 0x1F), expand((color >> 10) & 0x1F)]
+}
+
+/// Adjustment applied to CGB colors so that they look closer to the
+/// original LCD on modern displays.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorCorrection {
+    #[default]
+    None,
+    Cgb,
+    Gba
+}
+
+impl ColorCorrection {
+    pub fn from_name(name: &str) -> Option<ColorCorrection> {
+        match name {
+            "none" => Some(ColorCorrection::None),
+            "cgb" => Some(ColorCorrection::Cgb),
+            "gba" => Some(ColorCorrection::Gba),
+            _ => None
+        }
+    }
+
+    pub fn apply(&self, color: u16) -> [u8; 3] {
+        let r = (color & 0x1F) as u32;
+        let g = ((color >> 5) & 0x1F) as u32;
+        let b = ((color >> 10) & 0x1F) as u32;
+        match *self {
+            ColorCorrection::None => rgb555_to_rgb(color),
+            ColorCorrection::Cgb => {
+                // Channel mixing used by most CGB emulators to desaturate the raw values.
+                [
+                    ((r * 13 + g * 2 + b) >> 1).min(0xFF) as u8,
+                    ((g * 3 + b) << 1).min(0xFF) as u8,
+                    ((r * 3 + g * 2 + b * 11) >> 1).min(0xFF) as u8
+                ]
+            }
+            ColorCorrection::Gba => {
+                // The GBA LCD is darker than the CGB one: linearize with a steep
+                // gamma, mix channels and re-encode for a 2.2 gamma display.
+                let linear = |c: u32| (c as f32 / 31.0).powf(4.0);
+                let (lr, lg, lb) = (linear(r), linear(g), linear(b));
+                let encode = |c: f32| ((c / 255.0).min(1.0).powf(1.0 / 2.2) * 255.0).round() as u8;
+                [
+                    encode(50.0 * lg + 255.0 * lr),
+                    encode(30.0 * lb + 230.0 * lg + 10.0 * lr),
+                    encode(220.0 * lb + 10.0 * lg + 50.0 * lr)
+                ]
+            }
+        }
+    }
+}
+
+/// Interframe blending, emulating the slow response of the original LCD
+/// that some games rely on for transparency effects.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FrameBlending {
+    #[default]
+    None,
+    // Average of the current and the previous frame.
+    Mix,
+    // Average of the current frame and the previous output, leaving a longer trail.
+    Accumulate
+}
+
+impl FrameBlending {
+    pub fn from_name(name: &str) -> Option<FrameBlending> {
+        match name {
+            "none" => Some(FrameBlending::None),
+            "mix" => Some(FrameBlending::Mix),
+            "accumulate" => Some(FrameBlending::Accumulate),
+            _ => None
+        }
+    }
+}
+
+/// Converts a PPU framebuffer into RGBA8 bytes. DMG framebuffers hold shades
+/// that are looked up in `palette`; CGB framebuffers hold RGB555 colors.
+pub fn framebuffer_to_rgba(framebuffer: &[u16], cgb: bool, palette: &DmgPalette, correction: ColorCorrection) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(framebuffer.len() * 4);
+    for &pixel in framebuffer {
+        if cgb {
+            rgba.extend_from_slice(&correction.apply(pixel));
+        } else {
+            rgba.extend_from_slice(&palette.get_rgb(pixel as u8));
+        }
+        rgba.push(0xFF);
+    }
+    rgba
+}
+
+/// Output filters applied to every frame, in order: color correction, then blending.
+#[derive(Default)]
+pub struct PostProcess {
+    pub color_correction: ColorCorrection,
+    pub blending: FrameBlending,
+    previous: Vec<u8>
+}
+
+impl PostProcess {
+    pub fn new(color_correction: ColorCorrection, blending: FrameBlending) -> PostProcess {
+        PostProcess { color_correction, blending, previous: Vec::new() }
+    }
+
+    /// Converts a completed frame to RGBA8. Must be called once per frame for
+    /// blending to be accurate.
+    pub fn process(&mut self, framebuffer: &[u16], cgb: bool, palette: &DmgPalette) -> Vec<u8> {
+        let current = framebuffer_to_rgba(framebuffer, cgb, palette, self.color_correction);
+        if self.blending == FrameBlending::None || self.previous.len() != current.len() {
+            self.previous = current.clone();
+            return current;
+        }
+        let blended: Vec<u8> = current.iter()
+            .zip(self.previous.iter())
+            .map(|(&a, &b)| (a as u16 + b as u16).div_ceil(2) as u8)
+            .collect();
+        self.previous = match self.blending {
+            FrameBlending::Accumulate => blended.clone(),
+            _ => current
+        };
+        blended
+    }
+}
+
+#[cfg(test)]
+#[path = "./screen_test.rs"]
+mod screen_test;
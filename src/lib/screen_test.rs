@@ -0,0 +1,50 @@
+use super::*;
+
+#[test]
+fn test_palette_from_name() {
+    assert_eq!(DmgPalette::from_name("green"), Some(DmgPalette::Green));
+    assert_eq!(
+        DmgPalette::from_name("ffffff,#aaaaaa,555555,000000"),
+        Some(DmgPalette::Custom(GRAYSCALE_PALETTE))
+    );
+    assert_eq!(DmgPalette::from_name("ffffff,aaaaaa,555555"), None);
+    assert_eq!(DmgPalette::from_name("ffffff,aaaaaa,555555,zz"), None);
+}
+
+#[test]
+fn test_rgb555_to_rgb() {
+    assert_eq!(rgb555_to_rgb(0x7FFF), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(rgb555_to_rgb(0x001F), [0xFF, 0x00, 0x00]);
+    assert_eq!(rgb555_to_rgb(0x03E0), [0x00, 0xFF, 0x00]);
+    assert_eq!(rgb555_to_rgb(0x7C00), [0x00, 0x00, 0xFF]);
+}
+
+#[test]
+fn test_color_correction_desaturates_primaries() {
+    for correction in [ColorCorrection::Cgb, ColorCorrection::Gba].iter() {
+        let red = correction.apply(0x001F);
+        assert!(red[0] > 0xC0);
+        assert!(red[1] > 0x00 || red[2] > 0x00);
+        let black = correction.apply(0x0000);
+        assert_eq!(black, [0, 0, 0]);
+    }
+}
+
+#[test]
+fn test_mix_blending_averages_consecutive_frames() {
+    let mut post_process = PostProcess::new(ColorCorrection::None, FrameBlending::Mix);
+    let white = [0x7FFF];
+    let black = [0x0000];
+    assert_eq!(post_process.process(&white, true, &DmgPalette::Green), vec![0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(post_process.process(&black, true, &DmgPalette::Green), vec![0x80, 0x80, 0x80, 0xFF]);
+    // Mixing only looks one frame back.
+    assert_eq!(post_process.process(&black, true, &DmgPalette::Green), vec![0x00, 0x00, 0x00, 0xFF]);
+}
+
+#[test]
+fn test_accumulate_blending_leaves_a_trail() {
+    let mut post_process = PostProcess::new(ColorCorrection::None, FrameBlending::Accumulate);
+    post_process.process(&[0x7FFF], true, &DmgPalette::Green);
+    post_process.process(&[0x0000], true, &DmgPalette::Green);
+    assert_eq!(post_process.process(&[0x0000], true, &DmgPalette::Green), vec![0x40, 0x40, 0x40, 0xFF]);
+}
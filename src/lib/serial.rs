@@ -0,0 +1,184 @@
+pub const INT_SERIAL: u8 = 0b1000;
+
+const SC_TRANSFER: u8 = 0b10000000;
+const SC_FAST_CLOCK: u8 = 0b10;
+const SC_INTERNAL_CLOCK: u8 = 0b1;
+
+// The internal clock shifts one bit at 8192 Hz, or 262144 Hz with the fast
+// clock of the Game Boy Color.
+const CYCLES_PER_BIT: u32 = 128;
+const FAST_CYCLES_PER_BIT: u32 = 4;
+
+/// What one end of a link cable tells the other after every slice of
+/// emulated time, so that both can complete transfers started in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkState {
+    // Byte sent by a transfer started with the internal clock.
+    pub transfer: Option<u8>,
+    pub fast: bool,
+    pub sb: u8,
+    pub sc: u8
+}
+
+impl LinkState {
+    pub fn encode (&self) -> [u8; 4] {
+        let flags = self.transfer.is_some() as u8 | (self.fast as u8) << 1;
+        [flags, self.transfer.unwrap_or(0), self.sb, self.sc]
+    }
+
+    pub fn decode (bytes: [u8; 4]) -> LinkState {
+        LinkState {
+            transfer: if bytes[0] & 1 > 0 { Some(bytes[1]) } else { None },
+            fast: bytes[0] & 2 > 0,
+            sb: bytes[2],
+            sc: bytes[3]
+        }
+    }
+}
+
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    cgb: bool,
+    // Bits still to shift in the current transfer, the cycles until the
+    // next one and between two of them.
+    bits_left: u8,
+    cycles: u32,
+    bit_cycles: u32,
+    // Byte sent by an internally clocked transfer not yet seen by the other
+    // end of the cable.
+    started: Option<u8>,
+    // Byte shifted in from the other end of the cable, MSB first.
+    incoming: u8,
+    // Bytes sent since the last call to `take_output`.
+    output: Vec<u8>
+}
+
+impl Serial {
+    pub fn new () -> Serial {
+        debug!("Creating new Serial...");
+        Serial {
+            sb: 0,
+            sc: 0,
+            cgb: false,
+            bits_left: 0,
+            cycles: 0,
+            bit_cycles: CYCLES_PER_BIT,
+            started: None,
+            incoming: 0,
+            output: Vec::new()
+        }
+    }
+
+    pub fn set_cgb_mode (&mut self, cgb: bool) {
+        self.cgb = cgb;
+    }
+
+    pub fn rb (&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.sb,
+            0xFF02 if self.cgb => self.sc | 0x7C,
+            0xFF02 => self.sc | 0x7E,
+            _ => 0xFF
+        }
+    }
+
+    pub fn wb (&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF01 => self.sb = value,
+            0xFF02 => {
+                self.sc = value & (SC_TRANSFER | SC_FAST_CLOCK | SC_INTERNAL_CLOCK);
+                if self.sc & SC_TRANSFER > 0 && self.sc & SC_INTERNAL_CLOCK > 0 {
+                    self.start_transfer();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn start_transfer (&mut self) {
+        self.started = Some(self.sb);
+        // The input line stays high unless the other end of a link cable
+        // sends something with `set_incoming`.
+        self.shift(0xFF, self.is_fast());
+    }
+
+    fn shift (&mut self, incoming: u8, fast: bool) {
+        self.output.push(self.sb);
+        self.incoming = incoming;
+        self.bits_left = 8;
+        self.bit_cycles = if fast { FAST_CYCLES_PER_BIT } else { CYCLES_PER_BIT };
+        self.cycles = self.bit_cycles;
+    }
+
+    fn is_fast (&self) -> bool {
+        self.cgb && self.sc & SC_FAST_CLOCK > 0
+    }
+
+    /// Returns the state to send to the other end of the link cable.
+    pub fn link_state (&mut self) -> LinkState {
+        LinkState {
+            transfer: self.started.take(),
+            fast: self.is_fast(),
+            sb: self.sb,
+            sc: self.sc
+        }
+    }
+
+    /// Completes the transfers of a slice given the state sent by both ends
+    /// of the cable: a transfer started by the other end clocks this one if it
+    /// waits for an external clock, and a transfer started here receives the
+    /// byte of the other end if it was waiting.
+    pub fn apply_link_state (&mut self, own: &LinkState, peer: &LinkState) {
+        if let Some(byte) = peer.transfer {
+            let waiting = self.sc & SC_TRANSFER > 0 && self.sc & SC_INTERNAL_CLOCK == 0;
+            if own.transfer.is_none() && waiting && self.bits_left == 0 {
+                self.shift(byte, peer.fast);
+            }
+        }
+        if own.transfer.is_some() {
+            let peer_waiting = peer.transfer.is_none() && peer.sc & SC_TRANSFER > 0 && peer.sc & SC_INTERNAL_CLOCK == 0;
+            self.set_incoming(if peer_waiting { peer.sb } else { 0xFF });
+        }
+    }
+
+    // Replaces the byte shifted in by the current internally clocked
+    // transfer, including the bits already shifted.
+    fn set_incoming (&mut self, byte: u8) {
+        let shifted = 8 - self.bits_left as u32;
+        let mask = ((1u16 << shifted) - 1) as u8;
+        self.sb = (self.sb & !mask) | (((byte as u16) >> (8 - shifted)) as u8 & mask);
+        self.incoming = ((byte as u16) << shifted) as u8;
+    }
+
+    /// Returns the bytes sent since the last call.
+    pub fn take_output (&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Shifts the bits of the current transfer. Returns the interrupt flags
+    /// to request.
+    pub fn step (&mut self, cycles: u32) -> u8 {
+        if self.bits_left == 0 {
+            return 0;
+        }
+        let mut cycles = cycles;
+        while self.bits_left > 0 && cycles >= self.cycles {
+            cycles -= self.cycles;
+            self.cycles = self.bit_cycles;
+            self.sb = (self.sb << 1) | (self.incoming >> 7);
+            self.incoming <<= 1;
+            self.bits_left -= 1;
+        }
+        if self.bits_left > 0 {
+            self.cycles -= cycles;
+            return 0;
+        }
+        self.sc &= !SC_TRANSFER;
+        INT_SERIAL
+    }
+}
+
+#[cfg(test)]
+#[path = "./serial_test.rs"]
+mod serial_test;
@@ -0,0 +1,91 @@
+use super::*;
+
+#[test]
+fn test_internal_clock_transfer() {
+    let mut serial = Serial::new();
+    serial.wb(0xFF01, 0x41);
+    serial.wb(0xFF02, 0x81);
+    assert_eq!(serial.rb(0xFF02), 0xFF);
+    // One bit every 128 cycles, MSB first, with ones shifted in.
+    assert_eq!(serial.step(511), 0);
+    assert_eq!(serial.rb(0xFF01), 0x0F);
+    assert_eq!(serial.step(1), 0);
+    assert_eq!(serial.rb(0xFF01), 0x1F);
+    assert_eq!(serial.step(512), INT_SERIAL);
+    assert_eq!(serial.rb(0xFF01), 0xFF);
+    assert_eq!(serial.rb(0xFF02), 0x7F);
+    assert_eq!(serial.step(1024), 0);
+    assert_eq!(serial.take_output(), vec![0x41]);
+    assert!(serial.take_output().is_empty());
+}
+
+#[test]
+fn test_external_clock_waits() {
+    let mut serial = Serial::new();
+    serial.wb(0xFF01, 0x41);
+    serial.wb(0xFF02, 0x80);
+    assert_eq!(serial.step(10000), 0);
+    assert_eq!(serial.rb(0xFF02), 0xFE);
+    assert!(serial.take_output().is_empty());
+}
+
+#[test]
+fn test_fast_clock_on_cgb() {
+    let mut serial = Serial::new();
+    serial.set_cgb_mode(true);
+    serial.wb(0xFF02, 0x83);
+    assert_eq!(serial.rb(0xFF02), 0xFF);
+    assert_eq!(serial.step(32), INT_SERIAL);
+    assert_eq!(serial.rb(0xFF02), 0x7F);
+}
+
+fn exchange(a: &mut Serial, b: &mut Serial) {
+    let state_a = a.link_state();
+    let state_b = b.link_state();
+    a.apply_link_state(&state_a, &state_b);
+    b.apply_link_state(&state_b, &state_a);
+}
+
+#[test]
+fn test_link_transfer() {
+    let mut master = Serial::new();
+    let mut slave = Serial::new();
+    slave.wb(0xFF01, 0x5A);
+    slave.wb(0xFF02, 0x80);
+    master.wb(0xFF01, 0xC3);
+    master.wb(0xFF02, 0x81);
+    // The other end learns about the transfer after two bits were shifted.
+    assert_eq!(master.step(256), 0);
+    exchange(&mut master, &mut slave);
+    assert_eq!(master.rb(0xFF01), 0x0D);
+    assert_eq!(master.step(768), INT_SERIAL);
+    assert_eq!(slave.step(1023), 0);
+    assert_eq!(slave.step(1), INT_SERIAL);
+    assert_eq!(master.rb(0xFF01), 0x5A);
+    assert_eq!(slave.rb(0xFF01), 0xC3);
+    assert_eq!(slave.rb(0xFF02), 0x7E);
+    assert_eq!(master.take_output(), vec![0xC3]);
+    assert_eq!(slave.take_output(), vec![0x5A]);
+}
+
+#[test]
+fn test_link_peer_not_ready() {
+    let mut master = Serial::new();
+    let mut slave = Serial::new();
+    slave.wb(0xFF01, 0x5A);
+    master.wb(0xFF01, 0xC3);
+    master.wb(0xFF02, 0x81);
+    exchange(&mut master, &mut slave);
+    assert_eq!(master.step(1024), INT_SERIAL);
+    assert_eq!(master.rb(0xFF01), 0xFF);
+    assert_eq!(slave.step(1024), 0);
+    assert_eq!(slave.rb(0xFF01), 0x5A);
+}
+
+#[test]
+fn test_link_state_encoding() {
+    let state = LinkState { transfer: Some(0x12), fast: true, sb: 0x34, sc: 0x83 };
+    assert_eq!(LinkState::decode(state.encode()), state);
+    let state = LinkState { transfer: None, fast: false, sb: 0xFF, sc: 0x00 };
+    assert_eq!(LinkState::decode(state.encode()), state);
+}
@@ -0,0 +1,468 @@
+use super::gpu::{GPU, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const BORDER_WIDTH: usize = 256;
+pub const BORDER_HEIGHT: usize = 224;
+// Position of the Game Boy screen inside the border.
+pub const SCREEN_X: usize = 48;
+pub const SCREEN_Y: usize = 40;
+// Controllers the multiplayer adapter connects.
+pub const PLAYER_COUNT: usize = 4;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+const MAX_PACKETS: usize = 7;
+
+// Select lines of P1 as written by the game, active low.
+const P14: u8 = 0b010000;
+const P15: u8 = 0b100000;
+
+// Palette numbers of the 20x18 grid of 8x8 cells covering the screen.
+const ATTR_WIDTH: usize = SCREEN_WIDTH / 8;
+const ATTR_HEIGHT: usize = SCREEN_HEIGHT / 8;
+
+const TRANSFER_SIZE: usize = 0x1000;
+const SYSTEM_PALETTES: usize = 512;
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_MAP_WIDTH: usize = 32;
+const BORDER_MAP_HEIGHT: usize = 28;
+const BORDER_MAP_SIZE: usize = BORDER_MAP_WIDTH * BORDER_MAP_HEIGHT * 2;
+const ADDR_BORDER_PALETTES: usize = 0x800;
+const BORDER_MAP_FLIP_X: u16 = 0x4000;
+const BORDER_MAP_FLIP_Y: u16 = 0x8000;
+
+const CMD_PAL01: u8 = 0x00;
+const CMD_PAL23: u8 = 0x01;
+const CMD_PAL03: u8 = 0x02;
+const CMD_PAL12: u8 = 0x03;
+const CMD_ATTR_BLK: u8 = 0x04;
+const CMD_ATTR_LIN: u8 = 0x05;
+const CMD_ATTR_DIV: u8 = 0x06;
+const CMD_ATTR_CHR: u8 = 0x07;
+const CMD_PAL_SET: u8 = 0x0A;
+const CMD_PAL_TRN: u8 = 0x0B;
+const CMD_MLT_REQ: u8 = 0x11;
+const CMD_CHR_TRN: u8 = 0x13;
+const CMD_PCT_TRN: u8 = 0x14;
+const CMD_MASK_EN: u8 = 0x17;
+
+// White to black, until the game sends its own colors.
+const DEFAULT_PALETTE: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+/// What MASK_EN shows instead of the Game Boy screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mask {
+    None,
+    Freeze,
+    Black,
+    Color0
+}
+
+/// VRAM transfers wait for the next frame, which shows the data to send.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Transfer {
+    // First or second half of the 256 border tiles.
+    BorderTiles(usize),
+    BorderMap,
+    SystemPalettes
+}
+
+/// The Super Game Boy side of the joypad port. Games send it 16 byte
+/// packets by pulsing the P14 and P15 lines: both low to start a packet, then
+/// P14 low for a 0 bit or P15 low for a 1 bit, with both lines high between
+/// bits, least significant bit first, and a 0 bit to end the packet. The
+/// first byte holds the command and how many packets it spans.
+pub struct Sgb {
+    packets: [u8; PACKET_SIZE * MAX_PACKETS],
+    bits: usize,
+    receiving: bool,
+    // Set once both lines went high after the last pulse.
+    pulse_ready: bool,
+    awaiting_stop: bool,
+    select: u8,
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<[u16; 4]>,
+    attributes: [u8; ATTR_WIDTH * ATTR_HEIGHT],
+    mask: Mask,
+    transfer: Option<Transfer>,
+    border_tiles: Vec<u8>,
+    border_map: Vec<u8>,
+    border_palettes: [[u16; 16]; 4],
+    player_count: u8,
+    player: u8,
+    buttons: [u8; PLAYER_COUNT],
+    // Last frame as RGB555 colors.
+    screen: Vec<u16>
+}
+
+fn color_at (data: &[u8], offset: usize) -> u16 {
+    (data[offset] as u16 | (data[offset + 1] as u16) << 8) & 0x7FFF
+}
+
+impl Sgb {
+    pub fn new () -> Sgb {
+        debug!("Creating new SGB...");
+        Sgb {
+            packets: [0; PACKET_SIZE * MAX_PACKETS],
+            bits: 0,
+            receiving: false,
+            pulse_ready: false,
+            awaiting_stop: false,
+            select: P14 | P15,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![DEFAULT_PALETTE; SYSTEM_PALETTES],
+            attributes: [0; ATTR_WIDTH * ATTR_HEIGHT],
+            mask: Mask::None,
+            transfer: None,
+            border_tiles: vec![0; TRANSFER_SIZE * 2],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [[0; 16]; 4],
+            player_count: 1,
+            player: 0,
+            buttons: [0; PLAYER_COUNT],
+            screen: vec![DEFAULT_PALETTE[0]; SCREEN_WIDTH * SCREEN_HEIGHT]
+        }
+    }
+
+    pub fn get_player (&self) -> u8 {
+        self.player
+    }
+
+    pub fn get_buttons (&self, player: u8) -> u8 {
+        self.buttons[player as usize]
+    }
+
+    pub fn set_buttons (&mut self, player: u8, pressed: u8) {
+        self.buttons[player as usize] = pressed;
+    }
+
+    /// Low nibble of P1 while neither button group is selected: 0xF minus
+    /// the player currently read.
+    pub fn get_joypad_id (&self) -> u8 {
+        0x0F - self.player
+    }
+
+    /// The last frame as RGB555 colors, with the palettes and mask applied.
+    pub fn get_screen (&self) -> &[u16] {
+        &self.screen
+    }
+
+    /// Handles a write to P1. Returns true if it switched to another player,
+    /// which happens whenever P15 goes high in multiplayer mode.
+    pub fn write_joypad (&mut self, value: u8) -> bool {
+        let select = value & (P14 | P15);
+        let switched = self.player_count > 1 && select & P15 > 0 && self.select & P15 == 0;
+        if switched {
+            self.player = (self.player + 1) % self.player_count;
+        }
+        self.select = select;
+        match select {
+            0 => self.start_packet(),
+            P15 => self.receive_bit(false),
+            P14 => self.receive_bit(true),
+            _ => self.pulse_ready = true
+        }
+        switched
+    }
+
+    fn start_packet (&mut self) {
+        // Packets after the first continue the command, unless the last one
+        // was cut short.
+        if !self.bits.is_multiple_of(PACKET_BITS) || self.awaiting_stop {
+            self.reset_command();
+        }
+        self.receiving = true;
+        self.pulse_ready = false;
+    }
+
+    fn reset_command (&mut self) {
+        self.packets = [0; PACKET_SIZE * MAX_PACKETS];
+        self.bits = 0;
+        self.awaiting_stop = false;
+    }
+
+    fn receive_bit (&mut self, bit: bool) {
+        if !self.receiving || !self.pulse_ready {
+            return;
+        }
+        self.pulse_ready = false;
+        if self.awaiting_stop {
+            self.awaiting_stop = false;
+            self.receiving = false;
+            if bit {
+                debug!("Corrupt SGB packet.");
+                self.reset_command();
+            } else if self.bits >= self.command_bits() {
+                self.execute();
+                self.reset_command();
+            }
+            return;
+        }
+        if self.bits >= PACKET_BITS * MAX_PACKETS {
+            return;
+        }
+        if bit {
+            self.packets[self.bits / 8] |= 1 << (self.bits % 8);
+        }
+        self.bits += 1;
+        self.awaiting_stop = self.bits.is_multiple_of(PACKET_BITS);
+    }
+
+    fn command_bits (&self) -> usize {
+        std::cmp::max(self.packets[0] as usize & 0x07, 1) * PACKET_BITS
+    }
+
+    fn execute (&mut self) {
+        let command = self.packets[0] >> 3;
+        let data = self.packets;
+        debug!("SGB command 0x{:02x}.", command);
+        match command {
+            CMD_PAL01 => self.set_palette_pair(0, 1, &data),
+            CMD_PAL23 => self.set_palette_pair(2, 3, &data),
+            CMD_PAL03 => self.set_palette_pair(0, 3, &data),
+            CMD_PAL12 => self.set_palette_pair(1, 2, &data),
+            CMD_ATTR_BLK => self.attr_block(&data),
+            CMD_ATTR_LIN => self.attr_line(&data),
+            CMD_ATTR_DIV => self.attr_divide(&data),
+            CMD_ATTR_CHR => self.attr_character(&data),
+            CMD_PAL_SET => self.set_system_palettes(&data),
+            CMD_PAL_TRN => self.transfer = Some(Transfer::SystemPalettes),
+            CMD_MLT_REQ => {
+                self.player_count = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1
+                };
+                self.player = 0;
+            }
+            CMD_CHR_TRN => self.transfer = Some(Transfer::BorderTiles((data[1] & 0x01) as usize)),
+            CMD_PCT_TRN => self.transfer = Some(Transfer::BorderMap),
+            CMD_MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Color0,
+                    _ => Mask::None
+                };
+            }
+            _ => debug!("Unsupported SGB command 0x{:02x}.", command)
+        }
+    }
+
+    // Color 0 is shared by all palettes.
+    fn set_palette_pair (&mut self, first: usize, second: usize, data: &[u8]) {
+        let color0 = color_at(data, 1);
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color0;
+        }
+        for color in 1..4 {
+            self.palettes[first][color] = color_at(data, 1 + color * 2);
+            self.palettes[second][color] = color_at(data, 7 + color * 2);
+        }
+    }
+
+    fn set_system_palettes (&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let id = color_at(data, 1 + palette * 2) as usize % SYSTEM_PALETTES;
+            self.palettes[palette] = self.system_palettes[id];
+        }
+        for palette in 1..4 {
+            self.palettes[palette][0] = self.palettes[0][0];
+        }
+        if data[9] & 0x40 > 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    fn attr_block (&mut self, data: &[u8]) {
+        let count = std::cmp::min(data[1] as usize, 18);
+        for set in data[2..].chunks(6).take(count) {
+            let control = set[0] & 0x07;
+            let inside = set[1] & 0x03;
+            let mut border = (set[1] >> 2) & 0x03;
+            let outside = (set[1] >> 4) & 0x03;
+            // A block changing only its inside or outside also colors its
+            // border.
+            let change_border = match control {
+                0b001 => { border = inside; true }
+                0b100 => { border = outside; true }
+                _ => control & 0b010 > 0
+            };
+            let (x1, y1, x2, y2) = (set[2] as usize, set[3] as usize, set[4] as usize, set[5] as usize);
+            for y in 0..ATTR_HEIGHT {
+                for x in 0..ATTR_WIDTH {
+                    let in_block = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_border = in_block && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let palette = if on_border {
+                        change_border.then_some(border)
+                    } else if in_block {
+                        (control & 0b001 > 0).then_some(inside)
+                    } else {
+                        (control & 0b100 > 0).then_some(outside)
+                    };
+                    if let Some(palette) = palette {
+                        self.attributes[y * ATTR_WIDTH + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    fn attr_line (&mut self, data: &[u8]) {
+        let count = std::cmp::min(data[1] as usize, data.len() - 2);
+        for &line in &data[2..2 + count] {
+            let index = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0x03;
+            if line & 0x80 > 0 {
+                if index < ATTR_HEIGHT {
+                    self.attributes[index * ATTR_WIDTH..(index + 1) * ATTR_WIDTH].fill(palette);
+                }
+            } else if index < ATTR_WIDTH {
+                for y in 0..ATTR_HEIGHT {
+                    self.attributes[y * ATTR_WIDTH + index] = palette;
+                }
+            }
+        }
+    }
+
+    fn attr_divide (&mut self, data: &[u8]) {
+        let after = data[1] & 0x03;
+        let before = (data[1] >> 2) & 0x03;
+        let on_line = (data[1] >> 4) & 0x03;
+        let horizontal = data[1] & 0x40 > 0;
+        let line = data[2] as usize;
+        for y in 0..ATTR_HEIGHT {
+            for x in 0..ATTR_WIDTH {
+                let position = if horizontal { y } else { x };
+                self.attributes[y * ATTR_WIDTH + x] = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after
+                };
+            }
+        }
+    }
+
+    fn attr_character (&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = std::cmp::min(color_at(data, 3) as usize, ATTR_WIDTH * ATTR_HEIGHT);
+        let vertical = data[5] & 0x01 > 0;
+        for entry in 0..count {
+            let byte = match data.get(6 + entry / 4) {
+                Some(byte) => *byte,
+                None => break
+            };
+            if x >= ATTR_WIDTH || y >= ATTR_HEIGHT {
+                break;
+            }
+            self.attributes[y * ATTR_WIDTH + x] = (byte >> (6 - (entry % 4) * 2)) & 0x03;
+            if vertical {
+                y += 1;
+                if y == ATTR_HEIGHT {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTR_WIDTH {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    /// Called when the PPU completes a frame: performs any pending VRAM
+    /// transfer and colors the frame.
+    pub fn on_frame (&mut self, gpu: &GPU) {
+        if let Some(transfer) = self.transfer.take() {
+            let data = vram_transfer(gpu);
+            match transfer {
+                Transfer::BorderTiles(half) => {
+                    self.border_tiles[half * TRANSFER_SIZE..(half + 1) * TRANSFER_SIZE].copy_from_slice(&data);
+                }
+                Transfer::BorderMap => {
+                    self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+                    for (palette, colors) in self.border_palettes.iter_mut().enumerate() {
+                        for (color, value) in colors.iter_mut().enumerate() {
+                            *value = color_at(&data, ADDR_BORDER_PALETTES + (palette * 16 + color) * 2);
+                        }
+                    }
+                }
+                Transfer::SystemPalettes => {
+                    for (id, palette) in self.system_palettes.iter_mut().enumerate() {
+                        for (color, value) in palette.iter_mut().enumerate() {
+                            *value = color_at(&data, (id * 4 + color) * 2);
+                        }
+                    }
+                }
+            }
+        }
+        match self.mask {
+            Mask::None => {
+                for (index, &shade) in gpu.get_framebuffer().iter().enumerate() {
+                    let (x, y) = (index % SCREEN_WIDTH, index / SCREEN_WIDTH);
+                    let palette = self.attributes[(y / 8) * ATTR_WIDTH + x / 8] as usize;
+                    self.screen[index] = self.palettes[palette][(shade & 0x03) as usize];
+                }
+            }
+            Mask::Freeze => {}
+            Mask::Black => self.screen.fill(0x0000),
+            Mask::Color0 => self.screen.fill(self.palettes[0][0])
+        }
+    }
+
+    /// Renders the 256x224 border as RGB555 colors. Transparent pixels,
+    /// including the whole screen area of most borders, show color 0.
+    pub fn render_border (&self) -> Vec<u16> {
+        let backdrop = self.palettes[0][0];
+        let mut pixels = vec![backdrop; BORDER_WIDTH * BORDER_HEIGHT];
+        for cell in 0..BORDER_MAP_WIDTH * BORDER_MAP_HEIGHT {
+            let entry = self.border_map[cell * 2] as u16 | (self.border_map[cell * 2 + 1] as u16) << 8;
+            let tile = &self.border_tiles[(entry & 0xFF) as usize * BORDER_TILE_SIZE..][..BORDER_TILE_SIZE];
+            // Border palettes are numbered 4 to 7.
+            let palette = &self.border_palettes[((entry >> 10) & 0x03) as usize];
+            for row in 0..8 {
+                let ty = if entry & BORDER_MAP_FLIP_Y > 0 { 7 - row } else { row };
+                for column in 0..8 {
+                    let tx = if entry & BORDER_MAP_FLIP_X > 0 { 7 - column } else { column };
+                    let bit = 7 - tx;
+                    let color = ((tile[ty * 2] >> bit) & 1)
+                        | ((tile[ty * 2 + 1] >> bit) & 1) << 1
+                        | ((tile[16 + ty * 2] >> bit) & 1) << 2
+                        | ((tile[16 + ty * 2 + 1] >> bit) & 1) << 3;
+                    if color > 0 {
+                        let x = (cell % BORDER_MAP_WIDTH) * 8 + column;
+                        let y = (cell / BORDER_MAP_WIDTH) * 8 + row;
+                        pixels[y * BORDER_WIDTH + x] = palette[color as usize];
+                    }
+                }
+            }
+        }
+        pixels
+    }
+}
+
+// The SGB receives transfers as the picture on screen, which the game fills
+// with 256 tiles in order from the top left of the BG map. The tile data is
+// read straight from VRAM, assuming the identity BGP the games use.
+fn vram_transfer (gpu: &GPU) -> Vec<u8> {
+    let lcdc = gpu.rb(0xFF40);
+    let map: u16 = if lcdc & 0x08 > 0 { 0x9C00 } else { 0x9800 };
+    let mut data = Vec::with_capacity(TRANSFER_SIZE);
+    for tile in 0..TRANSFER_SIZE / 16 {
+        let index = gpu.rb(map + (tile / ATTR_WIDTH * 32 + tile % ATTR_WIDTH) as u16);
+        let addr = if lcdc & 0x10 > 0 {
+            0x8000 + index as u16 * 16
+        } else {
+            (0x9000 + index as i8 as i32 * 16) as u16
+        };
+        for offset in 0..16 {
+            data.push(gpu.rb(addr + offset));
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+#[path = "./sgb_test.rs"]
+mod sgb_test;
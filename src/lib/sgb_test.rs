@@ -0,0 +1,119 @@
+use super::*;
+
+fn send_packet(sgb: &mut Sgb, packet: &[u8; PACKET_SIZE]) {
+    sgb.write_joypad(0x00);
+    sgb.write_joypad(0x30);
+    for bit in 0..PACKET_BITS {
+        let one = packet[bit / 8] & (1 << (bit % 8)) > 0;
+        sgb.write_joypad(if one { 0x10 } else { 0x20 });
+        sgb.write_joypad(0x30);
+    }
+    sgb.write_joypad(0x20);
+    sgb.write_joypad(0x30);
+}
+
+fn command(command: u8, data: &[u8]) -> [u8; PACKET_SIZE] {
+    let mut packet = [0; PACKET_SIZE];
+    packet[0] = command << 3 | 1;
+    packet[1..1 + data.len()].copy_from_slice(data);
+    packet
+}
+
+fn attribute(sgb: &Sgb, x: usize, y: usize) -> u8 {
+    sgb.attributes[y * ATTR_WIDTH + x]
+}
+
+#[test]
+fn test_pal01_sets_shared_color0() {
+    let mut sgb = Sgb::new();
+    send_packet(&mut sgb, &command(CMD_PAL01, &[
+        0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C, 0x00, 0x00,
+        0x11, 0x11, 0x22, 0x22, 0x33, 0x33
+    ]));
+    assert_eq!(sgb.palettes[0], [0x001F, 0x03E0, 0x7C00, 0x0000]);
+    assert_eq!(sgb.palettes[1], [0x001F, 0x1111, 0x2222, 0x3333]);
+    assert_eq!(sgb.palettes[3][0], 0x001F);
+    assert_eq!(sgb.palettes[3][1], DEFAULT_PALETTE[1]);
+}
+
+#[test]
+fn test_stop_bit_must_be_zero() {
+    let mut sgb = Sgb::new();
+    let packet = command(CMD_MASK_EN, &[0x02]);
+    sgb.write_joypad(0x00);
+    sgb.write_joypad(0x30);
+    for bit in 0..PACKET_BITS {
+        sgb.write_joypad(if packet[bit / 8] & (1 << (bit % 8)) > 0 { 0x10 } else { 0x20 });
+        sgb.write_joypad(0x30);
+    }
+    sgb.write_joypad(0x10);
+    sgb.write_joypad(0x30);
+    assert_eq!(sgb.mask, Mask::None);
+    send_packet(&mut sgb, &packet);
+    assert_eq!(sgb.mask, Mask::Black);
+}
+
+#[test]
+fn test_attr_blk_colors_inside_and_border() {
+    let mut sgb = Sgb::new();
+    // Inside only, which also colors the border, with palette 2.
+    send_packet(&mut sgb, &command(CMD_ATTR_BLK, &[1, 0b001, 0b10, 2, 3, 5, 6]));
+    assert_eq!(attribute(&sgb, 2, 3), 2);
+    assert_eq!(attribute(&sgb, 4, 4), 2);
+    assert_eq!(attribute(&sgb, 6, 4), 0);
+}
+
+#[test]
+fn test_attr_div_and_lin() {
+    let mut sgb = Sgb::new();
+    // Columns left of 5 get palette 1, column 5 palette 2, the rest 3.
+    send_packet(&mut sgb, &command(CMD_ATTR_DIV, &[0b10_01_11, 5]));
+    assert_eq!(attribute(&sgb, 4, 0), 1);
+    assert_eq!(attribute(&sgb, 5, 17), 2);
+    assert_eq!(attribute(&sgb, 19, 9), 3);
+    // Row 9 gets palette 0.
+    send_packet(&mut sgb, &command(CMD_ATTR_LIN, &[1, 0x80 | 9]));
+    assert_eq!(attribute(&sgb, 19, 9), 0);
+    assert_eq!(attribute(&sgb, 19, 10), 3);
+}
+
+#[test]
+fn test_attr_chr_wraps_rows() {
+    let mut sgb = Sgb::new();
+    send_packet(&mut sgb, &command(CMD_ATTR_CHR, &[18, 0, 4, 0, 0, 0b01_10_11_01]));
+    assert_eq!(attribute(&sgb, 18, 0), 1);
+    assert_eq!(attribute(&sgb, 19, 0), 2);
+    assert_eq!(attribute(&sgb, 0, 1), 3);
+    assert_eq!(attribute(&sgb, 1, 1), 1);
+}
+
+#[test]
+fn test_multiplayer_joypad_ids() {
+    let mut sgb = Sgb::new();
+    send_packet(&mut sgb, &command(CMD_MLT_REQ, &[0x01]));
+    assert_eq!(sgb.player_count, 2);
+    assert_eq!(sgb.get_joypad_id(), 0x0F);
+    // Player 2 is selected when P15 goes high.
+    assert!(!sgb.write_joypad(0x10));
+    assert!(sgb.write_joypad(0x30));
+    assert_eq!(sgb.get_joypad_id(), 0x0E);
+    assert!(!sgb.write_joypad(0x20));
+    assert!(!sgb.write_joypad(0x30));
+    sgb.write_joypad(0x10);
+    sgb.write_joypad(0x30);
+    assert_eq!(sgb.get_joypad_id(), 0x0F);
+}
+
+#[test]
+fn test_render_border() {
+    let mut sgb = Sgb::new();
+    sgb.border_tiles[BORDER_TILE_SIZE] = 0x80;
+    // Tile 1 with palette 4, flipped horizontally, in the second cell.
+    sgb.border_map[2] = 0x01;
+    sgb.border_map[3] = 0x10 | (BORDER_MAP_FLIP_X >> 8) as u8;
+    sgb.border_palettes[0][1] = 0x001F;
+    let pixels = sgb.render_border();
+    assert_eq!(pixels[15], 0x001F);
+    assert_eq!(pixels[8], DEFAULT_PALETTE[0]);
+    assert_eq!(pixels[0], DEFAULT_PALETTE[0]);
+}
@@ -0,0 +1,65 @@
+// The timer is driven by a 16-bit system counter incremented every clock
+// (4 per machine cycle). DIV exposes its upper 8 bits.
+const CLOCKS_PER_CYCLE: u32 = 4;
+// The APU frame sequencer is clocked by the falling edge of DIV bit 4, or
+// bit 5 in double speed mode so that it keeps running at 512 Hz.
+const DIV_APU_SHIFT: u32 = 12;
+
+pub struct Timer {
+    counter: u16,
+    double_speed: bool
+}
+
+impl Timer {
+    pub fn new () -> Timer {
+        debug!("Creating new Timer...");
+        Timer {
+            counter: 0,
+            double_speed: false
+        }
+    }
+
+    pub fn set_double_speed (&mut self, double_speed: bool) {
+        self.double_speed = double_speed;
+    }
+
+    pub fn set_counter (&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    fn apu_shift (&self) -> u32 {
+        DIV_APU_SHIFT + self.double_speed as u32
+    }
+
+    pub fn rb (&self, addr: u16) -> u8 {
+        match addr {
+            0xFF04 => (self.counter >> 8) as u8,
+            _ => 0xFF
+        }
+    }
+
+    /// Writes a timer register. Returns the number of frame sequencer
+    /// clocks caused by the write (resetting DIV can produce a falling edge).
+    pub fn wb (&mut self, addr: u16, _value: u8) -> u32 {
+        match addr {
+            0xFF04 => {
+                let falling_edge = self.counter & (1 << self.apu_shift()) > 0;
+                self.counter = 0;
+                falling_edge as u32
+            }
+            _ => 0
+        }
+    }
+
+    /// Advances the system counter and returns how many times the APU frame
+    /// sequencer has to be clocked.
+    pub fn step (&mut self, cycles: u32) -> u32 {
+        let before = self.counter as u32;
+        let after = before + cycles * CLOCKS_PER_CYCLE;
+        self.counter = after as u16;
+        // The bit falls every time the counter crosses a multiple of twice
+        // its value.
+        let shift = self.apu_shift() + 1;
+        (after >> shift) - (before >> shift)
+    }
+}
@@ -0,0 +1,88 @@
+use super::apu::CLOCK_RATE;
+
+const HEADER_SIZE: usize = 0x100;
+const VERSION: u32 = 0x161;
+const SAMPLE_RATE: u64 = 44100;
+
+const CMD_GB_WRITE: u8 = 0xB3;
+const CMD_WAIT: u8 = 0x61;
+const CMD_WAIT_NTSC_FRAME: u8 = 0x62;
+const CMD_WAIT_PAL_FRAME: u8 = 0x63;
+const CMD_WAIT_SHORT: u8 = 0x70;
+const CMD_END: u8 = 0x66;
+
+/// Records APU register writes with their timing as a VGM stream. Waits are
+/// expressed in 44100 Hz samples, as required by the format.
+pub struct VgmLog {
+    commands: Vec<u8>,
+    // Clocks since the log was started.
+    clock: u64,
+    // Samples already covered by wait commands.
+    samples: u64
+}
+
+impl VgmLog {
+    pub fn new () -> VgmLog {
+        debug!("Starting VGM log...");
+        VgmLog {
+            commands: Vec::new(),
+            clock: 0,
+            samples: 0
+        }
+    }
+
+    pub fn advance (&mut self, clocks: u32) {
+        self.clock += clocks as u64;
+    }
+
+    /// Logs a write to a register in 0xFF10-0xFF3F.
+    pub fn write (&mut self, addr: u16, value: u8) {
+        self.wait_until_now();
+        self.commands.push(CMD_GB_WRITE);
+        self.commands.push((addr - 0xFF10) as u8);
+        self.commands.push(value);
+    }
+
+    fn wait_until_now (&mut self) {
+        // Computed from the absolute clock so that rounding does not drift.
+        let target = self.clock * SAMPLE_RATE / CLOCK_RATE as u64;
+        let mut remaining = target - self.samples;
+        self.samples = target;
+        while remaining > 0 {
+            let wait = std::cmp::min(remaining, 0xFFFF);
+            match wait {
+                1..=16 => self.commands.push(CMD_WAIT_SHORT + (wait - 1) as u8),
+                735 => self.commands.push(CMD_WAIT_NTSC_FRAME),
+                882 => self.commands.push(CMD_WAIT_PAL_FRAME),
+                _ => {
+                    self.commands.push(CMD_WAIT);
+                    self.commands.extend_from_slice(&(wait as u16).to_le_bytes());
+                }
+            }
+            remaining -= wait;
+        }
+    }
+
+    /// Returns the complete VGM file, with the time elapsed since the last
+    /// write included at the end.
+    pub fn encode (mut self) -> Vec<u8> {
+        self.wait_until_now();
+        self.commands.push(CMD_END);
+
+        let mut vgm = vec![0; HEADER_SIZE];
+        let file_size = (HEADER_SIZE + self.commands.len()) as u32;
+        vgm[0x00..0x04].copy_from_slice(b"Vgm ");
+        vgm[0x04..0x08].copy_from_slice(&(file_size - 0x04).to_le_bytes());
+        vgm[0x08..0x0C].copy_from_slice(&VERSION.to_le_bytes());
+        vgm[0x18..0x1C].copy_from_slice(&(self.samples as u32).to_le_bytes());
+        // Offset of the data, relative to the field itself.
+        vgm[0x34..0x38].copy_from_slice(&(HEADER_SIZE as u32 - 0x34).to_le_bytes());
+        vgm[0x80..0x84].copy_from_slice(&CLOCK_RATE.to_le_bytes());
+        vgm.extend_from_slice(&self.commands);
+        vgm
+    }
+}
+
+#[cfg(test)]
+#[path = "./vgm_test.rs"]
+mod vgm_test;
@@ -0,0 +1,41 @@
+use super::*;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+#[test]
+fn test_header() {
+    let mut log = VgmLog::new();
+    log.write(0xFF26, 0x80);
+    let vgm = log.encode();
+    assert_eq!(&vgm[0..4], b"Vgm ");
+    assert_eq!(read_u32(&vgm, 0x04) as usize, vgm.len() - 4);
+    assert_eq!(read_u32(&vgm, 0x08), 0x161);
+    assert_eq!(read_u32(&vgm, 0x34), 0xCC);
+    assert_eq!(read_u32(&vgm, 0x80), 4194304);
+    assert_eq!(&vgm[0x100..], &[0xB3, 0x16, 0x80, 0x66]);
+}
+
+#[test]
+fn test_waits_between_writes() {
+    let mut log = VgmLog::new();
+    log.write(0xFF12, 0xF0);
+    // Just over 735 samples, one 60 Hz frame at 44100 Hz.
+    log.advance(69906);
+    log.write(0xFF30, 0x12);
+    // Less than one sample, so no wait is needed.
+    log.advance(50);
+    log.write(0xFF14, 0x87);
+    log.advance(CLOCK_RATE);
+    let vgm = log.encode();
+    assert_eq!(read_u32(&vgm, 0x18), 735 + 44100);
+    assert_eq!(&vgm[0x100..], &[
+        0xB3, 0x02, 0xF0,
+        0x62,
+        0xB3, 0x20, 0x12,
+        0xB3, 0x04, 0x87,
+        0x61, 0x44, 0xAC,
+        0x66
+    ]);
+}
@@ -0,0 +1,196 @@
+//! Debug views of the PPU state: tile data, background maps and OAM,
+//! rendered as RGBA8 images plus a plain text summary.
+
+use std::fmt::Write;
+use super::gpu::{GPU, SCREEN_HEIGHT, SCREEN_WIDTH, TILE_COUNT};
+use super::gpu::{LCDC_OBJ_SIZE, OBJ_ATTR_PALETTE, OBJ_ATTR_PRIORITY, OBJ_ATTR_X_FLIP, OBJ_ATTR_Y_FLIP};
+use super::gpu::{BG_ATTR_BANK, BG_ATTR_PALETTE, BG_ATTR_X_FLIP, BG_ATTR_Y_FLIP, OBJ_ATTR_CGB_BANK, OBJ_ATTR_CGB_PALETTE};
+use super::screen::{self, DmgPalette};
+
+const TILES_PER_ROW: usize = 16;
+const MAP_SIZE: usize = 256;
+const OAM_ENTRIES: usize = 40;
+const OAM_PER_ROW: usize = 8;
+const VIEWPORT_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image { width, height, rgba: vec![0xFF; width * height * 4] }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        let i = (y * self.width + x) * 4;
+        self.rgba[i..i + 3].copy_from_slice(&rgb);
+        self.rgba[i + 3] = 0xFF;
+    }
+}
+
+fn apply_palette(palette_register: u8, color: u8) -> u8 {
+    (palette_register >> (color * 2)) & 0x03
+}
+
+/// How the 2-bit color indices of a tile are turned into RGB.
+enum TileColors<'a> {
+    Dmg(u8, &'a DmgPalette),
+    CgbBg(u8),
+    CgbObj(u8)
+}
+
+impl<'a> TileColors<'a> {
+    fn get_rgb(&self, gpu: &GPU, color: u8) -> [u8; 3] {
+        match *self {
+            TileColors::Dmg(register, palette) => palette.get_rgb(apply_palette(register, color)),
+            TileColors::CgbBg(index) => screen::rgb555_to_rgb(gpu.bg_color(index, color)),
+            TileColors::CgbObj(index) => screen::rgb555_to_rgb(gpu.obj_color(index, color))
+        }
+    }
+}
+
+/// A tile of one VRAM bank, flipped as its attributes say.
+struct Tile {
+    bank: usize,
+    index: usize,
+    attr: u8
+}
+
+fn draw_tile(image: &mut Image, gpu: &GPU, tile: &Tile, left: usize, top: usize, colors: &TileColors) {
+    for y in 0..8 {
+        for x in 0..8 {
+            let tx = if tile.attr & BG_ATTR_X_FLIP > 0 { 7 - x } else { x };
+            let ty = if tile.attr & BG_ATTR_Y_FLIP > 0 { 7 - y } else { y };
+            let color = gpu.tile_pixel(tile.bank, tile.index, tx as u8, ty as u8);
+            image.set_pixel(left + x, top + y, colors.get_rgb(gpu, color));
+        }
+    }
+}
+
+/// All tiles in VRAM, 16 per row, using the raw color indices. On CGB the
+/// second VRAM bank is drawn to the right of the first one.
+pub fn tiles(gpu: &GPU, palette: &DmgPalette) -> Image {
+    let banks = if gpu.is_cgb_mode() { 2 } else { 1 };
+    let rows = TILE_COUNT / TILES_PER_ROW;
+    let mut image = Image::new(TILES_PER_ROW * 8 * banks, rows * 8);
+    let colors = TileColors::Dmg(0b11100100, palette);
+    for bank in 0..banks {
+        for index in 0..TILE_COUNT {
+            let left = (bank * TILES_PER_ROW + index % TILES_PER_ROW) * 8;
+            let top = (index / TILES_PER_ROW) * 8;
+            draw_tile(&mut image, gpu, &Tile { bank, index, attr: 0 }, left, top, &colors);
+        }
+    }
+    image
+}
+
+/// One of the two 32x32 tile maps (0: 0x9800, 1: 0x9C00) drawn through BGP
+/// (or the CGB attributes of each entry), with the area currently shown on
+/// screen (SCX/SCY) outlined.
+pub fn tilemap(gpu: &GPU, map: usize, palette: &DmgPalette) -> Image {
+    let base: usize = if map == 0 { 0x1800 } else { 0x1C00 };
+    let bgp = gpu.rb(0xFF47);
+    let mut image = Image::new(MAP_SIZE, MAP_SIZE);
+    for i in 0..32 * 32 {
+        let index = gpu.bg_tile_index(gpu.bg_tile_number(base + i));
+        let attr = gpu.bg_attributes(base + i);
+        let bank = if attr & BG_ATTR_BANK > 0 { 1 } else { 0 };
+        let colors = if gpu.is_cgb_mode() {
+            TileColors::CgbBg(attr & BG_ATTR_PALETTE)
+        } else {
+            TileColors::Dmg(bgp, palette)
+        };
+        draw_tile(&mut image, gpu, &Tile { bank, index, attr }, (i % 32) * 8, (i / 32) * 8, &colors);
+    }
+
+    let scx = gpu.rb(0xFF43) as usize;
+    let scy = gpu.rb(0xFF42) as usize;
+    for x in 0..SCREEN_WIDTH {
+        image.set_pixel((scx + x) % MAP_SIZE, scy, VIEWPORT_COLOR);
+        image.set_pixel((scx + x) % MAP_SIZE, (scy + SCREEN_HEIGHT - 1) % MAP_SIZE, VIEWPORT_COLOR);
+    }
+    for y in 0..SCREEN_HEIGHT {
+        image.set_pixel(scx, (scy + y) % MAP_SIZE, VIEWPORT_COLOR);
+        image.set_pixel((scx + SCREEN_WIDTH - 1) % MAP_SIZE, (scy + y) % MAP_SIZE, VIEWPORT_COLOR);
+    }
+    image
+}
+
+/// The 40 objects in OAM order, 8 per row, each in an 8x16 cell and drawn
+/// with its own palette and flips.
+pub fn oam(gpu: &GPU, palette: &DmgPalette) -> Image {
+    let tall = gpu.rb(0xFF40) & LCDC_OBJ_SIZE > 0;
+    let mut image = Image::new(OAM_PER_ROW * 8, (OAM_ENTRIES / OAM_PER_ROW) * 16);
+    for i in 0..OAM_ENTRIES {
+        let entry = 0xFE00 + (i * 4) as u16;
+        let mut tile = gpu.rb(entry + 2) as usize;
+        let attr = gpu.rb(entry + 3);
+        let obp = if attr & OBJ_ATTR_PALETTE > 0 { gpu.rb(0xFF49) } else { gpu.rb(0xFF48) };
+        let (bank, colors) = if gpu.is_cgb_mode() {
+            let bank = if attr & OBJ_ATTR_CGB_BANK > 0 { 1 } else { 0 };
+            (bank, TileColors::CgbObj(attr & OBJ_ATTR_CGB_PALETTE))
+        } else {
+            (0, TileColors::Dmg(obp, palette))
+        };
+        let height = if tall { 16 } else { 8 };
+        if tall {
+            tile &= 0xFE;
+        }
+        let left = (i % OAM_PER_ROW) * 8;
+        let top = (i / OAM_PER_ROW) * 16;
+        for y in 0..height {
+            let ty = if attr & OBJ_ATTR_Y_FLIP > 0 { height - 1 - y } else { y };
+            for x in 0..8 {
+                let tx = if attr & OBJ_ATTR_X_FLIP > 0 { 7 - x } else { x };
+                let color = gpu.tile_pixel(bank, tile + ty / 8, tx as u8, (ty % 8) as u8);
+                image.set_pixel(left + x, top + y, colors.get_rgb(gpu, color));
+            }
+        }
+    }
+    image
+}
+
+/// PPU registers and the OAM table in a human readable form.
+pub fn summary(gpu: &GPU) -> String {
+    let mut out = String::new();
+    let registers = [
+        ("LCDC", 0xFF40), ("STAT", 0xFF41), ("SCY", 0xFF42), ("SCX", 0xFF43),
+        ("LY", 0xFF44), ("LYC", 0xFF45), ("BGP", 0xFF47), ("OBP0", 0xFF48),
+        ("OBP1", 0xFF49), ("WY", 0xFF4A), ("WX", 0xFF4B),
+    ];
+    for (name, addr) in registers.iter() {
+        let _ = writeln!(out, "{:<5} (0x{:04X}): 0x{:02X}", name, addr, gpu.rb(*addr));
+    }
+    let _ = writeln!(out);
+    let cgb = gpu.is_cgb_mode();
+    let _ = writeln!(out, "OAM  Y    X    Tile  Attr  Priority  FlipY  FlipX  Palette{}", if cgb { "  Bank" } else { "" });
+    for i in 0..OAM_ENTRIES {
+        let entry = 0xFE00 + (i * 4) as u16;
+        let attr = gpu.rb(entry + 3);
+        let _ = writeln!(
+            out,
+            "{:02}   {:<4} {:<4} 0x{:02X}  0x{:02X}  {:<8}  {:<5}  {:<5}  {}",
+            i,
+            gpu.rb(entry) as i16 - 16,
+            gpu.rb(entry + 1) as i16 - 8,
+            gpu.rb(entry + 2),
+            attr,
+            if attr & OBJ_ATTR_PRIORITY > 0 { "behind" } else { "above" },
+            attr & OBJ_ATTR_Y_FLIP > 0,
+            attr & OBJ_ATTR_X_FLIP > 0,
+            if cgb {
+                format!("OCP{}     {}", attr & OBJ_ATTR_CGB_PALETTE, (attr & OBJ_ATTR_CGB_BANK) >> 3)
+            } else {
+                format!("OBP{}", (attr & OBJ_ATTR_PALETTE) >> 4)
+            }
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "./vram_dump_test.rs"]
+mod vram_dump_test;
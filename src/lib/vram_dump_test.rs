@@ -0,0 +1,91 @@
+use super::*;
+
+const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+fn pixel(image: &Image, x: usize, y: usize) -> [u8; 3] {
+    let i = (y * image.width + x) * 4;
+    [image.rgba[i], image.rgba[i + 1], image.rgba[i + 2]]
+}
+
+// Tile 1 is color 0 except for the leftmost pixel of its first row, which is
+// color 3.
+fn gpu_with_tile_1() -> GPU {
+    let mut gpu = GPU::new();
+    gpu.wb(0x8010, 0b10000000);
+    gpu.wb(0x8011, 0b10000000);
+    gpu.wb(0xFF47, 0b11100100);
+    gpu
+}
+
+#[test]
+fn test_tile_sheet_layout() {
+    let image = tiles(&gpu_with_tile_1(), &DmgPalette::Grayscale);
+    assert_eq!((image.width, image.height), (128, 192));
+    assert_eq!(pixel(&image, 8, 0), BLACK);
+    assert_eq!(pixel(&image, 9, 0), WHITE);
+    assert_eq!(pixel(&image, 8, 1), WHITE);
+
+    let mut gpu = gpu_with_tile_1();
+    gpu.set_cgb_mode(true);
+    gpu.wb(0xFF4F, 0x01);
+    gpu.wb(0x8000, 0b01000000);
+    gpu.wb(0x8001, 0b01000000);
+    let image = tiles(&gpu, &DmgPalette::Grayscale);
+    // The second bank is drawn to the right of the first one.
+    assert_eq!((image.width, image.height), (256, 192));
+    assert_eq!(pixel(&image, 8, 0), BLACK);
+    assert_eq!(pixel(&image, 129, 0), BLACK);
+    assert_eq!(pixel(&image, 1, 0), WHITE);
+}
+
+#[test]
+fn test_both_map_bases() {
+    let mut gpu = gpu_with_tile_1();
+    // Unsigned tile data area, so tile number 1 is tile 1.
+    gpu.wb(0xFF40, 0b00010000);
+    gpu.wb(0xFF42, 100);
+    gpu.wb(0xFF43, 100);
+    gpu.wb(0x9800, 0x01);
+    gpu.wb(0x9C01, 0x01);
+
+    let map0 = tilemap(&gpu, 0, &DmgPalette::Grayscale);
+    assert_eq!((map0.width, map0.height), (256, 256));
+    assert_eq!(pixel(&map0, 0, 0), BLACK);
+    assert_eq!(pixel(&map0, 8, 0), WHITE);
+    let map1 = tilemap(&gpu, 1, &DmgPalette::Grayscale);
+    assert_eq!(pixel(&map1, 0, 0), WHITE);
+    assert_eq!(pixel(&map1, 8, 0), BLACK);
+
+    // The viewport is outlined from (SCX, SCY), wrapping around.
+    assert_eq!(pixel(&map0, 100, 100), VIEWPORT_COLOR);
+    assert_eq!(pixel(&map0, (100 + SCREEN_WIDTH - 1) % MAP_SIZE, 150), VIEWPORT_COLOR);
+    assert_eq!(pixel(&map0, 101, 101), WHITE);
+}
+
+#[test]
+fn test_oam_layout() {
+    let mut gpu = gpu_with_tile_1();
+    gpu.wb(0xFF48, 0b11100100);
+    // Object 0 shows tile 1 as is, object 9 flipped horizontally.
+    gpu.wb(0xFE02, 0x01);
+    gpu.wb(0xFE00 + 9 * 4 + 2, 0x01);
+    gpu.wb(0xFE00 + 9 * 4 + 3, OBJ_ATTR_X_FLIP);
+
+    let image = oam(&gpu, &DmgPalette::Grayscale);
+    assert_eq!((image.width, image.height), (64, 80));
+    assert_eq!(pixel(&image, 0, 0), BLACK);
+    // Object 9 is the second one of the second row.
+    assert_eq!(pixel(&image, 15, 16), BLACK);
+    assert_eq!(pixel(&image, 8, 16), WHITE);
+
+    // 8x16 objects ignore bit 0 of the tile number: tile 3 shows tiles 2
+    // and 3.
+    gpu.wb(0xFF40, LCDC_OBJ_SIZE);
+    gpu.wb(0xFE02, 0x03);
+    gpu.wb(0x8030, 0b10000000);
+    gpu.wb(0x8031, 0b10000000);
+    let image = oam(&gpu, &DmgPalette::Grayscale);
+    assert_eq!(pixel(&image, 0, 0), WHITE);
+    assert_eq!(pixel(&image, 0, 8), BLACK);
+}
@@ -0,0 +1,75 @@
+/// Bus accesses the debugger can break on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    // A write that changes the value read back from the address.
+    Change
+}
+
+impl Access {
+    pub fn from_name (name: &str) -> Option<Access> {
+        match name {
+            "read" => Some(Access::Read),
+            "write" => Some(Access::Write),
+            "change" => Some(Access::Change),
+            _ => None
+        }
+    }
+
+    pub fn get_name (self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Change => "change"
+        }
+    }
+}
+
+/// Triggers on `access` to any address from `start` to `end`, both
+/// included. With `mask` set, only values where `value & mask == expected`
+/// trigger it, e.g. mask 0x80 and expected 0x00 for a cleared bit 7.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub access: Access,
+    pub start: u16,
+    pub end: u16,
+    pub expected: u8,
+    pub mask: u8
+}
+
+impl Watchpoint {
+    pub fn new (access: Access, start: u16, end: u16) -> Watchpoint {
+        Watchpoint { access, start, end, expected: 0, mask: 0 }
+    }
+
+    fn matches (&self, addr: u16, value: u8) -> bool {
+        (self.start..=self.end).contains(&addr) && value & self.mask == self.expected & self.mask
+    }
+
+    pub fn check_read (&self, addr: u16, value: u8) -> bool {
+        self.access == Access::Read && self.matches(addr, value)
+    }
+
+    /// `value` is the byte written, `old` and `new` the bytes read back
+    /// before and after the write.
+    pub fn check_write (&self, addr: u16, value: u8, old: u8, new: u8) -> bool {
+        match self.access {
+            Access::Read => false,
+            Access::Write => self.matches(addr, value),
+            Access::Change => old != new && self.matches(addr, new)
+        }
+    }
+}
+
+/// The first access that triggered a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    // Index of the watchpoint in the MMU.
+    pub index: usize,
+    pub access: Access,
+    pub addr: u16,
+    pub value: u8,
+    // Byte read back before a write.
+    pub old: u8
+}
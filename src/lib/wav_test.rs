@@ -0,0 +1,26 @@
+use super::*;
+use std::fs;
+
+#[test]
+fn test_wav_header_and_samples() {
+    let file_name = std::env::temp_dir().join("gb_wav_test.wav");
+    let file_name = file_name.to_str().unwrap();
+    let mut writer = WavWriter::create(file_name, 2, 48000).unwrap();
+    writer.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+    writer.finish().unwrap();
+
+    let data = fs::read(file_name).unwrap();
+    fs::remove_file(file_name).unwrap();
+    assert_eq!(data.len(), 44 + 8);
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes([data[4], data[5], data[6], data[7]]), 36 + 8);
+    assert_eq!(&data[8..16], b"WAVEfmt ");
+    // Channels, sample rate, byte rate and block align
+    assert_eq!(u16::from_le_bytes([data[22], data[23]]), 2);
+    assert_eq!(u32::from_le_bytes([data[24], data[25], data[26], data[27]]), 48000);
+    assert_eq!(u32::from_le_bytes([data[28], data[29], data[30], data[31]]), 48000 * 4);
+    assert_eq!(u16::from_le_bytes([data[32], data[33]]), 4);
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(u32::from_le_bytes([data[40], data[41], data[42], data[43]]), 8);
+    assert_eq!(&data[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+}
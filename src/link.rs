@@ -0,0 +1,125 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use crate::lib::png;
+use crate::lib::printer::Printer;
+use crate::lib::serial::LinkState;
+
+/// One end of a link cable. Both ends exchange their serial state after
+/// every slice of emulated time, which keeps them in lockstep.
+pub trait LinkPort: Send {
+    /// Sends the state of this end and waits for the state of the other end
+    /// after the same slice. Returns `None` once the other end is gone.
+    fn exchange(&mut self, state: LinkState) -> Option<LinkState>;
+}
+
+/// Cable between two instances running on different threads of the same
+/// process.
+pub struct ChannelLink {
+    sender: Sender<LinkState>,
+    receiver: Receiver<LinkState>
+}
+
+impl ChannelLink {
+    pub fn pair() -> (ChannelLink, ChannelLink) {
+        let (sender_a, receiver_b) = mpsc::channel();
+        let (sender_b, receiver_a) = mpsc::channel();
+        (
+            ChannelLink { sender: sender_a, receiver: receiver_a },
+            ChannelLink { sender: sender_b, receiver: receiver_b }
+        )
+    }
+}
+
+impl LinkPort for ChannelLink {
+    fn exchange(&mut self, state: LinkState) -> Option<LinkState> {
+        self.sender.send(state).ok()?;
+        self.receiver.recv().ok()
+    }
+}
+
+/// Cable to an instance running in another process, over TCP.
+pub struct TcpLink {
+    stream: TcpStream
+}
+
+impl TcpLink {
+    /// Waits for the other end to connect on `port` of localhost.
+    pub fn listen(port: u16) -> io::Result<TcpLink> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        debug!("[LINK] Waiting for a connection on port {}", port);
+        let (stream, address) = listener.accept()?;
+        debug!("[LINK] Connected to {}", address);
+        TcpLink::from_stream(stream)
+    }
+
+    pub fn connect(address: &str) -> io::Result<TcpLink> {
+        debug!("[LINK] Connecting to {}", address);
+        TcpLink::from_stream(TcpStream::connect(address)?)
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<TcpLink> {
+        // Every slice is a round trip of a few bytes.
+        stream.set_nodelay(true)?;
+        Ok(TcpLink { stream })
+    }
+
+    fn try_exchange(&mut self, state: LinkState) -> io::Result<LinkState> {
+        self.stream.write_all(&state.encode())?;
+        let mut bytes = [0; 4];
+        self.stream.read_exact(&mut bytes)?;
+        Ok(LinkState::decode(bytes))
+    }
+}
+
+impl LinkPort for TcpLink {
+    fn exchange(&mut self, state: LinkState) -> Option<LinkState> {
+        match self.try_exchange(state) {
+            Ok(peer) => Some(peer),
+            Err(error) => {
+                warn!("[LINK] Connection lost: {}", error);
+                None
+            }
+        }
+    }
+}
+
+/// Game Boy Printer plugged into the link port. Every printout is written
+/// to `dir` as `print_NNN.png`.
+pub struct PrinterLink {
+    printer: Printer,
+    dir: String,
+    count: u32
+}
+
+impl PrinterLink {
+    pub fn new(dir: &str) -> io::Result<PrinterLink> {
+        fs::create_dir_all(dir)?;
+        Ok(PrinterLink { printer: Printer::new(), dir: String::from(dir), count: 0 })
+    }
+
+    fn save_printouts(&mut self) {
+        for image in self.printer.take_printouts() {
+            self.count += 1;
+            let file_name = format!("{}/print_{:03}.png", self.dir, self.count);
+            debug!("[LINK] Writing printout to {}", file_name);
+            if let Err(error) = png::write_rgba(&file_name, image.width as u32, image.height as u32, &image.rgba) {
+                warn!("[LINK] Could not write printout {}: {}", file_name, error);
+            }
+        }
+    }
+}
+
+impl LinkPort for PrinterLink {
+    fn exchange(&mut self, state: LinkState) -> Option<LinkState> {
+        // The printer is always clocked by the Game Boy, and answers every
+        // byte while receiving it.
+        let sb = match state.transfer {
+            Some(byte) => self.printer.exchange(byte),
+            None => 0x00
+        };
+        self.save_printouts();
+        Some(LinkState { transfer: None, fast: false, sb, sc: 0x80 })
+    }
+}
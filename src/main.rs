@@ -1,11 +1,167 @@
 #[macro_use] extern crate log;
 mod lib;
+mod cli;
 mod emulation;
+mod gbs_player;
+mod link;
+mod terminal;
+
+use std::io;
+use std::thread;
 
 fn main() {
     env_logger::init();
 
-    let rom_data: Vec<u8> = lib::rom::from_file("./roms/test.gb");
+    let options = cli::Options::from_args(std::env::args().skip(1));
+    let rom_data: Vec<u8> = lib::rom::from_file(&options.rom_path);
+    if lib::gbs::is_gbs(&rom_data) {
+        play_gbs(&rom_data, &options);
+        return;
+    }
+    let mut e = create_emulation(rom_data, &options);
+    if options.terminal {
+        e.enable_terminal();
+    }
+    e.set_serial_echo(options.serial_stdout);
+
+    if options.headless {
+        let export = emulation::FrameExport {
+            every: options.export_every,
+            dir: options.export_dir.clone(),
+            png: options.png,
+            rgba: options.rgba
+        };
+        if let Some(file_name) = &options.record_wav {
+            if let Err(error) = e.start_audio_recording(file_name, options.sample_rate, options.high_pass, options.record_channels) {
+                panic!("Could not record audio: {}", error);
+            }
+        }
+        if let Some(file_name) = &options.input_script {
+            match lib::input_script::InputScript::from_file(file_name) {
+                Ok(script) => e.set_input_script(script),
+                Err(error) => panic!("Could not load input script {}: {}", file_name, error)
+            }
+        }
+        if options.record_vgm.is_some() {
+            e.start_vgm_log();
+        }
+        // A frame count of 0 on the command line runs forever.
+        let mut frames = Some(options.frames).filter(|frames| *frames > 0);
+        if let Some(file_name) = &options.replay_movie {
+            let movie = match lib::movie::Movie::from_file(file_name) {
+                Ok(movie) => movie,
+                Err(error) => panic!("Could not load movie {}: {}", file_name, error)
+            };
+            frames = Some(movie.get_frame_count());
+            if let Err(error) = e.replay_movie(movie) {
+                panic!("Could not replay movie: {}", error);
+            }
+        } else if options.record_movie.is_some() {
+            e.start_movie_recording(options.hash_every);
+        }
+        let player2 = connect_link(&mut e, &options, frames);
+        let result = e.run_headless(frames, &export);
+        // The VGM log is kept even when the run fails, e.g. on a desync.
+        if let Some(file_name) = &options.record_vgm {
+            if let Err(error) = e.save_vgm(file_name) {
+                panic!("Could not write VGM log: {}", error);
+            }
+        }
+        if let Err(error) = result {
+            panic!("Headless run failed: {}", error);
+        }
+        if let Some(player2) = player2 {
+            // Both ends count frames, not exchanges, so player 2 may still be
+            // waiting for an exchange that will never come.
+            e.disconnect_link();
+            if let Err(error) = player2.join().expect("Linked instance panicked") {
+                panic!("Headless run of the linked instance failed: {}", error);
+            }
+        }
+        if let Some(file_name) = &options.record_movie {
+            if let Err(error) = e.save_movie(file_name) {
+                panic!("Could not write movie: {}", error);
+            }
+        }
+        if options.dump_vram {
+            if let Err(error) = e.dump_vram(&options.export_dir) {
+                panic!("Could not dump VRAM: {}", error);
+            }
+        }
+    } else if let Some(port) = options.gdb {
+        if let Err(error) = e.start_gdb(port) {
+            panic!("GDB session failed: {}", error);
+        }
+    } else {
+        e.start();
+    }
+}
+
+fn create_emulation(rom_data: Vec<u8>, options: &cli::Options) -> emulation::Emulation {
     let mut e = emulation::Emulation::from_rom(rom_data);
-    e.start();
+    if let Some(model) = options.model {
+        e.set_model(model);
+    }
+    if let Some(file_name) = &options.boot_rom {
+        if let Err(error) = e.set_boot_rom(lib::rom::from_file(file_name)) {
+            panic!("Could not load boot ROM {}: {}", file_name, error);
+        }
+    }
+    e.set_palette(options.palette);
+    e.set_post_process(lib::screen::PostProcess::new(options.color_correction, options.frame_blending));
+    e
+}
+
+/// Plugs the link cable requested on the command line into `e`. A second
+/// instance in this process is started on its own thread, which is returned.
+fn connect_link(e: &mut emulation::Emulation, options: &cli::Options, frames: Option<u64>) -> Option<thread::JoinHandle<io::Result<()>>> {
+    if let Some(rom_path) = &options.link_rom {
+        let mut player2 = create_emulation(lib::rom::from_file(rom_path), options);
+        let (link1, link2) = link::ChannelLink::pair();
+        e.connect_link(Box::new(link1));
+        player2.connect_link(Box::new(link2));
+        let export = emulation::FrameExport {
+            every: options.export_every,
+            dir: format!("{}/player2", options.export_dir),
+            png: options.png,
+            rgba: options.rgba
+        };
+        return Some(thread::spawn(move || player2.run_headless(frames, &export)));
+    }
+    let link: io::Result<Box<dyn link::LinkPort>> = if let Some(port) = options.link_listen {
+        link::TcpLink::listen(port).map(|link| Box::new(link) as _)
+    } else if let Some(address) = &options.link_connect {
+        link::TcpLink::connect(address).map(|link| Box::new(link) as _)
+    } else if options.printer {
+        link::PrinterLink::new(&options.export_dir).map(|printer| Box::new(printer) as _)
+    } else {
+        return None;
+    };
+    match link {
+        Ok(link) => e.connect_link(link),
+        Err(error) => panic!("Could not connect link cable: {}", error)
+    }
+    None
+}
+
+fn play_gbs(data: &[u8], options: &cli::Options) {
+    let mut player = match gbs_player::GbsPlayer::from_gbs(data) {
+        Ok(player) => player,
+        Err(error) => panic!("Could not load GBS file: {}", error)
+    };
+    let header = player.get_header();
+    let track = options.track.unwrap_or(header.first_song);
+    let file_name = match &options.record_wav {
+        Some(file_name) => file_name.clone(),
+        None => {
+            if let Err(error) = std::fs::create_dir_all(&options.export_dir) {
+                panic!("Could not create {}: {}", options.export_dir, error);
+            }
+            format!("{}/track_{:02}.wav", options.export_dir, track)
+        }
+    };
+    println!("{} - {} ({}), track {}/{} -> {}", header.title, header.author, header.copyright, track, header.song_count, file_name);
+    if let Err(error) = player.render_wav(&file_name, track, options.seconds, options.sample_rate, options.high_pass) {
+        panic!("Could not render GBS track: {}", error);
+    }
 }
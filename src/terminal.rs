@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+use crate::lib::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+
+type Rgb = (u8, u8, u8);
+
+/// Draws RGBA8 frames in a terminal using 24-bit ANSI colors. Every character
+/// cell holds two pixels: the upper half block is painted with the foreground
+/// color (top pixel) over the background color (bottom pixel).
+pub struct TerminalRenderer {
+    started: bool
+}
+
+impl TerminalRenderer {
+    pub fn new() -> TerminalRenderer {
+        TerminalRenderer { started: false }
+    }
+
+    pub fn draw(&mut self, rgba: &[u8]) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        if !self.started {
+            // Clear the screen and hide the cursor.
+            out.write_all(b"\x1b[2J\x1b[?25l")?;
+            self.started = true;
+        }
+        out.write_all(render(rgba).as_bytes())?;
+        out.flush()
+    }
+}
+
+impl Drop for TerminalRenderer {
+    fn drop(&mut self) {
+        if self.started {
+            let _ = io::stdout().write_all(b"\x1b[0m\x1b[?25h\n");
+        }
+    }
+}
+
+fn pixel(rgba: &[u8], x: usize, y: usize) -> Rgb {
+    let i = (y * SCREEN_WIDTH + x) * 4;
+    (rgba[i], rgba[i + 1], rgba[i + 2])
+}
+
+/// Builds the escape sequence that redraws a whole frame from the top left
+/// corner. Color codes are only emitted when they change between cells.
+pub fn render(rgba: &[u8]) -> String {
+    let mut out = String::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 12);
+    out.push_str("\x1b[H");
+    for row in 0..SCREEN_HEIGHT / 2 {
+        let mut last: Option<(Rgb, Rgb)> = None;
+        for x in 0..SCREEN_WIDTH {
+            let top = pixel(rgba, x, row * 2);
+            let bottom = pixel(rgba, x, row * 2 + 1);
+            if last != Some((top, bottom)) {
+                out.push_str(&format!(
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    top.0, top.1, top.2, bottom.0, bottom.1, bottom.2
+                ));
+                last = Some((top, bottom));
+            }
+            out.push(UPPER_HALF_BLOCK);
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+#[path = "./terminal_test.rs"]
+mod terminal_test;
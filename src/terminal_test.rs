@@ -0,0 +1,33 @@
+use super::*;
+
+fn frame(color: [u8; 4]) -> Vec<u8> {
+    color.repeat(SCREEN_WIDTH * SCREEN_HEIGHT)
+}
+
+fn set_pixel(rgba: &mut [u8], x: usize, y: usize, rgb: [u8; 3]) {
+    let i = (y * SCREEN_WIDTH + x) * 4;
+    rgba[i..i + 3].copy_from_slice(&rgb);
+}
+
+#[test]
+fn test_render_uniform_frame() {
+    let text = render(&frame([0x10, 0x20, 0x30, 0xFF]));
+    assert!(text.starts_with("\x1b[H"));
+    let rows: Vec<&str> = text["\x1b[H".len()..].split_terminator("\r\n").collect();
+    assert_eq!(rows.len(), SCREEN_HEIGHT / 2);
+    // One color code per row, as the color never changes along it.
+    let row = format!("\x1b[38;2;16;32;48;48;2;16;32;48m{}\x1b[0m", UPPER_HALF_BLOCK.to_string().repeat(SCREEN_WIDTH));
+    assert!(rows.iter().all(|r| *r == row));
+}
+
+#[test]
+fn test_render_pairs_rows_into_half_blocks() {
+    let mut rgba = frame([0x00, 0x00, 0x00, 0xFF]);
+    set_pixel(&mut rgba, 0, 0, [0xFF, 0x00, 0x00]);
+    set_pixel(&mut rgba, 0, 1, [0x00, 0xFF, 0x00]);
+    let text = render(&rgba);
+    let first_row = text.split("\r\n").next().unwrap();
+    // The top pixel is the foreground, the bottom one the background.
+    assert!(first_row.starts_with("\x1b[H\x1b[38;2;255;0;0;48;2;0;255;0m\u{2580}\x1b[38;2;0;0;0;48;2;0;0;0m\u{2580}\u{2580}"));
+    assert_eq!(first_row.matches("\x1b[38;2;").count(), 2);
+}